@@ -0,0 +1,474 @@
+//! List-append checker based on dependency-graph cycle detection, in the
+//! spirit of Jepsen's Elle.
+//!
+//! As every append adds a unique element and every read returns the whole
+//! list, the version order of a key is recoverable from its longest read.
+//! Operations then form a graph of write-write (ww), write-read (wr), and
+//! read-write anti-dependency (rw) edges, plus real-time (rt) edges for
+//! linearizability or process-order edges for sequential consistency; the
+//! level holds iff the graph is acyclic. This scales far better than
+//! searching for an ordering.
+//!
+//! Each cycle found is reported with its Elle-style name, suffixed
+//! `-realtime` or `-process` if it relies on such edges, and the `:index` of
+//! the operations on it; so are reads incompatible with each other, reads with
+//! duplicate elements, and reads of elements never appended, which no order
+//! explains.
+
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+use std::fmt;
+
+use crate::error::{Error, Result};
+use crate::types::{Consistency, KeyType, OpData, OpSpan, Timeline, Timestamp, ValType, Value};
+
+/// Kind of a dependency edge.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Edge {
+    /// Append followed by the next append in version order.
+    Ww,
+    /// Append observed as the last element by a read.
+    Wr,
+    /// Read that did not observe the next append in version order.
+    Rw,
+    /// Completion before the invocation of the other (real time).
+    Rt,
+    /// Consecutive operations of a client (program order).
+    Process,
+}
+
+/// An anomaly found on a key, with the `:index` of the operations involved
+/// (in cycle order for cycles).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ListAnomaly {
+    pub key: KeyType,
+    /// Elle-style name, e.g., "G-single-realtime" or "incompatible-order".
+    pub name: String,
+    pub ops: Vec<u64>,
+}
+
+impl fmt::Display for ListAnomaly {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} on key {} among ops {:?}",
+            self.name, self.key, self.ops
+        )
+    }
+}
+
+/// Operation of a key, refined for building the graph.
+#[derive(Debug, Clone)]
+struct LOp {
+    index: u64,
+    invoke: Timestamp,
+    finish: Timestamp,
+    /// Appended element, or the elements read (`None` if not a list).
+    data: LData,
+    /// May or may not have taken effect.
+    optional: bool,
+}
+
+#[derive(Debug, Clone)]
+enum LData {
+    Append(ValType),
+    Read(Option<Vec<ValType>>),
+}
+
+impl LOp {
+    fn from_raw(raw: &OpSpan) -> Result<Self> {
+        let data = match &raw.data {
+            OpData::Append { val, .. } => LData::Append(val.clone()),
+            OpData::Read { val, .. } => LData::Read(match val {
+                Value::Nil => Some(vec![]),
+                Value::List(elems) => Some(elems.to_vec()),
+                _ => None,
+            }),
+            other => {
+                return Err(Error::Config(format!(
+                    "not a list-append operation: {}",
+                    other
+                )));
+            }
+        };
+        Ok(LOp {
+            index: raw.index,
+            invoke: raw.invoke,
+            finish: if raw.terminated() {
+                raw.finish
+            } else {
+                Timestamp::MAX
+            },
+            data,
+            optional: raw.indeterminate || !raw.terminated(),
+        })
+    }
+}
+
+/// Overall list-append checker, checking each key independently.
+#[derive(Debug)]
+pub struct AppendChecker {
+    /// Operations of each key, one stream per client.
+    per_key: HashMap<KeyType, Vec<Vec<LOp>>>,
+
+    /// Levels to check, strongest first.
+    levels: Vec<Consistency>,
+
+    /// Print per-key results?
+    verbose: bool,
+}
+
+impl AppendChecker {
+    /// Create a new checker of a list-append history. Operations other than
+    /// appends and reads are rejected, as are duplicate appends of an element
+    /// to a key.
+    pub fn new(timeline: Timeline) -> Result<Self> {
+        let num_clients = timeline.num_clients();
+        let mut per_key: HashMap<KeyType, Vec<Vec<LOp>>> = HashMap::new();
+        let mut appended = HashSet::new();
+        for (client, queue) in timeline.queues.iter().enumerate() {
+            for span in queue {
+                if let OpData::Append { key, val } = &span.data
+                    && !appended.insert((key.clone(), val.clone()))
+                {
+                    return Err(Error::Config(format!(
+                        "element {} appended to key {} more than once",
+                        val, key
+                    )));
+                }
+                let streams = per_key
+                    .entry(span.key())
+                    .or_insert_with(|| vec![vec![]; num_clients]);
+                streams[client].push(LOp::from_raw(span)?);
+            }
+        }
+
+        Ok(AppendChecker {
+            per_key,
+            levels: vec![Consistency::Linearizable],
+            verbose: true,
+        })
+    }
+
+    /// Set the levels to check (linearizability only by default). The result
+    /// of a check is the strongest of them that holds, or `Weak` if none.
+    pub fn set_levels(&mut self, levels: &[Consistency]) {
+        self.levels = levels
+            .iter()
+            .copied()
+            .filter(|&level| level != Consistency::Weak)
+            .collect();
+        self.levels.sort_unstable_by(|a, b| b.cmp(a));
+        self.levels.dedup();
+    }
+
+    /// Suppress per-key result printing.
+    pub fn set_quiet(&mut self) {
+        self.verbose = false;
+    }
+
+    /// Run the check for all keys.
+    pub fn check(&self) -> Consistency {
+        let mut result = self.levels.first().copied().unwrap_or(Consistency::Weak);
+        for level in self.check_per_key().into_values() {
+            if level < result {
+                result = level; // take minimum level strength across keys
+            }
+        }
+        result
+    }
+
+    /// Run the check for all keys, returning the result level of each key.
+    pub fn check_per_key(&self) -> BTreeMap<KeyType, Consistency> {
+        let mut results = BTreeMap::new();
+        for (key, streams) in self.per_key.iter() {
+            if self.verbose {
+                println!(" checking key {} ...", key);
+            }
+
+            let mut result = Consistency::Weak;
+            for &level in &self.levels {
+                let anomalies = find_anomalies(key, streams, level);
+                if anomalies.is_empty() {
+                    if self.verbose {
+                        println!("  ✅ {} ", level);
+                    }
+                    result = level;
+                    break;
+                }
+                if self.verbose {
+                    println!("  ❌ non-{}", level);
+                    for anomaly in &anomalies {
+                        println!("     {}", anomaly);
+                    }
+                }
+            }
+            results.insert(key.clone(), result);
+        }
+        results
+    }
+
+    /// Find all anomalies against the given level, across keys.
+    pub fn anomalies(&self, level: Consistency) -> Vec<ListAnomaly> {
+        let mut anomalies: Vec<ListAnomaly> = self
+            .per_key
+            .iter()
+            .flat_map(|(key, streams)| find_anomalies(key, streams, level))
+            .collect();
+        anomalies.sort_by(|a, b| (&a.key, &a.ops).cmp(&(&b.key, &b.ops)));
+        anomalies
+    }
+}
+
+/// Find the anomalies of a key against the level: those of the reads alone,
+/// or if there are none, the cycles of the dependency graph.
+fn find_anomalies(key: &KeyType, streams: &[Vec<LOp>], level: Consistency) -> Vec<ListAnomaly> {
+    if level == Consistency::Weak {
+        return vec![];
+    }
+    let ops: Vec<&LOp> = streams.iter().flatten().collect();
+    let anomaly = |name: &str, ops: Vec<u64>| ListAnomaly {
+        key: key.clone(),
+        name: name.to_string(),
+        ops,
+    };
+
+    // version order from the longest read, which all reads must be prefixes
+    // of, and which must only contain appended elements
+    let appends: HashMap<&ValType, usize> = ops
+        .iter()
+        .enumerate()
+        .filter_map(|(node, op)| match &op.data {
+            LData::Append(val) => Some((val, node)),
+            LData::Read(_) => None,
+        })
+        .collect();
+    let mut anomalies = vec![];
+    let mut order: &[ValType] = &[];
+    let mut longest = None;
+    for op in &ops {
+        let LData::Read(read) = &op.data else {
+            continue;
+        };
+        let Some(elems) = read else {
+            anomalies.push(anomaly("garbage-read", vec![op.index]));
+            continue;
+        };
+        if elems.iter().collect::<HashSet<_>>().len() < elems.len() {
+            anomalies.push(anomaly("duplicate-elements", vec![op.index]));
+        } else if elems.iter().any(|elem| !appends.contains_key(elem)) {
+            anomalies.push(anomaly("garbage-read", vec![op.index]));
+        } else if elems.len() > order.len() {
+            if !elems.starts_with(order) {
+                anomalies.push(anomaly(
+                    "incompatible-order",
+                    vec![longest.unwrap_or(op.index), op.index],
+                ));
+            }
+            order = elems;
+            longest = Some(op.index);
+        } else if !order.starts_with(elems) {
+            anomalies.push(anomaly(
+                "incompatible-order",
+                vec![longest.unwrap_or(op.index), op.index],
+            ));
+        }
+    }
+    if !anomalies.is_empty() {
+        return anomalies;
+    }
+
+    // dependency edges
+    let mut graph: Vec<Vec<(usize, Edge)>> = vec![vec![]; ops.len()];
+    let observed: HashSet<&ValType> = order.iter().collect();
+    let unobserved: Vec<usize> = appends
+        .iter()
+        .filter(|&(val, &node)| !observed.contains(val) && !ops[node].optional)
+        .map(|(_, &node)| node)
+        .collect();
+    for pair in order.windows(2) {
+        graph[appends[&pair[0]]].push((appends[&pair[1]], Edge::Ww));
+    }
+    if let Some(last) = order.last() {
+        for &node in &unobserved {
+            graph[appends[last]].push((node, Edge::Ww));
+        }
+    }
+    for (node, op) in ops.iter().enumerate() {
+        let LData::Read(Some(elems)) = &op.data else {
+            continue;
+        };
+        if let Some(last) = elems.last() {
+            graph[appends[last]].push((node, Edge::Wr));
+        }
+        match order.get(elems.len()) {
+            Some(next) => graph[node].push((appends[next], Edge::Rw)),
+            None => {
+                for &next in &unobserved {
+                    graph[node].push((next, Edge::Rw));
+                }
+            }
+        }
+    }
+
+    if level == Consistency::Linearizable {
+        add_real_time_edges(&ops, &mut graph);
+    } else {
+        let mut node = 0;
+        for stream in streams {
+            for next in node + 1..node + stream.len() {
+                graph[next - 1].push((next, Edge::Process));
+            }
+            node += stream.len();
+        }
+    }
+
+    strongly_connected(&graph)
+        .iter()
+        .map(|component| {
+            let cycle = find_cycle(&graph, component);
+            let ops = cycle.iter().map(|&(node, _)| ops[node].index).collect();
+            anomaly(&classify(&cycle), ops)
+        })
+        .collect()
+}
+
+/// Add real-time edges, transitively reduced: each operation gets an edge
+/// from every operation on the frontier when invoked, i.e., those completed
+/// but not yet known to precede another completed one.
+fn add_real_time_edges(ops: &[&LOp], graph: &mut [Vec<(usize, Edge)>]) {
+    // (time, is invoke, node), completions never at the same time as invokes
+    let mut events = vec![];
+    for (node, op) in ops.iter().enumerate() {
+        events.push((op.invoke, true, node));
+        if op.finish != Timestamp::MAX {
+            events.push((op.finish, false, node));
+        }
+    }
+    events.sort_unstable();
+
+    let mut frontier: Vec<usize> = vec![];
+    let mut preceding: HashMap<usize, Vec<usize>> = HashMap::new();
+    for (_, is_invoke, node) in events {
+        if is_invoke {
+            for &prev in &frontier {
+                graph[prev].push((node, Edge::Rt));
+            }
+            preceding.insert(node, frontier.clone());
+        } else {
+            let implied = preceding.remove(&node).unwrap_or_default();
+            frontier.retain(|prev| !implied.contains(prev));
+            frontier.push(node);
+        }
+    }
+}
+
+/// Strongly connected components of the graph with more than one node
+/// (Tarjan's algorithm, iteratively).
+fn strongly_connected(graph: &[Vec<(usize, Edge)>]) -> Vec<Vec<usize>> {
+    const UNVISITED: usize = usize::MAX;
+    let mut index = vec![UNVISITED; graph.len()];
+    let mut low = vec![0; graph.len()];
+    let mut on_stack = vec![false; graph.len()];
+    let mut stack = vec![];
+    let mut next_index = 0;
+    let mut components = vec![];
+
+    for root in 0..graph.len() {
+        if index[root] != UNVISITED {
+            continue;
+        }
+        // (node, next edge to follow)
+        let mut work = vec![(root, 0)];
+        while let Some((node, edge)) = work.pop() {
+            if edge == 0 {
+                index[node] = next_index;
+                low[node] = next_index;
+                next_index += 1;
+                stack.push(node);
+                on_stack[node] = true;
+            }
+            if let Some(&(succ, _)) = graph[node].get(edge) {
+                work.push((node, edge + 1));
+                if index[succ] == UNVISITED {
+                    work.push((succ, 0));
+                } else if on_stack[succ] {
+                    low[node] = low[node].min(index[succ]);
+                }
+                continue;
+            }
+
+            if low[node] == index[node] {
+                let mut component = vec![];
+                loop {
+                    let member = stack.pop().unwrap();
+                    on_stack[member] = false;
+                    component.push(member);
+                    if member == node {
+                        break;
+                    }
+                }
+                if component.len() > 1 {
+                    components.push(component);
+                }
+            }
+            if let Some(&(parent, _)) = work.last() {
+                low[parent] = low[parent].min(low[node]);
+            }
+        }
+    }
+    components
+}
+
+/// Find a shortest cycle through the first node of a strongly connected
+/// component, as (node, edge to the next node) pairs.
+fn find_cycle(graph: &[Vec<(usize, Edge)>], component: &[usize]) -> Vec<(usize, Edge)> {
+    let members: HashSet<usize> = component.iter().copied().collect();
+    let start = component[0];
+    let mut parent: HashMap<usize, (usize, Edge)> = HashMap::new();
+    let mut queue = VecDeque::from([start]);
+
+    while let Some(node) = queue.pop_front() {
+        for &(succ, edge) in &graph[node] {
+            if !members.contains(&succ) || parent.contains_key(&succ) {
+                continue;
+            }
+            parent.insert(succ, (node, edge));
+            if succ == start {
+                let mut cycle = vec![];
+                let mut at = start;
+                loop {
+                    let (prev, edge) = parent[&at];
+                    cycle.push((prev, edge));
+                    at = prev;
+                    if at == start {
+                        break;
+                    }
+                }
+                cycle.reverse();
+                return cycle;
+            }
+            queue.push_back(succ);
+        }
+    }
+    unreachable!("no cycle in a strongly connected component");
+}
+
+/// Elle-style name of a cycle by its edges: G0 (ww only), G1c (ww and wr),
+/// G-single (one rw), or G2 (more rw), suffixed if it relies on real-time or
+/// process-order edges.
+fn classify(cycle: &[(usize, Edge)]) -> String {
+    let count = |kind| cycle.iter().filter(|&&(_, edge)| edge == kind).count();
+    let base = match count(Edge::Rw) {
+        0 if count(Edge::Wr) == 0 => "G0",
+        0 => "G1c",
+        1 => "G-single",
+        _ => "G2",
+    };
+    if count(Edge::Rt) > 0 {
+        format!("{}-realtime", base)
+    } else if count(Edge::Process) > 0 {
+        format!("{}-process", base)
+    } else {
+        base.to_string()
+    }
+}
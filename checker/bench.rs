@@ -0,0 +1,503 @@
+//! Criterion-style benchmarking of the checker pipeline stages (parsing,
+//! timeline construction, and checking) over stored and generated histories.
+//!
+//! Results are saved as a TSV file per label (e.g., the git revision) so that
+//! later runs can be compared against a saved baseline.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use crate::check::Checker;
+use crate::error::{Error, Result};
+use crate::generator::{GenConfig, generate};
+use crate::store::{HISTORY_FILE, HistoryWriter, parse_history};
+use crate::types::Timeline;
+
+/// Generated histories benchmarked besides the stored ones, as (name,
+/// clients, keys, ops). Seeds are fixed so they are identical across runs.
+const GEN_PRESETS: &[(&str, usize, usize, usize)] = &[
+    ("gen c=3 k=1 n=2000", 3, 1, 2000),
+    ("gen c=5 k=1 n=1000", 5, 1, 1000),
+    ("gen c=5 k=5 n=2500", 5, 5, 2500),
+];
+
+/// Benchmarking parameters.
+#[derive(Debug, Clone)]
+pub struct BenchConfig {
+    /// Time spent running a stage before measuring.
+    pub warmup: Duration,
+    /// Target time spent measuring a stage.
+    pub measure: Duration,
+    /// Bounds on the number of samples per stage.
+    pub min_samples: usize,
+    pub max_samples: usize,
+    /// Time limit of a single check, beyond which the stage is skipped.
+    pub check_limit: Duration,
+    /// Limit on pending possibilities of a single check, likewise.
+    pub check_possib_limit: usize,
+    /// Relative change of median regarded as noise when comparing.
+    pub noise: f64,
+}
+
+/// Benchmarked stages of the pipeline.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Stage {
+    Parse,
+    Timeline,
+    Check,
+}
+
+impl Stage {
+    const ALL: [Stage; 3] = [Stage::Parse, Stage::Timeline, Stage::Check];
+
+    fn name(self) -> &'static str {
+        match self {
+            Stage::Parse => "parse",
+            Stage::Timeline => "timeline",
+            Stage::Check => "check",
+        }
+    }
+
+    fn from_name(s: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|stage| stage.name() == s)
+    }
+}
+
+/// Sample statistics, in nanoseconds.
+#[derive(Debug, Clone, Copy)]
+pub struct Stats {
+    pub samples: usize,
+    pub mean: f64,
+    pub stddev: f64,
+    pub median: f64,
+    pub min: f64,
+    pub max: f64,
+}
+
+impl Stats {
+    fn of(samples: &[Duration]) -> Self {
+        let mut ns: Vec<f64> = samples.iter().map(|d| d.as_nanos() as f64).collect();
+        ns.sort_by(f64::total_cmp);
+
+        let n = ns.len();
+        let mean = ns.iter().sum::<f64>() / n as f64;
+        let var = ns.iter().map(|x| (x - mean).powi(2)).sum::<f64>() / n as f64;
+        let median = if n.is_multiple_of(2) {
+            (ns[n / 2 - 1] + ns[n / 2]) / 2.0
+        } else {
+            ns[n / 2]
+        };
+
+        Stats {
+            samples: n,
+            mean,
+            stddev: var.sqrt(),
+            median,
+            min: ns[0],
+            max: ns[n - 1],
+        }
+    }
+}
+
+/// Human-readable duration from nanoseconds.
+struct Nanos(f64);
+
+impl fmt::Display for Nanos {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let ns = self.0;
+        if ns < 1e3 {
+            write!(f, "{:.2} ns", ns)
+        } else if ns < 1e6 {
+            write!(f, "{:.2} µs", ns / 1e3)
+        } else if ns < 1e9 {
+            write!(f, "{:.2} ms", ns / 1e6)
+        } else {
+            write!(f, "{:.2} s", ns / 1e9)
+        }
+    }
+}
+
+/// Result of one stage on one target; `stats` is `None` if the stage was
+/// skipped (e.g., the check exceeded its time limit).
+#[derive(Debug, Clone)]
+pub struct BenchResult {
+    pub target: String,
+    pub stage: Stage,
+    pub stats: Option<Stats>,
+}
+
+/// A history to benchmark on.
+struct Target {
+    name: String,
+    dir: PathBuf,
+}
+
+/// Collect the latest runs under the store directory.
+fn store_targets(store_dir: &Path) -> Result<Vec<Target>> {
+    let mut targets = vec![];
+    for entry in fs::read_dir(store_dir)? {
+        let dir = entry?.path().join("latest");
+        if dir.join(HISTORY_FILE).is_file()
+            && let Some(name) = dir.parent().and_then(|p| p.file_name())
+        {
+            targets.push(Target {
+                name: name.to_string_lossy().into_owned(),
+                dir,
+            });
+        }
+    }
+    targets.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(targets)
+}
+
+/// Generate the preset histories into a scratch directory.
+fn gen_targets(filter: Option<&str>) -> Result<Vec<Target>> {
+    let mut targets = vec![];
+    for &(name, clients, keys, ops) in GEN_PRESETS {
+        if filter.is_some_and(|f| !name.contains(f)) {
+            continue;
+        }
+
+        let cfg = GenConfig {
+            clients,
+            keys,
+            ops,
+            ..GenConfig::default()
+        };
+        let dir = std::env::temp_dir()
+            .join("sop-bench")
+            .join(name.replace(' ', "_"));
+        fs::create_dir_all(&dir)?;
+
+        let mut writer = HistoryWriter::new(BufWriter::new(File::create(dir.join(HISTORY_FILE))?));
+        for event in &generate(&cfg)?.events {
+            writer.write_event(event)?;
+        }
+        writer.flush()?;
+
+        targets.push(Target {
+            name: name.to_string(),
+            dir,
+        });
+    }
+    Ok(targets)
+}
+
+/// Time a routine: warm up, then take samples until the measuring time is
+/// used up (within the sample count bounds). The routine returns the duration
+/// of its own measured part, so that setup work can be excluded.
+fn measure(cfg: &BenchConfig, mut routine: impl FnMut() -> Result<Duration>) -> Result<Stats> {
+    let warmup_ts = Instant::now();
+    let (mut iters, mut spent) = (0, Duration::ZERO);
+    while iters == 0 || warmup_ts.elapsed() < cfg.warmup {
+        spent += routine()?;
+        iters += 1;
+    }
+
+    let per_iter = spent / iters;
+    let wanted = if per_iter.is_zero() {
+        cfg.max_samples
+    } else {
+        (cfg.measure.as_nanos() / per_iter.as_nanos()) as usize
+    };
+    let num_samples = wanted.clamp(cfg.min_samples, cfg.max_samples);
+
+    let mut samples = Vec::with_capacity(num_samples);
+    for _ in 0..num_samples {
+        samples.push(routine()?);
+    }
+    Ok(Stats::of(&samples))
+}
+
+/// Benchmark all stages on one target.
+fn bench_target(cfg: &BenchConfig, target: &Target) -> Result<Vec<BenchResult>> {
+    let mut results = vec![];
+
+    let parse = measure(cfg, || {
+        let ts = Instant::now();
+        parse_history(&target.dir)?;
+        Ok(ts.elapsed())
+    })?;
+    results.push(BenchResult {
+        target: target.name.clone(),
+        stage: Stage::Parse,
+        stats: Some(parse),
+    });
+
+    let (events, max_client) = parse_history(&target.dir)?;
+    let timeline = measure(cfg, || {
+        let events = events.clone();
+        let ts = Instant::now();
+        Timeline::new(events, max_client)?;
+        Ok(ts.elapsed())
+    })?;
+    results.push(BenchResult {
+        target: target.name.clone(),
+        stage: Stage::Timeline,
+        stats: Some(timeline),
+    });
+
+    let check = measure(cfg, || {
+        let timeline = Timeline::new(events.clone(), max_client)?;
+        let ts = Instant::now();
+        let mut checker = Checker::new(timeline);
+        checker.set_quiet();
+        checker.set_time_limit(cfg.check_limit);
+        checker.set_possib_limit(cfg.check_possib_limit);
+        checker.check()?;
+        Ok(ts.elapsed())
+    });
+    if let Err(err) = &check {
+        eprintln!("Skip check stage of '{}': {}", target.name, err);
+    }
+    results.push(BenchResult {
+        target: target.name.clone(),
+        stage: Stage::Check,
+        stats: check.ok(),
+    });
+
+    Ok(results)
+}
+
+/// Run the benchmarks on stored (unless `store_dir` is `None`) and generated
+/// histories whose names contain the filter.
+pub fn run_benchmarks(
+    cfg: &BenchConfig,
+    store_dir: Option<&Path>,
+    filter: Option<&str>,
+) -> Result<Vec<BenchResult>> {
+    let mut targets = match store_dir {
+        Some(dir) => store_targets(dir)?,
+        None => vec![],
+    };
+    targets.retain(|t| filter.is_none_or(|f| t.name.contains(f)));
+    targets.extend(gen_targets(filter)?);
+
+    let mut results = vec![];
+    for target in &targets {
+        println!("Benchmarking '{}' ...", target.name);
+        for result in bench_target(cfg, target)? {
+            match result.stats {
+                Some(stats) => println!(
+                    "  {:<8}  time: [{} {} {}]  mean {} ± {}  ({} samples)",
+                    result.stage.name(),
+                    Nanos(stats.min),
+                    Nanos(stats.median),
+                    Nanos(stats.max),
+                    Nanos(stats.mean),
+                    Nanos(stats.stddev),
+                    stats.samples
+                ),
+                None => println!("  {:<8}  skipped", result.stage.name()),
+            }
+            results.push(result);
+        }
+    }
+
+    Ok(results)
+}
+
+/// Header line of the saved results file.
+const RESULTS_HEADER: &str =
+    "target\tstage\tsamples\tmean_ns\tstddev_ns\tmedian_ns\tmin_ns\tmax_ns";
+
+/// Save results as TSV; skipped stages are saved with no statistics.
+pub fn save_results(path: &Path, results: &[BenchResult]) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    let mut out = BufWriter::new(File::create(path)?);
+    writeln!(out, "{}", RESULTS_HEADER)?;
+    for result in results {
+        match result.stats {
+            Some(s) => writeln!(
+                out,
+                "{}\t{}\t{}\t{:.0}\t{:.0}\t{:.0}\t{:.0}\t{:.0}",
+                result.target,
+                result.stage.name(),
+                s.samples,
+                s.mean,
+                s.stddev,
+                s.median,
+                s.min,
+                s.max
+            )?,
+            None => writeln!(
+                out,
+                "{}\t{}\t0\t\t\t\t\t",
+                result.target,
+                result.stage.name()
+            )?,
+        }
+    }
+    out.flush()?;
+    Ok(())
+}
+
+/// Load results saved by `save_results()`.
+pub fn load_results(path: &Path) -> Result<Vec<BenchResult>> {
+    let text = fs::read_to_string(path)?;
+
+    let mut results = vec![];
+    for (lineno, line) in text.lines().enumerate().skip(1) {
+        let malformed = |reason: &str| Error::Malformed {
+            path: path.to_path_buf(),
+            line: Some(lineno + 1),
+            reason: reason.to_string(),
+        };
+
+        let fields: Vec<&str> = line.split('\t').collect();
+        if fields.len() != 8 {
+            return Err(malformed("expected 8 fields"));
+        }
+        let stage = Stage::from_name(fields[1]).ok_or_else(|| malformed("unknown stage"))?;
+
+        let samples = fields[2]
+            .parse::<usize>()
+            .map_err(|_| malformed("invalid sample count"))?;
+        let stats = if samples == 0 {
+            None
+        } else {
+            let num = |i: usize| {
+                fields[i]
+                    .parse::<f64>()
+                    .map_err(|_| malformed("invalid statistic"))
+            };
+            Some(Stats {
+                samples,
+                mean: num(3)?,
+                stddev: num(4)?,
+                median: num(5)?,
+                min: num(6)?,
+                max: num(7)?,
+            })
+        };
+
+        results.push(BenchResult {
+            target: fields[0].to_string(),
+            stage,
+            stats,
+        });
+    }
+    Ok(results)
+}
+
+/// Critical value of Welch's t-statistic regarded as significant (roughly a
+/// 95% confidence level for reasonable sample counts).
+const T_CRITICAL: f64 = 2.0;
+
+/// Welch's t-statistic between two samples' means.
+fn welch_t(old: &Stats, new: &Stats) -> f64 {
+    let se =
+        (old.stddev.powi(2) / old.samples as f64 + new.stddev.powi(2) / new.samples as f64).sqrt();
+    if se == 0.0 {
+        if new.mean == old.mean {
+            0.0
+        } else {
+            f64::INFINITY
+        }
+    } else {
+        (new.mean - old.mean) / se
+    }
+}
+
+/// Compare results against a baseline and print a report. A stage is regarded
+/// as changed if its mean differs significantly and its median changed beyond
+/// the noise threshold. Returns true if no stage regressed.
+pub fn compare_results(
+    cfg: &BenchConfig,
+    baseline: &[BenchResult],
+    results: &[BenchResult],
+) -> bool {
+    let base: HashMap<(&str, Stage), Stats> = baseline
+        .iter()
+        .filter_map(|r| r.stats.map(|s| ((r.target.as_str(), r.stage), s)))
+        .collect();
+
+    let mut num_regressed = 0;
+    println!("Bench comparison against baseline (median, change):");
+    for result in results {
+        let (Some(stats), Some(old)) = (
+            result.stats,
+            base.get(&(result.target.as_str(), result.stage)),
+        ) else {
+            continue;
+        };
+
+        let change = (stats.median - old.median) / old.median;
+        let significant = welch_t(old, &stats).abs() > T_CRITICAL;
+        let verdict = if significant && change > cfg.noise {
+            num_regressed += 1;
+            "regressed"
+        } else if significant && change < -cfg.noise {
+            "improved"
+        } else {
+            "no change"
+        };
+        println!(
+            "  {:<40}  {:<8}  {:>10} -> {:>10}  {:+7.2}%  {}",
+            result.target,
+            result.stage.name(),
+            Nanos(old.median).to_string(),
+            Nanos(stats.median).to_string(),
+            change * 100.0,
+            verdict
+        );
+    }
+
+    println!("Bench summary: {} stage(s) regressed", num_regressed);
+    num_regressed == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::fixture_dir;
+
+    /// The harness times every stage of a generated history, within the
+    /// sample bounds, and its saved results load back and compare as
+    /// unchanged against themselves, yet regressed against a faster baseline.
+    #[test]
+    fn bench_smoke() {
+        let cfg = BenchConfig {
+            warmup: Duration::ZERO,
+            measure: Duration::ZERO,
+            min_samples: 2,
+            max_samples: 3,
+            check_limit: Duration::from_secs(20),
+            check_possib_limit: 50000,
+            noise: 0.05,
+        };
+        let results = run_benchmarks(&cfg, None, Some("c=5 k=1")).expect("benchmark failed");
+        let stages: Vec<Stage> = results.iter().map(|result| result.stage).collect();
+        assert_eq!(stages, [Stage::Parse, Stage::Timeline, Stage::Check]);
+        for result in &results {
+            assert_eq!(result.target, "gen c=5 k=1 n=1000");
+            let stats = result.stats.expect("stage skipped");
+            assert!((2..=3).contains(&stats.samples), "{:?}", stats);
+            assert!(stats.min <= stats.median && stats.median <= stats.max);
+            assert!(stats.min <= stats.mean && stats.mean <= stats.max);
+        }
+
+        let dir = fixture_dir("bench", &[]);
+        let path = dir.join("results.tsv");
+        save_results(&path, &results).expect("cannot save results");
+        let loaded = load_results(&path).expect("cannot load results");
+        assert_eq!(format!("{:.0?}", loaded), format!("{:.0?}", results));
+        assert!(compare_results(&cfg, &loaded, &results));
+
+        let mut faster = loaded;
+        for result in &mut faster {
+            let stats = result.stats.as_mut().unwrap();
+            (stats.mean, stats.median, stats.stddev) =
+                (stats.mean / 10.0, stats.median / 10.0, 0.0);
+        }
+        assert!(!compare_results(&cfg, &faster, &results));
+        let _ = fs::remove_dir_all(dir);
+    }
+}
@@ -0,0 +1,314 @@
+//! Causal consistency check of register histories across keys.
+//!
+//! The causal level is causal convergence (causal+): there is a causal order
+//! including program order, and a total arbitration order extending it, such
+//! that each read observes the last write to its key (by arbitration) among
+//! those causally preceding it. Following Bouajjani et al., "On Verifying
+//! Causal Consistency" (POPL'17), when every value is written at most once per
+//! key, this holds iff none of the following bad patterns occur, each found in
+//! polynomial time:
+//!
+//! - the causal order, i.e., program order and reads-from closed transitively,
+//!   is cyclic;
+//! - a read observes a value never written;
+//! - a read observes nil although a write to its key causally precedes it;
+//! - the conflict order, ordering every write before any other write to the
+//!   same key observed by one of its causal successors, is cyclic together
+//!   with the causal order. This covers a read observing a write causally
+//!   overwritten before the read.
+//!
+//! Reads-from is by the tags (`:tstag`) of the versions read and written, when
+//! present, so that values may repeat; reads without a tag fall back to the
+//! value, which must then have been written once only.
+
+use std::collections::{HashMap, HashSet};
+
+use crate::check::CkSpan;
+use crate::error::{Error, Result};
+use crate::types::{KeyType, OpData, UniqueTag, ValType, Value};
+
+/// Key, value, and tag (if any) of the version an operation reads or writes.
+type Version = (KeyType, ValType, Option<UniqueTag>);
+
+/// Read and write of an operation, if any.
+type Access = (Option<Version>, Option<Version>);
+
+/// Read and write of a register operation. RMWs read and write at once, if
+/// legal by comparing values.
+fn access(data: &OpData) -> Result<Access> {
+    match data {
+        OpData::Read { key, val, tag } => Ok((Some((key.clone(), val.clone(), *tag)), None)),
+        // absent tags of writes parse as 0
+        OpData::Write { key, val, tag } => Ok((
+            None,
+            Some((key.clone(), val.clone(), (*tag != 0).then_some(*tag))),
+        )),
+        OpData::Rmw {
+            key,
+            func,
+            rval,
+            rtag,
+            wval,
+            wtag,
+            ..
+        } if func.reads_value(rval) => Ok((
+            Some((key.clone(), rval.clone(), *rtag)),
+            Some((key.clone(), wval.clone(), *wtag)),
+        )),
+        other => Err(Error::Config(format!(
+            "causal check supports register reads, writes, and value-comparing RMWs only, not: {}",
+            other
+        ))),
+    }
+}
+
+/// Bitmap rows of a relation over the operations.
+struct Bits {
+    words: usize,
+    rows: Vec<Vec<u64>>,
+}
+
+impl Bits {
+    fn new(n: usize) -> Self {
+        let words = n.div_ceil(64);
+        Bits {
+            words,
+            rows: vec![vec![0; words]; n],
+        }
+    }
+
+    fn has(&self, row: usize, idx: usize) -> bool {
+        self.rows[row][idx / 64] & (1 << (idx % 64)) != 0
+    }
+
+    fn add(&mut self, row: usize, idx: usize) {
+        self.rows[row][idx / 64] |= 1 << (idx % 64);
+    }
+
+    /// Add row `src` and `src` itself into row `dst`.
+    fn absorb(&mut self, dst: usize, src: usize) {
+        for w in 0..self.words {
+            let word = self.rows[src][w];
+            self.rows[dst][w] |= word;
+        }
+        self.add(dst, src);
+    }
+}
+
+/// Topological order of a graph, `None` if it is cyclic.
+fn topo_order(succs: &[Vec<usize>]) -> Option<Vec<usize>> {
+    let mut indegree = vec![0; succs.len()];
+    for &s in succs.iter().flatten() {
+        indegree[s] += 1;
+    }
+    let mut order: Vec<usize> = (0..succs.len()).filter(|&v| indegree[v] == 0).collect();
+    let mut next = 0;
+    while next < order.len() {
+        for &s in &succs[order[next]] {
+            indegree[s] -= 1;
+            if indegree[s] == 0 {
+                order.push(s);
+            }
+        }
+        next += 1;
+    }
+    (order.len() == succs.len()).then_some(order)
+}
+
+/// Version a read observes.
+#[derive(Debug, Clone, Copy)]
+enum Source {
+    /// The initial nil.
+    Initial,
+    /// That of the write at the position of its client's stream.
+    Write((usize, usize)),
+    /// One never written.
+    Missing,
+}
+
+/// Writes of a history by the tags and values of their versions.
+#[derive(Default)]
+struct Writers {
+    by_tag: HashMap<(KeyType, UniqueTag), (usize, usize)>,
+    by_value: HashMap<(KeyType, ValType), Vec<(usize, usize)>>,
+}
+
+impl Writers {
+    fn new(client_queues: &[Vec<CkSpan>]) -> Result<Self> {
+        let mut writers = Writers::default();
+        for (client, queue) in client_queues.iter().enumerate() {
+            for (idx, span) in queue.iter().enumerate() {
+                let (_, Some((key, val, tag))) = access(&span.data)? else {
+                    continue;
+                };
+                if let Some(tag) = tag
+                    && writers
+                        .by_tag
+                        .insert((key.clone(), tag), (client, idx))
+                        .is_some()
+                {
+                    return Err(Error::Config(format!(
+                        "causal check needs distinct tags per key, not {} on key {}",
+                        tag, key
+                    )));
+                }
+                writers
+                    .by_value
+                    .entry((key, val))
+                    .or_default()
+                    .push((client, idx));
+            }
+        }
+        Ok(writers)
+    }
+
+    /// Write the read observes, by tag if it has one, or else by value.
+    fn source(&self, read: &Version) -> Result<Source> {
+        let (key, val, tag) = read;
+        if let Some(tag) = tag {
+            return Ok(self
+                .by_tag
+                .get(&(key.clone(), *tag))
+                .map_or(Source::Missing, |&writer| Source::Write(writer)));
+        }
+        let writes = self
+            .by_value
+            .get(&(key.clone(), val.clone()))
+            .map(Vec::as_slice)
+            .unwrap_or_default();
+        match (writes, val) {
+            ([], Value::Nil) => Ok(Source::Initial),
+            ([], _) => Ok(Source::Missing),
+            (&[writer], val) if *val != Value::Nil => Ok(Source::Write(writer)),
+            _ => Err(Error::Config(format!(
+                "causal check needs tags or distinct non-nil written values per key, not [{} {}]",
+                key, val
+            ))),
+        }
+    }
+}
+
+/// Check the operations of all keys, one stream per client, for causal
+/// consistency. Errors if the history has other than register operations, or
+/// a version it cannot tell the writer of, i.e., a tag written more than once
+/// to a key, or an untagged read of a value written more than once (or of nil
+/// when written) by an operation known to have completed.
+pub(crate) fn check_causal(client_queues: &[Vec<CkSpan>]) -> Result<bool> {
+    let writers = Writers::new(client_queues)?;
+    let mut sources: HashMap<(usize, usize), Source> = HashMap::new();
+    for (client, queue) in client_queues.iter().enumerate() {
+        for (idx, span) in queue.iter().enumerate() {
+            if let (Some(read), _) = access(&span.data)?
+                && !span.optional
+            {
+                sources.insert((client, idx), writers.source(&read)?);
+            }
+        }
+    }
+
+    // indeterminate operations are left out unless observed
+    let observed: HashSet<(usize, usize)> = sources
+        .values()
+        .filter_map(|&source| match source {
+            Source::Write(writer) => Some(writer),
+            _ => None,
+        })
+        .collect();
+    let mut nodes: HashMap<(usize, usize), usize> = HashMap::new();
+    let mut origins = vec![];
+    for (client, queue) in client_queues.iter().enumerate() {
+        for (idx, span) in queue.iter().enumerate() {
+            if !span.optional || observed.contains(&(client, idx)) {
+                nodes.insert((client, idx), origins.len());
+                origins.push((client, idx));
+            }
+        }
+    }
+    let n = origins.len();
+
+    // an observed indeterminate operation did read, but what it read is only
+    // kept if known, dropping it otherwise just loses constraints
+    let mut accesses = vec![];
+    for &(client, idx) in &origins {
+        let span = &client_queues[client][idx];
+        let (mut read, write) = access(&span.data)?;
+        if span.optional
+            && let Some(version) = &read
+        {
+            match writers.source(version) {
+                Ok(Source::Write(writer)) if nodes.contains_key(&writer) => {
+                    sources.insert((client, idx), Source::Write(writer));
+                }
+                Ok(Source::Initial) => {
+                    sources.insert((client, idx), Source::Initial);
+                }
+                _ => read = None,
+            }
+        }
+        accesses.push((read, write));
+    }
+
+    // program order and reads-from edges
+    let mut succs: Vec<Vec<usize>> = vec![vec![]; n];
+    for (client, queue) in client_queues.iter().enumerate() {
+        let mut prev: Option<usize> = None;
+        for idx in 0..queue.len() {
+            if let Some(&node) = nodes.get(&(client, idx)) {
+                if let Some(prev) = prev {
+                    succs[prev].push(node);
+                }
+                prev = Some(node);
+            }
+        }
+    }
+    let mut reads_from = vec![None; n];
+    for (node, origin) in origins.iter().enumerate() {
+        match sources.get(origin) {
+            None | Some(Source::Initial) => {}
+            Some(Source::Missing) => return Ok(false), // thin-air read
+            Some(Source::Write(writer)) => {
+                succs[nodes[writer]].push(node);
+                reads_from[node] = Some(nodes[writer]);
+            }
+        }
+    }
+
+    // causal past of each operation, in topological order
+    let Some(order) = topo_order(&succs) else {
+        return Ok(false); // cyclic causal order
+    };
+    let mut past = Bits::new(n);
+    for &node in &order {
+        for &succ in &succs[node] {
+            past.absorb(succ, node);
+        }
+    }
+
+    let mut writes_of: HashMap<&KeyType, Vec<usize>> = HashMap::new();
+    for (node, (_, write)) in accesses.iter().enumerate() {
+        if let Some((key, ..)) = write {
+            writes_of.entry(key).or_default().push(node);
+        }
+    }
+    let mut conflicts = succs;
+    for (node, (read, _)) in accesses.iter().enumerate() {
+        let Some((key, ..)) = read else {
+            continue;
+        };
+        let writes = writes_of.get(key).map(Vec::as_slice).unwrap_or_default();
+        match reads_from[node] {
+            // the initial nil, overwritten by any causally preceding write
+            None => {
+                if writes.iter().any(|&w| past.has(node, w)) {
+                    return Ok(false);
+                }
+            }
+            Some(writer) => {
+                for &other in writes.iter().filter(|&&w| w != writer && past.has(node, w)) {
+                    conflicts[other].push(writer);
+                }
+            }
+        }
+    }
+    Ok(topo_order(&conflicts).is_some())
+}
@@ -1,17 +1,51 @@
 //! Demonstrative checker implementation.
 //!
-//! TODO: currently only linearizability exploration supported, but other levels
-//!       should be achievable with the same logic but confined to smaller scales
-//!       due to complexity.
+//! Linearizability and sequential consistency are explored with the same
+//! search, the latter simply without the real-time constraint. The search is
+//! generic over the data type's `Model`, a register by default.
+//!
+//! Linearizability is local, so it is checked key by key. Sequential (and
+//! causal) consistency are not: a history may satisfy them on every key but
+//! not overall, so for multi-key histories they are checked over the whole
+//! history, searching with an object of the model per key. That search may
+//! blow up with the number of keys and indeterminate operations, so past its
+//! limit on pending possibilities it falls back to checking key by key under
+//! the same limit: a key in violation refutes the whole history, but keys
+//! that all hold leave it undecided.
+//!
+//! Real-time order assumes `:time`s comparable across clients. With clocks
+//! skewed between client hosts, or large client-side latencies, an epsilon
+//! relaxes it: an operation only precedes another if it completed more than
+//! epsilon before the other's invocation, while each client's program order
+//! still holds. A linearizable verdict is then checked again without the
+//! tolerance, to tell timing noise from a history that holds outright.
+//!
+//! The staleness levels are searched per key for their least bound, k = 1
+//! and Δ = 0 being linearizability itself. As they relax real time only,
+//! neither implies sequential consistency, which is then checked too: a
+//! history that is k-atomic but not sequentially consistent has a weaker
+//! result, with the staleness level reported as holding besides it. Program
+//! order still holds as under epsilon, so a read missing its client's own
+//! earlier write is Δ-atomic for no Δ, though possibly k-atomic.
+//!
+//! TODO: other levels should be achievable with the same logic but confined to
+//!       smaller scales due to complexity.
 
 use std::cmp;
-use std::collections::{HashMap, HashSet, VecDeque};
-use std::error::Error;
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
 use std::fmt;
 use std::hash::{Hash, Hasher};
-use std::time::Instant;
+use std::time::{Duration, Instant};
+
+use crate::causal::check_causal;
+use crate::error::{Error, Result};
+use crate::model::{KAtomic, Keyed, Model, Register};
+use crate::tags::check_tags;
+use crate::types::{ClientId, Consistency, KeyType, OpData, OpSpan, Timeline, Timestamp};
 
-use crate::types::{ClientId, Consistency, KeyType, OpData, OpSpan, Timeline, Timestamp, ValType};
+/// Limit on pending possibilities of the whole-history search unless set
+/// otherwise, past which it falls back to checking key by key.
+const WHOLE_POSSIB_LIMIT: usize = 100_000;
 
 /// Index into `client_queues` for a specific span.
 type FeedIdx = (ClientId, usize);
@@ -24,7 +58,7 @@ type Ordering = Vec<FeedIdx>;
 
 /// A single possibility to be explored.
 #[derive(Debug, Clone)]
-struct Possibility {
+struct Possibility<S> {
     /// The ordering graph of operations (not used in uniqueness).
     // NOTE: This is tracked to make this implementation represent the
     //       "pureness" of the underlying algorithm; in practice, tracking
@@ -33,7 +67,7 @@ struct Possibility {
     graph: Ordering,
 
     /// The resulting state after the operations in the graph.
-    state: Option<ValType>,
+    state: S,
     /// Maximum invoke timestamp of in-graph operations.
     max_invoke: Timestamp,
 
@@ -41,24 +75,19 @@ struct Possibility {
     feed_prog: FeedProgress,
 }
 
-impl Possibility {
+impl<S> Possibility<S> {
     /// Create an initial possibility.
-    fn initial(num_clients: usize) -> Self {
+    fn initial(state: S, num_clients: usize) -> Self {
         Possibility {
             graph: vec![],
-            state: None,
+            state,
             max_invoke: 0,
             feed_prog: vec![0; num_clients],
         }
     }
 
     /// Create a new possibility.
-    fn from(
-        graph: Ordering,
-        state: Option<ValType>,
-        max_invoke: Timestamp,
-        feed_prog: FeedProgress,
-    ) -> Self {
+    fn from(graph: Ordering, state: S, max_invoke: Timestamp, feed_prog: FeedProgress) -> Self {
         Possibility {
             graph,
             state,
@@ -68,60 +97,43 @@ impl Possibility {
     }
 }
 
-impl Hash for Possibility {
+impl<S: Hash> Hash for Possibility<S> {
     fn hash<H: Hasher>(&self, state: &mut H) {
         self.state.hash(state);
         self.feed_prog.hash(state);
     }
 }
 
-impl PartialEq for Possibility {
+impl<S: PartialEq> PartialEq for Possibility<S> {
     fn eq(&self, other: &Self) -> bool {
         self.state == other.state && self.feed_prog == other.feed_prog
     }
 }
 
-impl Eq for Possibility {}
-
-/// Refined type of `OpData` with only relevant info for checking.
-#[derive(Debug, Clone)]
-enum CkData {
-    Read {
-        val: Option<ValType>,
-    },
-    Write {
-        val: ValType,
-    },
-    Rmw {
-        rval: Option<ValType>,
-        wval: Option<ValType>,
-    },
-}
-
-impl CkData {
-    fn from_raw(raw: OpData) -> Self {
-        match raw {
-            OpData::Read { val, .. } => CkData::Read { val },
-            OpData::Write { val, .. } => CkData::Write { val },
-            OpData::Rmw { rval, wval, .. } => CkData::Rmw { rval, wval },
-        }
-    }
-}
+impl<S: Eq> Eq for Possibility<S> {}
 
 /// Refined type of `OpSpan` with only relevant info for checking.
 #[derive(Debug, Clone)]
-struct CkSpan {
-    invoke: Timestamp,
-    finish: Timestamp,
-    data: CkData,
+pub(crate) struct CkSpan {
+    pub(crate) invoke: Timestamp,
+    pub(crate) finish: Timestamp,
+    pub(crate) data: OpData,
+    /// May be left out of the ordering (indeterminate operation).
+    pub(crate) optional: bool,
 }
 
 impl CkSpan {
     fn from_raw(raw: OpSpan) -> Self {
+        // operations that never completed may or may not have taken effect
         CkSpan {
             invoke: raw.invoke,
-            finish: raw.finish,
-            data: CkData::from_raw(raw.data),
+            finish: if raw.terminated() {
+                raw.finish
+            } else {
+                Timestamp::MAX
+            },
+            optional: raw.indeterminate || !raw.terminated(),
+            data: raw.data,
         }
     }
 
@@ -132,57 +144,115 @@ impl CkSpan {
 
 impl fmt::Display for CkSpan {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(
-            f,
-            "|{}-{} {}|",
-            self.invoke,
-            self.finish,
-            match self.data {
-                CkData::Read { val } => format!(
-                    "R({})",
-                    if let Some(val) = val {
-                        val.to_string()
-                    } else {
-                        "nil".to_string()
-                    }
-                ),
-                CkData::Write { val } => format!("W({})", val),
-                CkData::Rmw { rval, wval } => format!(
-                    "CAS({},{})",
-                    if let Some(rval) = rval {
-                        rval.to_string()
-                    } else {
-                        "nil".to_string()
-                    },
-                    if let Some(wval) = wval {
-                        wval.to_string()
-                    } else {
-                        "nil".to_string()
-                    }
-                ),
-            },
-        )
+        write!(f, "|{}-{} {}|", self.invoke, self.finish, self.data)
+    }
+}
+
+/// How the verdict of a level was reached.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CheckScope {
+    /// Key by key, which is exact for a local level (linearizability) or a
+    /// single-key history.
+    PerKey,
+    /// Over the whole multi-key history at once.
+    Whole,
+}
+
+impl fmt::Display for CheckScope {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CheckScope::PerKey => write!(f, "per-key"),
+            CheckScope::Whole => write!(f, "whole-history"),
+        }
     }
 }
 
-/// Overall checker, a collection of per-key checkers.
+/// Verdict of a single level.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LevelVerdict {
+    pub level: Consistency,
+    pub holds: bool,
+    pub scope: CheckScope,
+    /// Holds only thanks to the clock-skew tolerance (see `set_epsilon`).
+    pub skewed: bool,
+    /// Least k or Δ of a staleness level, over all keys, if any suffices.
+    pub bound: Option<u64>,
+    /// Keys found in violation of a level checked key by key, each with the
+    /// time by which no ordering of its operations got further. Only the
+    /// first key found unless checking exhaustively (see `set_exhaustive`).
+    pub violations: BTreeMap<KeyType, Timestamp>,
+}
+
+/// Strongest level that holds by the verdicts, or `Weak` if none. A
+/// staleness level only counts if sequential consistency holds too, as it
+/// does not imply it.
+pub fn result_level(verdicts: &[LevelVerdict]) -> Consistency {
+    let sequential = verdicts
+        .iter()
+        .any(|verdict| verdict.level == Consistency::Sequential && verdict.holds);
+    verdicts
+        .iter()
+        .find(|verdict| verdict.holds && (sequential || !verdict.level.is_staleness()))
+        .map_or(Consistency::Weak, |verdict| verdict.level)
+}
+
+/// Overall checker, a collection of per-key checkers plus one of the whole
+/// history.
 #[derive(Debug)]
-pub(crate) struct Checker {
-    per_key: HashMap<KeyType, CheckerPerKey>,
+pub struct Checker<M: Model = Register> {
+    /// Data type of each key.
+    model: M,
+    per_key: HashMap<KeyType, CheckerPerKey<M::State>>,
+    whole: CheckerPerKey<BTreeMap<KeyType, M::State>>,
+
+    /// Levels to check, strongest first.
+    levels: Vec<Consistency>,
+
+    /// Print progress and per-key results?
+    verbose: bool,
+    /// Give up checking after this long.
+    time_limit: Option<Duration>,
+    /// Give up checking once this many possibilities are pending.
+    possib_limit: Option<usize>,
+    /// Clock-skew tolerance of the real-time order.
+    epsilon: Timestamp,
+    /// Greatest k accepted as k-atomic, any if none.
+    max_k: Option<u64>,
+    /// Greatest Δ accepted as Δ-atomic, any if none.
+    max_delta: Option<Timestamp>,
+    /// Check every key even after one is found in violation?
+    exhaustive: bool,
+    /// Keys known to violate a level before any search, with the time of
+    /// each violation.
+    refuted: BTreeMap<Consistency, BTreeMap<KeyType, Timestamp>>,
 }
 
 impl Checker {
-    /// Create a new checker. Split the timeline into per-key stream groups,
-    /// and check each stream independently.
-    pub(crate) fn new(timeline: Timeline) -> Self {
+    /// Create a new checker of a register history.
+    pub fn new(timeline: Timeline) -> Self {
+        Self::with_model(timeline, Register)
+    }
+}
+
+impl<M: Model> Checker<M> {
+    /// Create a new checker of a history on the given data type. Split the
+    /// timeline into per-key stream groups to check independently, keeping
+    /// the whole streams as well.
+    pub fn with_model(timeline: Timeline, model: M) -> Self {
         let mut per_key_spans = HashMap::new();
-        for (&key, &cnt) in timeline.stats_key_ops.iter() {
-            per_key_spans.insert(key, vec![Vec::with_capacity(cnt); timeline.num_clients()]);
+        for (key, &cnt) in timeline.stats_key_ops.iter() {
+            per_key_spans.insert(
+                key.clone(),
+                vec![Vec::with_capacity(cnt); timeline.num_clients()],
+            );
         }
+        let mut whole_spans = vec![vec![]; timeline.num_clients()];
         for (client, queue) in timeline.queues.into_iter().enumerate() {
             for span in queue {
                 if let Some(spans) = per_key_spans.get_mut(&span.key()) {
-                    spans[client].push(CkSpan::from_raw(span));
+                    let span = CkSpan::from_raw(span);
+                    whole_spans[client].push(span.clone());
+                    spans[client].push(span);
                 }
             }
         }
@@ -193,64 +263,571 @@ impl Checker {
         }
 
         Checker {
+            model,
             per_key: per_key_checkers,
+            whole: CheckerPerKey::new(whole_spans),
+            levels: vec![Consistency::Linearizable],
+            verbose: true,
+            time_limit: None,
+            possib_limit: None,
+            epsilon: 0,
+            max_k: None,
+            max_delta: None,
+            exhaustive: false,
+            refuted: BTreeMap::new(),
         }
     }
 
-    /// Run the check for all keys.
-    pub(crate) fn check(&mut self) -> Result<Consistency, Box<dyn Error>> {
-        let mut result = Consistency::Linearizable;
+    /// Set the levels to check (linearizability only by default). The result
+    /// of a check is the strongest of them that holds, or `Weak` if none; a
+    /// staleness level only if sequential consistency holds too. The check
+    /// fails with a config error on a transactional level.
+    pub fn set_levels(&mut self, levels: &[Consistency]) {
+        self.levels = levels
+            .iter()
+            .copied()
+            .filter(|&level| level != Consistency::Weak)
+            .collect();
+        self.levels.sort_unstable_by(|a, b| b.cmp(a));
+        self.levels.dedup();
+    }
+
+    /// Suppress progress printing (e.g., when benchmarking).
+    pub fn set_quiet(&mut self) {
+        self.verbose = false;
+    }
+
+    /// Make the check return an error once it has run longer than the limit.
+    pub fn set_time_limit(&mut self, limit: Duration) {
+        self.time_limit = Some(limit);
+    }
+
+    /// Make the check return an error once too many possibilities are pending,
+    /// which bounds its memory usage.
+    pub fn set_possib_limit(&mut self, limit: usize) {
+        self.possib_limit = Some(limit);
+    }
+
+    /// Tolerate clock skew between the timestamps of different operations:
+    /// an operation then only precedes another in real time if it finished
+    /// more than `epsilon` before the other's invocation.
+    pub fn set_epsilon(&mut self, epsilon: Timestamp) {
+        self.epsilon = epsilon;
+    }
+
+    /// Accept k-atomicity only up to the given k (any k that suffices by
+    /// default).
+    pub fn set_max_k(&mut self, k: u64) {
+        self.max_k = Some(k);
+    }
+
+    /// Accept Δ-atomicity only up to the given Δ (any Δ that suffices by
+    /// default).
+    pub fn set_max_delta(&mut self, delta: Timestamp) {
+        self.max_delta = Some(delta);
+    }
+
+    /// Keep checking the other keys after one is found in violation, to
+    /// locate all violations of a level checked key by key.
+    pub fn set_exhaustive(&mut self) {
+        self.exhaustive = true;
+    }
+
+    /// Take the key as violating the level at the given time, e.g., by an
+    /// anomaly in its recorded values, sparing its search for the level. A
+    /// level checked over the whole history then fails without a search.
+    pub fn refute(&mut self, level: Consistency, key: KeyType, time: Timestamp) {
+        let times = self.refuted.entry(level).or_default();
+        let at = times.entry(key).or_insert(time);
+        *at = (*at).min(time);
+    }
+
+    /// Greatest bound accepted under a staleness level.
+    fn max_bound(&self, level: Consistency) -> Option<u64> {
+        match level {
+            Consistency::KAtomic => self.max_k,
+            _ => self.max_delta,
+        }
+    }
+
+    /// Levels to check, strongest first, with sequential consistency added
+    /// under the staleness levels, which do not imply it.
+    fn chain(&self) -> Result<Vec<Consistency>> {
+        if let Some(level) = self.levels.iter().find(|level| level.is_transactional()) {
+            return Err(Error::Config(format!("not a register level: {}", level)));
+        }
+        let mut levels = self.levels.clone();
+        if levels.iter().any(|level| level.is_staleness())
+            && !levels.contains(&Consistency::Sequential)
+        {
+            let at = levels.partition_point(|&level| level > Consistency::Sequential);
+            levels.insert(at, Consistency::Sequential);
+        }
+        Ok(levels)
+    }
+
+    /// Run the check, returning the strongest level that holds.
+    pub fn check(&mut self) -> Result<Consistency> {
+        Ok(result_level(&self.check_report()?))
+    }
+
+    /// Run the check level by level, strongest first, until one that is not
+    /// a staleness level holds, returning the verdict of each level checked.
+    /// Sequential consistency is checked too under a staleness level.
+    pub fn check_report(&mut self) -> Result<Vec<LevelVerdict>> {
+        let deadline = self.time_limit.map(|limit| (Instant::now() + limit, limit));
+        let mut verdicts = vec![];
+
+        for level in self.chain()? {
+            let whole = level == Consistency::Causal
+                || (level == Consistency::Sequential && self.per_key.len() > 1);
+            let verdict = match level {
+                _ if whole && self.refuted.contains_key(&level) => {
+                    if self.verbose {
+                        println!("  ❌ non-{} (refuted)", level);
+                    }
+                    LevelVerdict {
+                        level,
+                        holds: false,
+                        scope: CheckScope::Whole,
+                        skewed: false,
+                        bound: None,
+                        violations: self.refuted[&level].clone(),
+                    }
+                }
+                Consistency::Causal => {
+                    if self.verbose {
+                        println!(" checking whole history ...");
+                    }
+                    let holds = self.whole.check_causal(self.verbose)?;
+                    LevelVerdict {
+                        level,
+                        holds,
+                        scope: CheckScope::Whole,
+                        skewed: false,
+                        bound: None,
+                        violations: BTreeMap::new(),
+                    }
+                }
+                Consistency::Sequential if self.per_key.len() > 1 => {
+                    if self.verbose {
+                        println!(" checking whole history ...");
+                    }
+                    let limit = self.possib_limit.unwrap_or(WHOLE_POSSIB_LIMIT);
+                    match self.whole.check(
+                        &Keyed(&self.model),
+                        level,
+                        0,
+                        self.verbose,
+                        deadline,
+                        Some(limit),
+                    ) {
+                        Ok(holds) => LevelVerdict {
+                            level,
+                            holds,
+                            scope: CheckScope::Whole,
+                            skewed: false,
+                            bound: None,
+                            violations: BTreeMap::new(),
+                        },
+                        Err(Error::PossibLimitExceeded { limit }) => {
+                            if self.verbose {
+                                println!(
+                                    "  ❔ beyond {} possibilities, checking key by key",
+                                    limit
+                                );
+                            }
+                            let violations =
+                                self.check_keys(level, 0, self.verbose, deadline, Some(limit))?;
+                            // sequential consistency is not local, so keys
+                            // that all hold do not decide the whole history
+                            if violations.is_empty() {
+                                return Err(Error::PossibLimitExceeded { limit });
+                            }
+                            LevelVerdict {
+                                level,
+                                holds: false,
+                                scope: CheckScope::PerKey,
+                                skewed: false,
+                                bound: None,
+                                violations,
+                            }
+                        }
+                        Err(err) => return Err(err),
+                    }
+                }
+                level if level.is_staleness() => {
+                    let max = self.max_bound(level);
+                    let within = |bounds: &BTreeMap<KeyType, Option<u64>>| {
+                        bounds.values().all(|&bound| within(bound, max))
+                    };
+                    let bounds =
+                        self.staleness_keys(level, self.epsilon, self.verbose, deadline)?;
+                    let holds = within(&bounds);
+                    let skewed = holds && self.epsilon > 0 && {
+                        if self.verbose {
+                            println!(" checking again without epsilon ...");
+                        }
+                        !within(&self.staleness_keys(level, 0, false, deadline)?)
+                    };
+                    LevelVerdict {
+                        level,
+                        holds,
+                        scope: CheckScope::PerKey,
+                        skewed,
+                        bound: bounds
+                            .into_values()
+                            .collect::<Option<Vec<_>>>()
+                            .map(|bounds| bounds.into_iter().max().unwrap_or(0)),
+                        violations: BTreeMap::new(),
+                    }
+                }
+                _ => {
+                    let limit = self.possib_limit;
+                    let violations =
+                        self.check_keys(level, self.epsilon, self.verbose, deadline, limit)?;
+                    let holds = violations.is_empty();
+                    // a verdict relying on real time may hold only within
+                    // the tolerance, as seen by checking again without it
+                    let skewed =
+                        holds && level == Consistency::Linearizable && self.epsilon > 0 && {
+                            if self.verbose {
+                                println!(" checking again without epsilon ...");
+                            }
+                            !self
+                                .check_keys(level, 0, false, deadline, limit)?
+                                .is_empty()
+                        };
+                    LevelVerdict {
+                        level,
+                        holds,
+                        scope: CheckScope::PerKey,
+                        skewed,
+                        bound: None,
+                        violations,
+                    }
+                }
+            };
+            let holds = verdict.holds;
+            verdicts.push(verdict);
+            if holds && !level.is_staleness() {
+                break;
+            }
+        }
+
+        Ok(verdicts)
+    }
+
+    /// Least bound of each key under a staleness level (k for k-atomicity, Δ
+    /// for Δ-atomicity), or `None` for a key that no bound makes valid.
+    pub fn least_staleness(
+        &mut self,
+        level: Consistency,
+    ) -> Result<BTreeMap<KeyType, Option<u64>>> {
+        if !level.is_staleness() {
+            return Err(Error::Config(format!("not a staleness level: {}", level)));
+        }
+        let deadline = self.time_limit.map(|limit| (Instant::now() + limit, limit));
+        self.staleness_keys(level, self.epsilon, self.verbose, deadline)
+    }
+
+    /// Least bound of every key under a staleness level, with real time
+    /// relaxed by `epsilon`.
+    fn staleness_keys(
+        &self,
+        level: Consistency,
+        epsilon: Timestamp,
+        verbose: bool,
+        deadline: Option<(Instant, Duration)>,
+    ) -> Result<BTreeMap<KeyType, Option<u64>>> {
+        let name = if level == Consistency::KAtomic {
+            "k"
+        } else {
+            "Δ"
+        };
+        let max = self.max_bound(level);
+        let mut bounds = BTreeMap::new();
+        let refuted = self.refuted.get(&level);
+        for (key, checker) in self.per_key.iter() {
+            if refuted.is_some_and(|refuted| refuted.contains_key(key)) {
+                bounds.insert(key.clone(), None);
+                continue;
+            }
+            if verbose {
+                println!(" checking key {} ...", key);
+            }
+            let bound =
+                checker.least_bound(&self.model, level, epsilon, deadline, self.possib_limit)?;
+            if verbose {
+                match bound {
+                    Some(bound) if within(Some(bound), max) => {
+                        println!("  ✅ {}, least {} = {}", level, name, bound)
+                    }
+                    Some(bound) => println!("  ❌ non-{}, least {} = {}", level, name, bound),
+                    None => println!("  ❌ non-{}, for any {}", level, name),
+                }
+            }
+            bounds.insert(key.clone(), bound);
+        }
+        Ok(bounds)
+    }
+
+    /// Check every key against the level, returning the keys in violation
+    /// with the time of each, stopping at the first unless exhaustive.
+    fn check_keys(
+        &mut self,
+        level: Consistency,
+        epsilon: Timestamp,
+        verbose: bool,
+        deadline: Option<(Instant, Duration)>,
+        possib_limit: Option<usize>,
+    ) -> Result<BTreeMap<KeyType, Timestamp>> {
+        let mut violations = self.refuted.get(&level).cloned().unwrap_or_default();
+        if !violations.is_empty() && !self.exhaustive {
+            return Ok(violations);
+        }
+        for (key, checker) in self.per_key.iter_mut() {
+            if violations.contains_key(key) {
+                continue;
+            }
+            if verbose {
+                println!(" checking key {} ...", key);
+            }
+            if !checker.check(&self.model, level, epsilon, verbose, deadline, possib_limit)? {
+                violations.insert(key.clone(), checker.witness);
+                if !self.exhaustive {
+                    break;
+                }
+            }
+        }
+        Ok(violations)
+    }
+
+    /// Run the check for all keys, returning the result level of each key.
+    /// For the levels that are not local, a key's result is only an upper
+    /// bound of the whole history's.
+    pub fn check_per_key(&mut self) -> Result<BTreeMap<KeyType, Consistency>> {
+        let mut results = BTreeMap::new();
+        let deadline = self.time_limit.map(|limit| (Instant::now() + limit, limit));
+        let levels = self.chain()?;
 
         // TODO: should be super easy to parallelize here at this loop, but
         //       there are probably a million ways to further optimize
         for (key, checker) in self.per_key.iter_mut() {
-            println!(" checking key {} ...", key);
-            let level = checker.check()?;
+            if self.verbose {
+                println!(" checking key {} ...", key);
+            }
 
-            if level < result {
-                result = level; // take minimum level strength across keys
+            let mut result = Consistency::Weak;
+            let mut stale = None;
+            for &level in &levels {
+                let holds = match level {
+                    Consistency::Causal => checker.check_causal(self.verbose)?,
+                    Consistency::KAtomic => within(
+                        checker.least_bound(
+                            &self.model,
+                            level,
+                            self.epsilon,
+                            deadline,
+                            self.possib_limit,
+                        )?,
+                        self.max_k,
+                    ),
+                    Consistency::DeltaAtomic => within(
+                        checker.least_bound(
+                            &self.model,
+                            level,
+                            self.epsilon,
+                            deadline,
+                            self.possib_limit,
+                        )?,
+                        self.max_delta,
+                    ),
+                    _ => checker.check(
+                        &self.model,
+                        level,
+                        self.epsilon,
+                        self.verbose,
+                        deadline,
+                        self.possib_limit,
+                    )?,
+                };
+                if holds && level.is_staleness() {
+                    stale = stale.or(Some(level));
+                } else if holds {
+                    result = match stale {
+                        Some(stale) if level == Consistency::Sequential => stale,
+                        _ => level,
+                    };
+                    break;
+                }
             }
+            results.insert(key.clone(), result);
         }
 
-        Ok(result)
+        Ok(results)
     }
 }
 
-/// Checker states per key.
+/// Checker states per key, of the model's state type `S`. Also used for the
+/// whole history, with the state of all keys.
 #[derive(Debug)]
-struct CheckerPerKey {
+struct CheckerPerKey<S> {
     /// Spans from the timeline, one stream per client.
     client_queues: Vec<Vec<CkSpan>>,
 
     /// Queue of current possibilities. Each possibility is a tuple of the
     /// (ordering graphs, resulting state, feeding progress) at that point.
-    possibilities: VecDeque<Possibility>,
+    possibilities: VecDeque<Possibility<S>>,
 
     /// Set of unique possibilities for uniqueness comparison.
-    possibilities_set: HashSet<Possibility>,
+    possibilities_set: HashSet<Possibility<S>>,
+
+    /// After a failed check, the time by which no ordering got further: the
+    /// latest finish any ordering reached without fitting the operation in.
+    witness: Timestamp,
 }
 
-impl CheckerPerKey {
+impl<S: Clone + Eq + Hash> CheckerPerKey<S> {
     /// Create a new per-key checker.
     fn new(client_queues: Vec<Vec<CkSpan>>) -> Self {
-        let num_clients = client_queues.len();
-        let initial = Possibility::initial(num_clients);
-
         CheckerPerKey {
             client_queues,
-            possibilities: VecDeque::from([initial.clone()]),
-            possibilities_set: HashSet::from([initial]),
+            possibilities: VecDeque::new(),
+            possibilities_set: HashSet::new(),
+            witness: 0,
+        }
+    }
+
+    /// Check the history for causal consistency, returning true if it holds.
+    fn check_causal(&self, verbose: bool) -> Result<bool> {
+        let holds = check_causal(&self.client_queues)?;
+        if verbose {
+            if holds {
+                println!("  ✅ {} ", Consistency::Causal);
+            } else {
+                println!("  ❌ non-{}", Consistency::Causal);
+            }
+        }
+        Ok(holds)
+    }
+
+    /// Least bound under a staleness level, if any: the least k for which the
+    /// k-atomic relaxation of the model is linearizable, or the least Δ for
+    /// which starting reads Δ earlier makes the history linearizable. Both
+    /// only relax real time, so each client's program order still holds.
+    fn least_bound<M: Model<State = S>>(
+        &self,
+        model: &M,
+        level: Consistency,
+        epsilon: Timestamp,
+        deadline: Option<(Instant, Duration)>,
+        possib_limit: Option<usize>,
+    ) -> Result<Option<u64>> {
+        let spans = || self.client_queues.iter().flatten();
+        if level == Consistency::KAtomic {
+            // a window past all modifications has every state
+            let modifications = spans().filter(|span| !span.data.read_only()).count() as u64;
+            let mut checker = CheckerPerKey::new(self.client_queues.clone());
+            return least_passing(1, modifications + 1, |k| {
+                let model = KAtomic {
+                    model,
+                    k: k as usize,
+                };
+                checker.check(
+                    &model,
+                    Consistency::Linearizable,
+                    epsilon,
+                    false,
+                    deadline,
+                    possib_limit,
+                )
+            });
         }
+
+        // reads started before everything else finished follow nothing
+        let finished = spans().filter(|span| span.terminated() && span.finish != Timestamp::MAX);
+        let first = spans().map(|span| span.invoke).min().unwrap_or(0);
+        let last = finished.map(|span| span.finish).max().unwrap_or(0);
+        least_passing(0, last.saturating_sub(first), |delta| {
+            let shifted = self
+                .client_queues
+                .iter()
+                .map(|queue| {
+                    queue
+                        .iter()
+                        .map(|span| {
+                            let mut span = span.clone();
+                            if span.data.read_only() {
+                                span.invoke = span.invoke.saturating_sub(delta);
+                            }
+                            span
+                        })
+                        .collect()
+                })
+                .collect();
+            CheckerPerKey::new(shifted).check(
+                model,
+                Consistency::Linearizable,
+                epsilon,
+                false,
+                deadline,
+                possib_limit,
+            )
+        })
+    }
+
+    /// Reset the possibilities to only the initial one.
+    fn reset(&mut self, state: S) {
+        let initial = Possibility::initial(state, self.client_queues.len());
+        self.possibilities = VecDeque::from([initial.clone()]);
+        self.possibilities_set = HashSet::from([initial]);
     }
 
-    /// Check the history.
-    fn check(&mut self) -> Result<Consistency, Box<dyn Error>> {
+    /// Check the history against the given level, returning true if it holds,
+    /// with real time relaxed by `epsilon`. Gives up with an error if past the
+    /// deadline (given with its time limit) or beyond the possibilities limit.
+    fn check<M: Model<State = S>>(
+        &mut self,
+        model: &M,
+        level: Consistency,
+        epsilon: Timestamp,
+        verbose: bool,
+        deadline: Option<(Instant, Duration)>,
+        possib_limit: Option<usize>,
+    ) -> Result<bool> {
+        if M::TAGGED
+            && let Some(verdict) = check_tags(&self.client_queues, level, epsilon)
+        {
+            if verbose {
+                match verdict {
+                    Ok(()) => println!("  ✅ {} (by tags)", level),
+                    Err(witness) => println!("  ❌ non-{} (by tags) by {}", level, witness),
+                }
+            }
+            self.witness = verdict.err().unwrap_or(0);
+            return Ok(verdict.is_ok());
+        }
+
         let mut last_print = Instant::now();
+        self.reset(model.initial());
+        self.witness = 0;
 
         while let Some(possib) = self.possibilities.pop_front() {
-            // only for auxiliary printing ...
             let now = Instant::now();
-            if now.duration_since(last_print).as_millis() > 500 {
+            if let Some((deadline, limit)) = deadline
+                && now > deadline
+            {
+                return Err(Error::TimeLimitExceeded { limit });
+            }
+            if let Some(limit) = possib_limit
+                && self.possibilities.len() > limit
+            {
+                return Err(Error::PossibLimitExceeded { limit });
+            }
+
+            // only for auxiliary printing ...
+            if verbose && now.duration_since(last_print).as_millis() > 500 {
                 last_print = now;
                 println!(
                     "  ...  ∑ feed_prog: {:5}  |possib|: {:8}  |unique|: {:8}",
@@ -262,6 +839,7 @@ impl CheckerPerKey {
             // ... auxiliary printing ends
 
             let mut client_end_count = 0;
+            let mut reached = Timestamp::MAX;
             for (client, idx) in possib.feed_prog.clone().into_iter().enumerate() {
                 if idx == self.client_queues[client].len() {
                     client_end_count += 1;
@@ -272,8 +850,12 @@ impl CheckerPerKey {
                 if !feeding.terminated() {
                     continue;
                 }
+                reached = reached.min(feeding.finish);
 
                 Self::handle_feed_attempt(
+                    model,
+                    level,
+                    epsilon,
                     &possib,
                     feeding,
                     (client, idx),
@@ -285,23 +867,35 @@ impl CheckerPerKey {
 
             if client_end_count == self.client_queues.len() {
                 // found a possible ordering where all spans fit in the ordering
-                println!("  ✅ linearizable ");
-                return Ok(Consistency::Linearizable);
+                if verbose {
+                    println!("  ✅ {} ", level);
+                }
+                return Ok(true);
+            }
+            // indeterminate operations do not hold an ordering back
+            if reached != Timestamp::MAX {
+                self.witness = self.witness.max(reached);
             }
         }
 
-        println!("  ❌ non-linearizable");
-        Ok(Consistency::Weak)
+        if verbose {
+            println!("  ❌ non-{} by {}", level, self.witness);
+        }
+        Ok(false)
     }
 
     /// Process a feeding attempt, producing zero or more new possibilities.
-    fn handle_feed_attempt(
-        possib: &Possibility,
+    #[allow(clippy::too_many_arguments)]
+    fn handle_feed_attempt<M: Model<State = S>>(
+        model: &M,
+        level: Consistency,
+        epsilon: Timestamp,
+        possib: &Possibility<S>,
         feeding: &CkSpan,
         feeding_idx: FeedIdx,
         _client_queues: &Vec<Vec<CkSpan>>,
-        possibilities: &mut VecDeque<Possibility>,
-        possibilities_set: &mut HashSet<Possibility>,
+        possibilities: &mut VecDeque<Possibility<S>>,
+        possibilities_set: &mut HashSet<Possibility<S>>,
     ) {
         // print!("  ");
         // for &(client, idx) in &possib.graph {
@@ -311,22 +905,30 @@ impl CheckerPerKey {
         // println!(
         //     " ~{} @{} <- {}",
         //     possib.max_invoke,
-        //     if let Some(state) = possib.state {
-        //         state.to_string()
-        //     } else {
-        //         "-".to_string()
-        //     },
+        //     possib.state,
         //     feeding
         // );
 
-        // check on timestamp span first
-        if feeding.finish < possib.max_invoke {
+        // check on timestamp span first (real-time order only matters for
+        // linearizability)
+        if level == Consistency::Linearizable
+            && feeding.finish.saturating_add(epsilon) < possib.max_invoke
+        {
             return;
         }
 
         // check if this operation can be appended to the current graph with
         // matching state
-        if let Some(new_possib) = Self::try_append_new_span(possib, feeding, feeding_idx) {
+        if let Some(new_possib) = Self::try_append_new_span(model, possib, feeding, feeding_idx)
+            && !possibilities_set.contains(&new_possib)
+        {
+            possibilities.push_back(new_possib.clone());
+            possibilities_set.insert(new_possib);
+        }
+
+        // an indeterminate operation may also have never taken effect
+        if feeding.optional {
+            let new_possib = Self::skip_span(possib, feeding, feeding_idx);
             if !possibilities_set.contains(&new_possib) {
                 possibilities.push_back(new_possib.clone());
                 possibilities_set.insert(new_possib);
@@ -334,52 +936,90 @@ impl CheckerPerKey {
         }
     }
 
+    /// Move past an optional operation without appending it to the graph.
+    // NOTE: max_invoke still accounts for it, keeping max_invoke determined
+    //       by the feeding progress; this loses nothing, as skipping can be
+    //       deferred to after any operation it would otherwise exclude.
+    fn skip_span(
+        possib: &Possibility<S>,
+        feeding: &CkSpan,
+        feeding_idx: FeedIdx,
+    ) -> Possibility<S> {
+        let mut new_feed_prog = possib.feed_prog.clone();
+        new_feed_prog[feeding_idx.0] += 1;
+
+        Possibility::from(
+            possib.graph.clone(),
+            possib.state.clone(),
+            cmp::max(feeding.invoke, possib.max_invoke),
+            new_feed_prog,
+        )
+    }
+
     /// Try to append the operation to the end of the graph, returning
-    /// `Some(new_possibility)` if success.
-    fn try_append_new_span(
-        possib: &Possibility,
+    /// `Some(new_possibility)` if the model accepts it.
+    fn try_append_new_span<M: Model<State = S>>(
+        model: &M,
+        possib: &Possibility<S>,
         feeding: &CkSpan,
         feeding_idx: FeedIdx,
-    ) -> Option<Possibility> {
-        if let Some((new_graph, new_state)) = match &feeding.data {
-            CkData::Read { val } => {
-                if &possib.state == val {
-                    Some((possib.graph.clone(), possib.state.clone()))
-                } else {
-                    None
-                }
-            }
+    ) -> Option<Possibility<S>> {
+        let new_state = model.step(&possib.state, &feeding.data)?;
 
-            CkData::Write { val } => {
-                let mut new_graph = possib.graph.clone();
-                new_graph.push(feeding_idx);
-                Some((new_graph, Some(*val)))
-            }
+        // only operations with effects are part of the graph
+        let mut new_graph = possib.graph.clone();
+        if !feeding.data.read_only() {
+            new_graph.push(feeding_idx);
+        }
 
-            CkData::Rmw { rval, wval } => {
-                if &possib.state == rval {
-                    let mut new_graph = possib.graph.clone();
-                    new_graph.push(feeding_idx);
-                    Some((new_graph, wval.clone()))
-                } else {
-                    None
-                }
-            }
-        } {
-            // state matches, compose the new possibility with the next feeding
-            // progress vector where this client's index is incremented
-            let client = feeding_idx.0;
-            let mut new_feed_prog = possib.feed_prog.clone();
-            new_feed_prog[client] += 1;
-
-            Some(Possibility::from(
-                new_graph,
-                new_state,
-                cmp::max(feeding.invoke, possib.max_invoke),
-                new_feed_prog,
-            ))
+        // state matches, compose the new possibility with the next feeding
+        // progress vector where this client's index is incremented
+        let client = feeding_idx.0;
+        let mut new_feed_prog = possib.feed_prog.clone();
+        new_feed_prog[client] += 1;
+
+        Some(Possibility::from(
+            new_graph,
+            new_state,
+            cmp::max(feeding.invoke, possib.max_invoke),
+            new_feed_prog,
+        ))
+    }
+}
+
+/// Is a staleness bound found, and within the greatest one accepted, if any?
+fn within(bound: Option<u64>, max: Option<u64>) -> bool {
+    bound.is_some_and(|bound| max.is_none_or(|max| bound <= max))
+}
+
+/// Least value in `lo..=hi` for which the monotone predicate holds, if any.
+/// Values exponentially further from `lo` are tried first, as small bounds
+/// are both likelier and cheaper to check.
+fn least_passing(
+    lo: u64,
+    hi: u64,
+    mut holds: impl FnMut(u64) -> Result<bool>,
+) -> Result<Option<u64>> {
+    let (mut fail, mut pass) = (None, lo);
+    let mut step = 1u64;
+    while !holds(pass)? {
+        if pass >= hi {
+            return Ok(None);
+        }
+        fail = Some(pass);
+        pass = lo.saturating_add(step).min(hi);
+        step = step.saturating_mul(2);
+    }
+    let Some(mut fail) = fail else {
+        return Ok(Some(pass));
+    };
+    while pass - fail > 1 {
+        let mid = fail + (pass - fail) / 2;
+        if holds(mid)? {
+            pass = mid;
         } else {
-            None
+            fail = mid;
         }
     }
+    Ok(Some(pass))
 }
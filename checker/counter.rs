@@ -0,0 +1,343 @@
+//! Counter checker based on interval arithmetic.
+//!
+//! Increments of a counter commute, so instead of searching for an ordering,
+//! each read is checked against the interval of values it could observe: it
+//! must include every add that surely precedes it, may include any add that
+//! is concurrent with it, and excludes the rest. For linearizability the
+//! precedence is by real time, while the weaker levels use program order only,
+//! giving a cheap bounds check. Either is necessary but not sufficient for its
+//! level on its own, as reads are not checked against each other.
+//!
+//! Under linearizability, reads are then checked against each other in real
+//! time: as long as no delta is negative the counter never decreases, so no
+//! read may observe less than one that finished before it was invoked. With
+//! negative deltas there is no such cheap check, and the bounds alone cannot
+//! tell linearizability, so the verdict falls to the next level checked.
+
+use std::collections::{BTreeMap, HashMap};
+
+use crate::error::{Error, Result};
+use crate::types::{Consistency, KeyType, OpData, OpSpan, Timeline, Timestamp, Value};
+
+/// An add of a counter, with its signed delta.
+#[derive(Debug, Clone, Copy)]
+struct CtAdd {
+    invoke: Timestamp,
+    finish: Timestamp,
+    delta: i128,
+    /// May or may not have taken effect.
+    optional: bool,
+}
+
+/// A read of a counter, `None` if the value is not an integer.
+#[derive(Debug, Clone, Copy)]
+struct CtRead {
+    invoke: Timestamp,
+    finish: Timestamp,
+    val: Option<i128>,
+}
+
+/// Operations of one client stream, in program order.
+#[derive(Debug, Clone, Copy)]
+enum CtOp {
+    Add(CtAdd),
+    Read(CtRead),
+}
+
+/// Delta of a counter add; a nil delta (e.g., from `:incr`) counts as 1.
+pub(crate) fn counter_delta(val: &Value) -> Option<i64> {
+    match val {
+        Value::Nil => Some(1),
+        Value::Int(delta) => Some(*delta),
+        _ => None,
+    }
+}
+
+/// Value of a counter read; a nil read counts as the initial 0.
+pub(crate) fn counter_value(val: &Value) -> Option<i64> {
+    match val {
+        Value::Nil => Some(0),
+        Value::Int(val) => Some(*val),
+        _ => None,
+    }
+}
+
+impl CtOp {
+    fn from_raw(raw: &OpSpan) -> Result<Self> {
+        // operations that never completed may or may not have taken effect
+        let finish = if raw.terminated() {
+            raw.finish
+        } else {
+            Timestamp::MAX
+        };
+        match &raw.data {
+            OpData::Add { val, .. } => Ok(CtOp::Add(CtAdd {
+                invoke: raw.invoke,
+                finish,
+                delta: counter_delta(val)
+                    .ok_or_else(|| Error::Config(format!("non-integer counter delta: {}", val)))?
+                    as i128,
+                optional: raw.indeterminate || !raw.terminated(),
+            })),
+            OpData::Read { val, .. } => Ok(CtOp::Read(CtRead {
+                invoke: raw.invoke,
+                finish,
+                val: counter_value(val).map(i128::from),
+            })),
+            other => Err(Error::Config(format!("not a counter operation: {}", other))),
+        }
+    }
+}
+
+/// Interval of values a read may observe, with the sums of positive and
+/// negative deltas of the adds it must include and may include.
+#[derive(Debug, Clone, Copy, Default)]
+struct Bounds {
+    must_pos: i128,
+    must_neg: i128,
+    may_pos: i128,
+    may_neg: i128,
+}
+
+impl Bounds {
+    fn contains(&self, val: i128) -> bool {
+        let must = self.must_pos + self.must_neg;
+        must + self.may_neg <= val && val <= must + self.may_pos
+    }
+}
+
+/// Sums of positive and negative deltas.
+fn split_sum<'a>(deltas: impl Iterator<Item = &'a i128>) -> (i128, i128) {
+    deltas.fold((0, 0), |(pos, neg), &d| {
+        if d > 0 {
+            (pos + d, neg)
+        } else {
+            (pos, neg + d)
+        }
+    })
+}
+
+/// Overall counter checker, checking each key independently.
+#[derive(Debug)]
+pub struct CounterChecker {
+    /// Operations of each key, one stream per client.
+    per_key: HashMap<KeyType, Vec<Vec<CtOp>>>,
+
+    /// Levels to check, strongest first.
+    levels: Vec<Consistency>,
+
+    /// Print per-key results?
+    verbose: bool,
+}
+
+impl CounterChecker {
+    /// Create a new checker of a counter history. Operations other than adds
+    /// and reads are rejected.
+    pub fn new(timeline: Timeline) -> Result<Self> {
+        let num_clients = timeline.num_clients();
+        let mut per_key: HashMap<KeyType, Vec<Vec<CtOp>>> = HashMap::new();
+        for (client, queue) in timeline.queues.iter().enumerate() {
+            for span in queue {
+                // a read that never completed observed nothing
+                if span.read_only() && !span.terminated() {
+                    continue;
+                }
+                let streams = per_key
+                    .entry(span.key())
+                    .or_insert_with(|| vec![vec![]; num_clients]);
+                streams[client].push(CtOp::from_raw(span)?);
+            }
+        }
+
+        Ok(CounterChecker {
+            per_key,
+            levels: vec![Consistency::Linearizable],
+            verbose: true,
+        })
+    }
+
+    /// Set the levels to check (linearizability only by default). The result
+    /// of a check is the strongest of them that holds, or `Weak` if none.
+    pub fn set_levels(&mut self, levels: &[Consistency]) {
+        self.levels = levels
+            .iter()
+            .copied()
+            .filter(|&level| level != Consistency::Weak)
+            .collect();
+        self.levels.sort_unstable_by(|a, b| b.cmp(a));
+        self.levels.dedup();
+    }
+
+    /// Suppress per-key result printing.
+    pub fn set_quiet(&mut self) {
+        self.verbose = false;
+    }
+
+    /// Run the check for all keys.
+    pub fn check(&self) -> Consistency {
+        let mut result = self.levels.first().copied().unwrap_or(Consistency::Weak);
+        for level in self.check_per_key().into_values() {
+            if level < result {
+                result = level; // take minimum level strength across keys
+            }
+        }
+        result
+    }
+
+    /// Run the check for all keys, returning the result level of each key.
+    pub fn check_per_key(&self) -> BTreeMap<KeyType, Consistency> {
+        let mut results = BTreeMap::new();
+        for (key, streams) in self.per_key.iter() {
+            if self.verbose {
+                println!(" checking key {} ...", key);
+            }
+
+            let mut result = Consistency::Weak;
+            for &level in &self.levels {
+                let holds = match level {
+                    Consistency::Linearizable => check_real_time(streams),
+                    _ => check_program_order(streams),
+                };
+                if holds {
+                    if self.verbose {
+                        println!("  ✅ {} ", level);
+                    }
+                    result = level;
+                    break;
+                }
+                if self.verbose {
+                    println!("  ❌ non-{}", level);
+                }
+            }
+            results.insert(key.clone(), result);
+        }
+        results
+    }
+}
+
+/// Check reads against the adds that precede them in real time (must include)
+/// or are invoked before they finish (may include), then against the reads
+/// that precede them in real time, which needs all deltas non-negative.
+fn check_real_time(streams: &[Vec<CtOp>]) -> bool {
+    let adds: Vec<CtAdd> = streams
+        .iter()
+        .flatten()
+        .filter_map(|op| match op {
+            CtOp::Add(add) => Some(*add),
+            CtOp::Read(_) => None,
+        })
+        .collect();
+
+    // prefix sums of acknowledged adds by finish, and all adds by invoke
+    let prefix = |mut keyed: Vec<(Timestamp, i128)>| {
+        keyed.sort_unstable_by_key(|&(ts, _)| ts);
+        let mut sums = vec![(0, (0, 0))];
+        for (ts, delta) in keyed {
+            let (_, (pos, neg)) = *sums.last().unwrap();
+            if delta > 0 {
+                sums.push((ts, (pos + delta, neg)));
+            } else {
+                sums.push((ts, (pos, neg + delta)));
+            }
+        }
+        sums
+    };
+    let by_finish = prefix(
+        adds.iter()
+            .filter(|add| !add.optional)
+            .map(|add| (add.finish, add.delta))
+            .collect(),
+    );
+    let by_invoke = prefix(adds.iter().map(|add| (add.invoke, add.delta)).collect());
+    let sum_before = |sums: &[(Timestamp, (i128, i128))], ts: Timestamp| {
+        // first entry is the empty prefix
+        let n = sums[1..].partition_point(|&(t, _)| t < ts);
+        sums[n].1
+    };
+
+    let mut reads = vec![];
+    for op in streams.iter().flatten() {
+        let CtOp::Read(read) = op else {
+            continue;
+        };
+        let Some(val) = read.val else {
+            return false;
+        };
+        let (must_pos, must_neg) = sum_before(&by_finish, read.invoke);
+        let (inv_pos, inv_neg) = sum_before(&by_invoke, read.finish);
+        let bounds = Bounds {
+            must_pos,
+            must_neg,
+            may_pos: inv_pos - must_pos,
+            may_neg: inv_neg - must_neg,
+        };
+        if !bounds.contains(val) {
+            return false;
+        }
+        reads.push((read.invoke, read.finish, val));
+    }
+    if adds.iter().any(|add| add.delta < 0) {
+        return false;
+    }
+
+    // greatest value read before each read is invoked
+    let mut finished = reads.clone();
+    finished.sort_unstable_by_key(|&(_, finish, _)| finish);
+    reads.sort_unstable_by_key(|&(invoke, _, _)| invoke);
+    let mut before = finished.iter().peekable();
+    let mut greatest = None;
+    reads.iter().all(|&(invoke, _, val)| {
+        while let Some(&(_, _, earlier)) = before.next_if(|&&(_, finish, _)| finish < invoke) {
+            greatest = greatest.max(Some(earlier));
+        }
+        greatest.is_none_or(|greatest| greatest <= val)
+    })
+}
+
+/// Check reads against the earlier adds of their own client (must include)
+/// and the adds of all other clients (may include).
+fn check_program_order(streams: &[Vec<CtOp>]) -> bool {
+    let deltas = |stream: &Vec<CtOp>| -> Vec<i128> {
+        stream
+            .iter()
+            .filter_map(|op| match op {
+                CtOp::Add(add) => Some(add.delta),
+                CtOp::Read(_) => None,
+            })
+            .collect()
+    };
+    let totals: Vec<(i128, i128)> = streams
+        .iter()
+        .map(|stream| split_sum(deltas(stream).iter()))
+        .collect();
+    let (all_pos, all_neg) = totals
+        .iter()
+        .fold((0, 0), |(p, n), &(tp, tn)| (p + tp, n + tn));
+
+    streams
+        .iter()
+        .zip(&totals)
+        .all(|(stream, &(own_pos, own_neg))| {
+            let mut bounds = Bounds {
+                may_pos: all_pos - own_pos,
+                may_neg: all_neg - own_neg,
+                ..Bounds::default()
+            };
+            stream.iter().all(|op| match op {
+                CtOp::Add(add) => {
+                    let (pos, neg) = if add.optional {
+                        (&mut bounds.may_pos, &mut bounds.may_neg)
+                    } else {
+                        (&mut bounds.must_pos, &mut bounds.must_neg)
+                    };
+                    if add.delta > 0 {
+                        *pos += add.delta;
+                    } else {
+                        *neg += add.delta;
+                    }
+                    true
+                }
+                CtOp::Read(read) => read.val.is_some_and(|val| bounds.contains(val)),
+            })
+        })
+}
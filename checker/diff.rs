@@ -0,0 +1,304 @@
+//! Differential testing of the SOP checker against the Knossos results that
+//! Jepsen stored alongside the history.
+//!
+//! Both checkers should agree on whether each key is linearizable; any
+//! disagreement means there is a bug in one of the two. Their semantics
+//! differ on reads of nil though: Knossos's `CASRegister` model lets a read
+//! that returns nil match any value (the client did not learn the value),
+//! where SOP takes it as reading an unwritten (or deleted) register. Such
+//! reads are dropped from the timeline before checking, see
+//! `drop_nil_reads()`.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+use crate::edn::Edn;
+use crate::error::{Error, Result};
+use crate::types::{Consistency, KeyType, OpData, Timeline, Value};
+
+/// Results file name.
+const RESULTS_FILE: &str = "results.edn";
+
+/// Per-key results directory name of `jepsen.independent`.
+const INDEPENDENT_DIR: &str = "independent";
+
+/// Knossos verdicts read from the store: `Some(valid)`, or `None` for
+/// `:unknown` (e.g., when Knossos timed out).
+#[derive(Debug)]
+pub struct KnossosVerdicts {
+    pub overall: Option<bool>,
+    pub per_key: BTreeMap<KeyType, Option<bool>>,
+}
+
+/// Outcome of comparing the verdicts of the two checkers on one key.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Agreement {
+    Agree,
+    Disagree,
+    Unknown, // Knossos gave no definite answer
+}
+
+impl Agreement {
+    fn of(knossos: Option<bool>, sop: bool) -> Self {
+        match knossos {
+            Some(valid) if valid == sop => Agreement::Agree,
+            Some(_) => Agreement::Disagree,
+            None => Agreement::Unknown,
+        }
+    }
+}
+
+/// Convert a `:valid?` form into a verdict.
+fn valid_of(form: &Edn) -> Option<bool> {
+    match form {
+        Edn::Bool(b) => Some(*b),
+        _ => None, // :unknown
+    }
+}
+
+/// Read and parse an EDN file.
+fn read_edn(path: &Path) -> Result<Edn> {
+    let text = fs::read_to_string(path)?;
+    Edn::parse(&text).map_err(|err| Error::Malformed {
+        path: path.to_path_buf(),
+        line: None,
+        reason: err.to_string(),
+    })
+}
+
+/// Reads the Knossos verdicts, both from the top-level results file (where
+/// `jepsen.independent` nests per-key results under `:results`) and from the
+/// per-key `independent/*` result files. The latter take precedence.
+pub fn read_knossos_verdicts(test_dir: &Path) -> Result<KnossosVerdicts> {
+    let results_path = test_dir.join(RESULTS_FILE);
+    let malformed = |reason: &str| Error::Malformed {
+        path: results_path.clone(),
+        line: None,
+        reason: reason.to_string(),
+    };
+
+    let results = read_edn(&results_path)?;
+    let overall = results
+        .get("valid?")
+        .ok_or_else(|| malformed("no top-level :valid?"))?;
+
+    let mut verdicts = KnossosVerdicts {
+        overall: valid_of(overall),
+        per_key: BTreeMap::new(),
+    };
+
+    // results of composed checkers are maps under their own names
+    let mut found = false;
+    if let Edn::Map(pairs) = &results {
+        for (_, sub) in pairs {
+            if sub.get("valid?").is_none() {
+                continue;
+            }
+            found = true;
+
+            if let Some(Edn::Map(keyed)) = sub.get("results") {
+                for (key, res) in keyed {
+                    if let (Some(key), Some(valid)) = (KeyType::from_edn(key), res.get("valid?")) {
+                        verdicts.per_key.insert(key, valid_of(valid));
+                    }
+                }
+            }
+        }
+    }
+    if !found {
+        return Err(malformed("no checker results found"));
+    }
+
+    let indp_dir = test_dir.join(INDEPENDENT_DIR);
+    if indp_dir.is_dir() {
+        for entry in fs::read_dir(&indp_dir)? {
+            let entry = entry?;
+            let Ok(key) = entry.file_name().to_string_lossy().parse::<KeyType>() else {
+                continue;
+            };
+
+            let path = entry.path().join(RESULTS_FILE);
+            if path.is_file() {
+                let res = read_edn(&path)?;
+                if let Some(valid) = res.get("valid?") {
+                    verdicts.per_key.insert(key, valid_of(valid));
+                }
+            }
+        }
+    }
+
+    Ok(verdicts)
+}
+
+/// Drop the completed reads of nil from the timeline, as Knossos takes them
+/// as wildcards: a read that may return any value constrains no order of the
+/// register's operations. Returns the number dropped.
+pub fn drop_nil_reads(timeline: &mut Timeline) -> usize {
+    let mut dropped = 0;
+    for queue in &mut timeline.queues {
+        queue.retain(|op| {
+            let nil = matches!(
+                op.data,
+                OpData::Read {
+                    val: Value::Nil,
+                    ..
+                }
+            );
+            if nil {
+                dropped += 1;
+            }
+            !nil
+        });
+    }
+    dropped
+}
+
+/// Compare the Knossos verdicts against the SOP checker's per-key results and
+/// print a report, a key being valid to SOP if it satisfies the level checked.
+/// A key missing from the SOP results had no successful ops, which trivially
+/// satisfies any level. Returns true if no disagreement found.
+pub fn compare_verdicts(
+    knossos: &KnossosVerdicts,
+    sop: &BTreeMap<KeyType, Consistency>,
+    level: Consistency,
+) -> bool {
+    let mut keys: Vec<KeyType> = knossos.per_key.keys().chain(sop.keys()).cloned().collect();
+    keys.sort_unstable();
+    keys.dedup();
+
+    let fmt_knossos = |v: Option<bool>| match v {
+        Some(true) => "valid",
+        Some(false) => "invalid",
+        None => "unknown",
+    };
+    let fmt_sop = |v: bool| if v { "valid" } else { "invalid" };
+
+    let (mut num_agree, mut num_disagree, mut num_unknown) = (0, 0, 0);
+    println!("Diff result:  {:>6}  {:>8}  {:>8}", "key", "knossos", "sop");
+    for key in keys {
+        let sop_valid = sop.get(&key).is_none_or(|&result| result >= level);
+        let knossos_valid = knossos.per_key.get(&key).copied().unwrap_or(Some(true));
+
+        let agreement = Agreement::of(knossos_valid, sop_valid);
+        match agreement {
+            Agreement::Agree => num_agree += 1,
+            Agreement::Disagree => num_disagree += 1,
+            Agreement::Unknown => num_unknown += 1,
+        }
+        println!(
+            "              {:>6}  {:>8}  {:>8}{}",
+            key,
+            fmt_knossos(knossos_valid),
+            fmt_sop(sop_valid),
+            if agreement == Agreement::Disagree {
+                "  <-- DISAGREE"
+            } else {
+                ""
+            }
+        );
+    }
+
+    let sop_overall = sop.values().all(|&result| result >= level);
+    let overall = Agreement::of(knossos.overall, sop_overall);
+    println!(
+        "       overall  {:>8}  {:>8}{}",
+        fmt_knossos(knossos.overall),
+        fmt_sop(sop_overall),
+        if overall == Agreement::Disagree {
+            "  <-- DISAGREE"
+        } else {
+            ""
+        }
+    );
+
+    println!(
+        "Diff summary: {} keys agree, {} disagree, {} unknown",
+        num_agree, num_disagree, num_unknown
+    );
+
+    num_disagree == 0 && overall != Agreement::Disagree
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::check::Checker;
+    use crate::store::{fixture_dir, parse_history};
+    use crate::types::Timeline;
+
+    /// Key 0 is linearizable, while key 1 reads a value overwritten before
+    /// the read began.
+    const HISTORY: &str = "\
+{:index 0, :time 10, :type :invoke, :process 0, :f :write, :value [0 1]}
+{:index 1, :time 20, :type :ok, :process 0, :f :write, :value [0 1]}
+{:index 2, :time 30, :type :invoke, :process 1, :f :read, :value [0 nil]}
+{:index 3, :time 40, :type :ok, :process 1, :f :read, :value [0 1]}
+{:index 4, :time 50, :type :invoke, :process 0, :f :write, :value [1 1]}
+{:index 5, :time 60, :type :ok, :process 0, :f :write, :value [1 1]}
+{:index 6, :time 70, :type :invoke, :process 0, :f :write, :value [1 2]}
+{:index 7, :time 80, :type :ok, :process 0, :f :write, :value [1 2]}
+{:index 8, :time 90, :type :invoke, :process 1, :f :read, :value [1 nil]}
+{:index 9, :time 100, :type :ok, :process 1, :f :read, :value [1 1]}
+";
+
+    /// Diff mode agrees with the stored Knossos verdicts, from either the
+    /// top-level results or the per-key ones, exactly at the level they were
+    /// checked at.
+    #[test]
+    fn diff_matches_knossos_verdicts() {
+        let results = "\
+{:indp-linear {:valid? false,
+               :results {0 {:valid? true}, 1 {:valid? true}}},
+ :valid? false}
+";
+        let dir = fixture_dir(
+            "diff",
+            &[
+                ("history.edn", HISTORY),
+                ("results.edn", results),
+                ("independent/1/results.edn", "{:valid? false}"),
+            ],
+        );
+        let knossos = read_knossos_verdicts(&dir).expect("cannot read verdicts");
+        assert_eq!(knossos.overall, Some(false));
+        assert_eq!(knossos.per_key.len(), 2);
+
+        let (events, max_client) = parse_history(&dir).expect("cannot parse");
+        let timeline = Timeline::new(events, max_client).expect("invalid history");
+        for (level, agree) in [
+            (Consistency::Linearizable, true),
+            (Consistency::Sequential, false),
+        ] {
+            let mut checker = Checker::new(timeline.clone());
+            checker.set_quiet();
+            checker.set_levels(&[level]);
+            let sop = checker.check_per_key().expect("checker failed");
+            assert_eq!(compare_verdicts(&knossos, &sop, level), agree, "{}", level);
+        }
+        let _ = fs::remove_dir_all(dir);
+    }
+
+    /// The stored run reads nil from registers already written, which Knossos
+    /// accepts as wildcards: the verdicts only agree once those reads are
+    /// dropped.
+    #[test]
+    fn diff_takes_nil_reads_as_wildcards() {
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("store/current");
+        let knossos = read_knossos_verdicts(&dir).expect("cannot read verdicts");
+        assert_eq!(knossos.overall, Some(true));
+
+        let (events, max_client) = parse_history(&dir).expect("cannot parse");
+        let timeline = Timeline::new(events, max_client).expect("invalid history");
+        let level = Consistency::Linearizable;
+        let mut normalized = timeline.clone();
+        assert!(drop_nil_reads(&mut normalized) > 0);
+        for (timeline, agree) in [(timeline, false), (normalized, true)] {
+            let mut checker = Checker::new(timeline);
+            checker.set_quiet();
+            checker.set_levels(&[level]);
+            let sop = checker.check_per_key().expect("checker failed");
+            assert_eq!(compare_verdicts(&knossos, &sop, level), agree);
+        }
+    }
+}
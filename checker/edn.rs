@@ -0,0 +1,236 @@
+//! Minimal EDN reader, just enough for the files found in a Jepsen store
+//! directory (e.g. `results.edn`).
+//!
+//! Tagged literals (`#knossos.model.CASRegister{...}`, `#inst "..."`, etc.)
+//! are kept as `Edn::Tagged` without any interpretation. Numbers that are not
+//! plain integers (floats, ratios, bigints) are kept as `Edn::Symbol` text.
+
+use crate::error::{Error, Result};
+
+/// Parsed EDN form.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Edn {
+    Nil,
+    Bool(bool),
+    Int(i64),
+    Str(String),
+    Char(char),
+    Keyword(String), // without the leading ':'
+    Symbol(String),
+    List(Vec<Edn>),
+    Vector(Vec<Edn>),
+    Set(Vec<Edn>),
+    Map(Vec<(Edn, Edn)>),
+    Tagged(String, Box<Edn>),
+}
+
+impl Edn {
+    /// Parse the first form out of the given text.
+    pub(crate) fn parse(s: &str) -> Result<Self> {
+        let mut reader = Reader {
+            chars: s.chars().collect(),
+            pos: 0,
+        };
+        match reader.read()? {
+            Some(form) => Ok(form),
+            None => Err(reader.error("no EDN form found")),
+        }
+    }
+
+    /// Look up a keyword key in a map form (tagged maps included).
+    pub(crate) fn get(&self, keyword: &str) -> Option<&Edn> {
+        match self {
+            Edn::Map(pairs) => pairs.iter().find_map(|(k, v)| match k {
+                Edn::Keyword(name) if name == keyword => Some(v),
+                _ => None,
+            }),
+            Edn::Tagged(_, form) => form.get(keyword),
+            _ => None,
+        }
+    }
+}
+
+/// Character-level reader state.
+struct Reader {
+    chars: Vec<char>,
+    pos: usize,
+}
+
+impl Reader {
+    fn error(&self, reason: impl Into<String>) -> Error {
+        Error::Edn {
+            pos: self.pos,
+            reason: reason.into(),
+        }
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn skip_blanks(&mut self) {
+        while let Some(c) = self.peek() {
+            if c.is_whitespace() || c == ',' {
+                self.pos += 1;
+            } else if c == ';' {
+                while let Some(c) = self.peek() {
+                    self.pos += 1;
+                    if c == '\n' {
+                        break;
+                    }
+                }
+            } else {
+                break;
+            }
+        }
+    }
+
+    /// Read the next form, returning `None` at end of input.
+    fn read(&mut self) -> Result<Option<Edn>> {
+        self.skip_blanks();
+        let Some(c) = self.peek() else {
+            return Ok(None);
+        };
+
+        let form = match c {
+            '(' => {
+                self.pos += 1;
+                Edn::List(self.read_seq(')')?)
+            }
+            '[' => {
+                self.pos += 1;
+                Edn::Vector(self.read_seq(']')?)
+            }
+            '{' => {
+                self.pos += 1;
+                let items = self.read_seq('}')?;
+                Edn::Map(self.pair_up(items)?)
+            }
+            '"' => {
+                self.pos += 1;
+                Edn::Str(self.read_string()?)
+            }
+            '\\' => {
+                self.pos += 1;
+                let token = self.read_token();
+                match token.as_str() {
+                    "newline" => Edn::Char('\n'),
+                    "space" => Edn::Char(' '),
+                    "tab" => Edn::Char('\t'),
+                    _ => Edn::Char(token.chars().next().unwrap_or(' ')),
+                }
+            }
+            '#' => {
+                self.pos += 1;
+                match self.peek() {
+                    Some('{') => {
+                        self.pos += 1;
+                        Edn::Set(self.read_seq('}')?)
+                    }
+                    Some('_') => {
+                        // discard the next form
+                        self.pos += 1;
+                        self.read()?;
+                        return self.read();
+                    }
+                    _ => {
+                        let tag = self.read_token();
+                        match self.read()? {
+                            Some(form) => Edn::Tagged(tag, Box::new(form)),
+                            None => return Err(self.error(format!("dangling tag #{}", tag))),
+                        }
+                    }
+                }
+            }
+            ')' | ']' | '}' => {
+                return Err(self.error(format!("unexpected '{}'", c)));
+            }
+            _ => Self::atom(self.read_token()),
+        };
+
+        Ok(Some(form))
+    }
+
+    /// Read forms until the given closing delimiter.
+    fn read_seq(&mut self, close: char) -> Result<Vec<Edn>> {
+        let mut items = vec![];
+        loop {
+            self.skip_blanks();
+            match self.peek() {
+                Some(c) if c == close => {
+                    self.pos += 1;
+                    return Ok(items);
+                }
+                Some(_) => {
+                    if let Some(form) = self.read()? {
+                        items.push(form);
+                    }
+                }
+                None => return Err(self.error(format!("missing closing '{}'", close))),
+            }
+        }
+    }
+
+    fn read_string(&mut self) -> Result<String> {
+        let mut s = String::new();
+        while let Some(c) = self.peek() {
+            self.pos += 1;
+            match c {
+                '"' => return Ok(s),
+                '\\' => {
+                    let Some(e) = self.peek() else { break };
+                    self.pos += 1;
+                    s.push(match e {
+                        'n' => '\n',
+                        't' => '\t',
+                        'r' => '\r',
+                        _ => e,
+                    });
+                }
+                _ => s.push(c),
+            }
+        }
+        Err(self.error("unterminated string"))
+    }
+
+    fn read_token(&mut self) -> String {
+        let start = self.pos;
+        while let Some(c) = self.peek() {
+            if c.is_whitespace() || matches!(c, ',' | '(' | ')' | '[' | ']' | '{' | '}' | '"' | ';')
+            {
+                break;
+            }
+            self.pos += 1;
+        }
+        self.chars[start..self.pos].iter().collect()
+    }
+
+    fn atom(token: String) -> Edn {
+        match token.as_str() {
+            "nil" => Edn::Nil,
+            "true" => Edn::Bool(true),
+            "false" => Edn::Bool(false),
+            _ => {
+                if let Some(name) = token.strip_prefix(':') {
+                    Edn::Keyword(name.to_string())
+                } else if let Ok(i) = token.parse::<i64>() {
+                    Edn::Int(i)
+                } else {
+                    Edn::Symbol(token)
+                }
+            }
+        }
+    }
+
+    fn pair_up(&self, items: Vec<Edn>) -> Result<Vec<(Edn, Edn)>> {
+        if !items.len().is_multiple_of(2) {
+            return Err(self.error("map with odd number of forms"));
+        }
+        let mut pairs = Vec::with_capacity(items.len() / 2);
+        let mut iter = items.into_iter();
+        while let (Some(k), Some(v)) = (iter.next(), iter.next()) {
+            pairs.push((k, v));
+        }
+        Ok(pairs)
+    }
+}
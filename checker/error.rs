@@ -0,0 +1,223 @@
+//! Error types of the checker library.
+
+use std::fmt;
+use std::io;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use crate::types::{ClientId, EventType, OpData, Timestamp};
+
+/// Errors produced by the checker library.
+#[derive(Debug)]
+pub enum Error {
+    /// Failure reading or writing a file.
+    Io(io::Error),
+
+    /// Malformed line of a history file (line numbers start from 1).
+    Parse { line: usize, kind: ParseErrorKind },
+
+    /// Client invoked an operation while its previous one is still flying.
+    InvokeWhileFlying {
+        client: ClientId,
+        time: Timestamp,
+        index: u64,
+    },
+    /// Completion event (`:ok`, `:fail`, or `:info`) of a client with no
+    /// operation flying.
+    CompleteWhenIdle {
+        client: ClientId,
+        time: Timestamp,
+        index: u64,
+        etype: EventType,
+    },
+    /// `:ok` event whose operation data mismatches that of the invocation.
+    MismatchedCompletion {
+        client: ClientId,
+        time: Timestamp,
+        index: u64,
+        invoked: Box<OpData>,
+        completed: Box<OpData>,
+    },
+    /// Invocation still flying at the end of the history.
+    DanglingInvoke {
+        client: ClientId,
+        time: Timestamp,
+        index: u64,
+    },
+    /// `:ok` event of a read-modify-write whose recorded written value does
+    /// not follow from its read value.
+    InvalidRmwResult {
+        client: ClientId,
+        time: Timestamp,
+        index: u64,
+        completed: Box<OpData>,
+    },
+
+    /// Malformed EDN text at the given char position.
+    Edn { pos: usize, reason: String },
+    /// Malformed auxiliary file (e.g., Knossos or benchmark results).
+    Malformed {
+        path: PathBuf,
+        line: Option<usize>,
+        reason: String,
+    },
+
+    /// Check exceeded its time limit before reaching a verdict.
+    TimeLimitExceeded { limit: Duration },
+    /// Check exceeded its limit on pending possibilities before reaching a
+    /// verdict.
+    PossibLimitExceeded { limit: usize },
+
+    /// Invalid configuration of a tool (generator, simulator, etc.).
+    Config(String),
+}
+
+/// Kinds of malformed history lines.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseErrorKind {
+    /// `:index` not larger than that of the previous line.
+    IndexNotMonotonic { index: i64, last: i64 },
+    /// `:time` not larger than that of the previous line.
+    TimeNotMonotonic { time: Timestamp, last: Timestamp },
+    /// Unknown `:type` keyword.
+    UnknownEventType(String),
+    /// Unknown `:f` keyword.
+    UnknownOpType(String),
+    /// Field whose value cannot be interpreted.
+    InvalidField { field: String, value: String },
+    /// Field that requires the `:f` field to be known first.
+    MissingOpType { field: String },
+    /// Segment not of the form `:field value`.
+    InvalidSegment(String),
+    /// Line lacking some of the fields required to form an event.
+    MissingFields,
+}
+
+/// Result type of the checker library.
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl Error {
+    /// Is this a violation of the invoke/complete protocol of clients, i.e.,
+    /// an error of the history content rather than its syntax?
+    pub fn is_protocol_violation(&self) -> bool {
+        matches!(
+            self,
+            Error::InvokeWhileFlying { .. }
+                | Error::CompleteWhenIdle { .. }
+                | Error::MismatchedCompletion { .. }
+                | Error::DanglingInvoke { .. }
+                | Error::InvalidRmwResult { .. }
+        )
+    }
+}
+
+impl fmt::Display for ParseErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseErrorKind::IndexNotMonotonic { index, last } => {
+                write!(f, "index {} <= last index {}", index, last)
+            }
+            ParseErrorKind::TimeNotMonotonic { time, last } => {
+                write!(f, "timestamp {} <= last timestamp {}", time, last)
+            }
+            ParseErrorKind::UnknownEventType(s) => write!(f, "unknown event type: {}", s),
+            ParseErrorKind::UnknownOpType(s) => write!(f, "unknown operation type: {}", s),
+            ParseErrorKind::InvalidField { field, value } => {
+                write!(f, "invalid {}: {}", field, value)
+            }
+            ParseErrorKind::MissingOpType { field } => {
+                write!(f, "missing op type :f for {}", field)
+            }
+            ParseErrorKind::InvalidSegment(seg) => write!(f, "invalid segment: {}", seg),
+            ParseErrorKind::MissingFields => write!(f, "missing event field(s)"),
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Io(err) => write!(f, "I/O error: {}", err),
+            Error::Parse { line, kind } => write!(f, "line {}: {}", line, kind),
+            Error::InvokeWhileFlying {
+                client,
+                time,
+                index,
+            } => write!(
+                f,
+                "client {} :invoke @ {} (index {}) when previous op flying",
+                client, time, index
+            ),
+            Error::CompleteWhenIdle {
+                client,
+                time,
+                index,
+                etype,
+            } => write!(
+                f,
+                "client {} {} @ {} (index {}) when no op is flying",
+                client,
+                etype.to_type(),
+                time,
+                index
+            ),
+            Error::MismatchedCompletion {
+                client,
+                time,
+                index,
+                invoked,
+                completed,
+            } => write!(
+                f,
+                "client {} :ok @ {} (index {}) op {} mismatching previous {}",
+                client, time, index, completed, invoked
+            ),
+            Error::DanglingInvoke {
+                client,
+                time,
+                index,
+            } => write!(
+                f,
+                "client {} :invoke @ {} (index {}) never completed",
+                client, time, index
+            ),
+            Error::InvalidRmwResult {
+                client,
+                time,
+                index,
+                completed,
+            } => write!(
+                f,
+                "client {} :ok @ {} (index {}) op {} not following from its function",
+                client, time, index, completed
+            ),
+            Error::Edn { pos, reason } => write!(f, "EDN error at char {}: {}", pos, reason),
+            Error::Malformed { path, line, reason } => match line {
+                Some(line) => write!(f, "{}:{}: {}", path.display(), line, reason),
+                None => write!(f, "{}: {}", path.display(), reason),
+            },
+            Error::TimeLimitExceeded { limit } => {
+                write!(f, "check exceeded its time limit of {:?}", limit)
+            }
+            Error::PossibLimitExceeded { limit } => {
+                write!(f, "check exceeded its limit of {} possibilities", limit)
+            }
+            Error::Config(msg) => write!(f, "invalid config: {}", msg),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(err: io::Error) -> Self {
+        Error::Io(err)
+    }
+}
@@ -0,0 +1,436 @@
+//! Synthetic history generator, simulating an atomic register per key under
+//! configurable concurrency. Generated histories follow the exact format of
+//! Jepsen's `history.edn`, for building regression and benchmark corpora.
+//!
+//! Each operation takes effect atomically at a random point within its span,
+//! so a generated history is linearizable unless anomalies are injected.
+//! `:fail` operations never take effect, while `:info` ones may or may not,
+//! as their outcome is indeterminate: the timeline keeps them as such.
+
+use std::collections::{HashMap, HashSet};
+
+use clap::ValueEnum;
+
+use crate::error::{Error, Result};
+use crate::rmw::RmwFunc;
+use crate::types::{
+    ClientId, Event, EventType, KeyType, OpData, Timestamp, UniqueTag, ValType, Value,
+};
+
+/// Minimal seedable pseudo-random generator (SplitMix64), so that generated
+/// histories are reproducible from the seed alone.
+#[derive(Debug, Clone)]
+pub struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        Rng { state: seed }
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9e3779b97f4a7c15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+        z ^ (z >> 31)
+    }
+
+    /// Uniform integer in `[0, n)`; `n` must be positive.
+    pub fn below(&mut self, n: u64) -> u64 {
+        self.next_u64() % n
+    }
+
+    /// Uniform integer in `[lo, hi]`.
+    pub fn between(&mut self, lo: u64, hi: u64) -> u64 {
+        lo + self.below(hi - lo + 1)
+    }
+
+    /// True with probability `p`.
+    pub fn chance(&mut self, p: f64) -> bool {
+        ((self.next_u64() >> 11) as f64 / (1u64 << 53) as f64) < p
+    }
+
+    /// Pick up to `n` distinct elements out of the given candidates.
+    pub fn sample<T: Copy>(&mut self, candidates: &[T], n: usize) -> Vec<T> {
+        let mut pool = candidates.to_vec();
+        let n = n.min(pool.len());
+        for i in 0..n {
+            let j = i + self.below((pool.len() - i) as u64) as usize;
+            pool.swap(i, j);
+        }
+        pool.truncate(n);
+        pool
+    }
+}
+
+/// Kinds of anomalies that could be injected into a generated history.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, ValueEnum)]
+pub enum AnomalyKind {
+    /// A read returns the value before the latest write.
+    StaleRead,
+    /// A write is acknowledged but never takes effect.
+    LostWrite,
+    /// A write takes effect only after the next write on the same key, or is
+    /// lost (and counted as such) if no write follows.
+    ReorderedWrites,
+}
+
+/// Generator configuration.
+#[derive(Debug, Clone)]
+pub struct GenConfig {
+    /// Random seed.
+    pub seed: u64,
+    /// Number of concurrent clients.
+    pub clients: usize,
+    /// Number of distinct keys.
+    pub keys: usize,
+    /// Total number of operations invoked.
+    pub ops: usize,
+    /// Values are drawn from `[0, value_range)`.
+    pub value_range: u64,
+    /// Relative weights of :read, :write, and :cas operations.
+    pub mix: [u64; 3],
+    /// Probability of an operation completing as :fail.
+    pub fail_rate: f64,
+    /// Probability of an operation completing as :info, having taken effect
+    /// or not with even odds.
+    pub info_rate: f64,
+    /// Number of anomalies to inject of each kind. The actual number injected
+    /// might be lower if not enough eligible operations exist.
+    pub anomalies: Vec<(AnomalyKind, usize)>,
+}
+
+impl Default for GenConfig {
+    fn default() -> Self {
+        GenConfig {
+            seed: 0,
+            clients: 5,
+            keys: 1,
+            ops: 1000,
+            value_range: 10,
+            mix: [1, 1, 1],
+            fail_rate: 0.0,
+            info_rate: 0.0,
+            anomalies: vec![],
+        }
+    }
+}
+
+// Timing parameters (in nanoseconds, like Jepsen's relative `:time`).
+const START_TIME: Timestamp = 100_000_000;
+const MAX_THINK: Timestamp = 2_000_000;
+const MAX_LATENCY: Timestamp = 1_000_000;
+
+/// Client time slots are spaced apart by this factor so that timestamps of
+/// different clients never collide.
+const TIME_SLOTS: Timestamp = 1024;
+
+/// How an operation completes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Outcome {
+    Okay,
+    Fail,
+    /// Indeterminate, though the operation took effect if true.
+    Info(bool),
+}
+
+/// A generated operation, before its result is known.
+#[derive(Debug, Clone)]
+struct GenOp {
+    process: ClientId,
+    invoke: Timestamp,
+    effect: Timestamp,
+    finish: Timestamp,
+    outcome: Outcome,
+    data: OpData,
+}
+
+/// Result of history generation.
+#[derive(Debug)]
+pub struct Generated {
+    /// Events sorted by time.
+    pub events: Vec<Event>,
+    /// Number of anomalies actually injected of each kind.
+    pub injected: HashMap<AnomalyKind, usize>,
+}
+
+/// Generate a history according to the configuration.
+pub fn generate(cfg: &GenConfig) -> Result<Generated> {
+    if cfg.clients == 0 || cfg.clients as Timestamp >= TIME_SLOTS {
+        return Err(Error::Config(format!(
+            "number of clients must be in [1, {})",
+            TIME_SLOTS
+        )));
+    }
+    if cfg.keys == 0 || cfg.value_range == 0 {
+        return Err(Error::Config(
+            "number of keys and value range must be positive".into(),
+        ));
+    }
+    if cfg.mix.iter().sum::<u64>() == 0 {
+        return Err(Error::Config(
+            "operation mix must have a positive weight".into(),
+        ));
+    }
+
+    let mut rng = Rng::new(cfg.seed);
+    let mut ops = schedule_ops(cfg, &mut rng);
+
+    // pick the operations to inject anomalies into
+    let mut chosen: HashMap<AnomalyKind, HashSet<usize>> = HashMap::new();
+    for &(kind, count) in &cfg.anomalies {
+        let eligible: Vec<usize> = (0..ops.len())
+            .filter(|&i| {
+                ops[i].outcome == Outcome::Okay
+                    && match kind {
+                        AnomalyKind::StaleRead => matches!(ops[i].data, OpData::Read { .. }),
+                        _ => matches!(ops[i].data, OpData::Write { .. }),
+                    }
+                    && !chosen.values().any(|set| set.contains(&i))
+            })
+            .collect();
+        chosen
+            .entry(kind)
+            .or_default()
+            .extend(rng.sample(&eligible, count));
+    }
+
+    let injected = apply_effects(&mut ops, &chosen);
+    Ok(Generated {
+        events: to_events(ops),
+        injected,
+    })
+}
+
+/// Schedule operations onto clients, each client issuing one operation at a
+/// time. Values and results are filled in later by `apply_effects()`.
+fn schedule_ops(cfg: &GenConfig, rng: &mut Rng) -> Vec<GenOp> {
+    let mut ops = Vec::with_capacity(cfg.ops);
+    let mut process: Vec<ClientId> = (0..cfg.clients).collect();
+    let mut avail: Vec<Timestamp> = (0..cfg.clients)
+        .map(|_| START_TIME / TIME_SLOTS + rng.below(MAX_THINK / TIME_SLOTS))
+        .collect();
+
+    let mix_sum: u64 = cfg.mix.iter().sum();
+    for _ in 0..cfg.ops {
+        // the client that becomes available the earliest issues the next op
+        let slot = (0..cfg.clients).min_by_key(|&c| avail[c]).unwrap();
+
+        // times are counted in slot ticks and converted at the end
+        let invoke = avail[slot] + rng.between(1, MAX_THINK / TIME_SLOTS);
+        let effect = invoke + rng.below(MAX_LATENCY / TIME_SLOTS);
+        let finish = effect + rng.between(1, MAX_LATENCY / TIME_SLOTS);
+        avail[slot] = finish;
+        let to_time = |tick: Timestamp| tick * TIME_SLOTS + slot as Timestamp;
+
+        let key = Value::Int(rng.below(cfg.keys as u64) as i64);
+        let pick = rng.below(mix_sum);
+        let data = if pick < cfg.mix[0] {
+            OpData::Read {
+                key,
+                val: Value::Nil,
+                tag: None,
+            }
+        } else if pick < cfg.mix[0] + cfg.mix[1] {
+            OpData::Write {
+                key,
+                val: Value::Int(rng.below(cfg.value_range) as i64),
+                tag: to_time(invoke) as UniqueTag,
+            }
+        } else {
+            OpData::Rmw {
+                key,
+                func: RmwFunc::Cas,
+                arg: Value::Nil,
+                rval: Value::Int(rng.below(cfg.value_range) as i64),
+                rtag: None,
+                wval: Value::Int(rng.below(cfg.value_range) as i64),
+                wtag: Some(to_time(invoke) as UniqueTag),
+            }
+        };
+
+        let outcome = if rng.chance(cfg.fail_rate) {
+            Outcome::Fail
+        } else if rng.chance(cfg.info_rate) {
+            Outcome::Info(rng.chance(0.5))
+        } else {
+            Outcome::Okay
+        };
+
+        ops.push(GenOp {
+            process: process[slot],
+            invoke: to_time(invoke),
+            effect: to_time(effect),
+            finish: to_time(finish),
+            outcome,
+            data,
+        });
+
+        // like Jepsen, a crashed process is replaced by a fresh one
+        if let Outcome::Info(_) = outcome {
+            process[slot] += cfg.clients;
+        }
+    }
+
+    ops
+}
+
+/// Apply operations to the simulated registers in the order of their effect
+/// points, filling in the results and injecting the chosen anomalies.
+fn apply_effects(
+    ops: &mut [GenOp],
+    chosen: &HashMap<AnomalyKind, HashSet<usize>>,
+) -> HashMap<AnomalyKind, usize> {
+    let is_chosen = |kind, i| chosen.get(&kind).is_some_and(|set| set.contains(&i));
+    let mut injected = HashMap::new();
+
+    // version history of each key, latest last
+    let mut versions: HashMap<KeyType, Vec<(ValType, UniqueTag)>> = HashMap::new();
+    // write held back per key, to be applied after the next write
+    let mut held: HashMap<KeyType, (ValType, UniqueTag)> = HashMap::new();
+
+    let mut order: Vec<usize> = (0..ops.len()).collect();
+    order.sort_by_key(|&i| (ops[i].effect, i));
+
+    for i in order {
+        let op = &mut ops[i];
+        if matches!(op.outcome, Outcome::Fail | Outcome::Info(false)) {
+            continue;
+        }
+
+        let hist = versions.entry(op.data.key()).or_default();
+        match &mut op.data {
+            OpData::Read { val, tag, .. } => {
+                let mut seen = hist.last().cloned();
+                if is_chosen(AnomalyKind::StaleRead, i) && hist.len() >= 2 {
+                    // return the latest older version with a different value
+                    let cur = &hist[hist.len() - 1].0;
+                    if let Some(old) = hist.iter().rev().find(|(v, _)| v != cur) {
+                        seen = Some(old.clone());
+                        *injected.entry(AnomalyKind::StaleRead).or_insert(0) += 1;
+                    }
+                }
+                *tag = seen.as_ref().map(|(_, t)| *t);
+                *val = seen.map(|(v, _)| v).unwrap_or_default();
+            }
+
+            OpData::Write { key, val, tag } => {
+                if is_chosen(AnomalyKind::LostWrite, i) {
+                    *injected.entry(AnomalyKind::LostWrite).or_insert(0) += 1;
+                } else if is_chosen(AnomalyKind::ReorderedWrites, i) && !held.contains_key(key) {
+                    held.insert(key.clone(), (val.clone(), *tag));
+                } else {
+                    hist.push((val.clone(), *tag));
+                    if let Some(prev) = held.remove(key) {
+                        hist.push(prev);
+                        *injected.entry(AnomalyKind::ReorderedWrites).or_insert(0) += 1;
+                    }
+                }
+            }
+
+            OpData::Rmw {
+                rval,
+                rtag,
+                wval,
+                wtag,
+                ..
+            } => {
+                let cur = hist.last().cloned();
+                if cur.as_ref().map_or(&Value::Nil, |(v, _)| v) == rval {
+                    *rtag = cur.map(|(_, t)| t);
+                    hist.push((wval.clone(), wtag.unwrap()));
+                } else if op.outcome == Outcome::Okay {
+                    op.outcome = Outcome::Fail;
+                }
+            }
+
+            OpData::Add { .. }
+            | OpData::Enqueue { .. }
+            | OpData::Dequeue { .. }
+            | OpData::Append { .. }
+            | OpData::Delete { .. }
+            | OpData::Txn { .. } => {
+                unreachable!()
+            }
+        }
+    }
+
+    // a write held with none following never takes effect
+    if !held.is_empty() {
+        *injected.entry(AnomalyKind::LostWrite).or_insert(0) += held.len();
+    }
+    injected
+}
+
+/// Turn operations into invocation and completion events sorted by time, and
+/// indexed in that order.
+fn to_events(ops: Vec<GenOp>) -> Vec<Event> {
+    let mut events = Vec::with_capacity(ops.len() * 2);
+    for op in ops {
+        let mut invoke_data = op.data.clone();
+        if let OpData::Read { val, tag, .. } = &mut invoke_data {
+            *val = Value::Nil;
+            *tag = None;
+        }
+        events.push(Event::new(
+            0,
+            op.invoke,
+            EventType::Invoke,
+            op.process,
+            invoke_data.clone(),
+        ));
+
+        let (etype, data) = match op.outcome {
+            Outcome::Okay => (EventType::Okay, op.data),
+            Outcome::Fail => (EventType::Fail, invoke_data),
+            Outcome::Info(_) => (EventType::Error, invoke_data),
+        };
+        events.push(Event::new(0, op.finish, etype, op.process, data));
+    }
+
+    events.sort_by_key(|e| e.time);
+    for (index, event) in events.iter_mut().enumerate() {
+        event.index = index as u64;
+    }
+    events
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{EventType, OpData};
+
+    /// Every write picked for reordering is counted, as lost if no write
+    /// follows to reorder it with.
+    #[test]
+    fn reordered_writes_are_counted() {
+        let mut rng = Rng::new(0);
+        let mut lost = 0;
+
+        for case in 0..400 {
+            let cfg = GenConfig {
+                seed: rng.next_u64(),
+                clients: rng.between(1, 3) as usize,
+                ops: rng.between(1, 8) as usize,
+                mix: [1, 1, 0],
+                anomalies: vec![(AnomalyKind::ReorderedWrites, 1)],
+                ..GenConfig::default()
+            };
+            let generated = generate(&cfg).expect("generator failed");
+            let writes = generated
+                .events
+                .iter()
+                .filter(|e| e.etype == EventType::Okay && matches!(e.opdata, OpData::Write { .. }))
+                .count();
+            let injected: usize = generated.injected.values().sum();
+            assert_eq!(injected, writes.min(1), "case {}: injected", case);
+            lost += generated
+                .injected
+                .get(&AnomalyKind::LostWrite)
+                .unwrap_or(&0);
+        }
+        assert!(lost > 0, "no held write left without a following one");
+    }
+}
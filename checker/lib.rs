@@ -0,0 +1,73 @@
+//! Library of a checker based on the SOP model: loading Jepsen histories,
+//! building per-client timelines, and checking them against consistency
+//! levels.
+//!
+//! The current implementation uses an offline procesesing style just like
+//! original Jepsen, but should be easy to adapt to an online style.
+//!
+//! Typical usage:
+//!
+//! ```no_run
+//! use std::path::Path;
+//! use sop_checker::{Checker, Consistency, Timeline, parse_history};
+//!
+//! let (events, max_client) = parse_history(Path::new("store/latest"))?;
+//! let timeline = Timeline::new(events, max_client)?;
+//! let mut checker = Checker::new(timeline);
+//! checker.set_levels(&[Consistency::Sequential, Consistency::Linearizable]);
+//! println!("{:?}", checker.check()?);
+//! # Ok::<(), sop_checker::Error>(())
+//! ```
+
+mod append;
+mod causal;
+mod check;
+mod counter;
+mod edn;
+mod error;
+mod json;
+mod model;
+mod nemesis;
+mod nodes;
+mod print;
+mod queue;
+mod repair;
+mod report;
+mod rmw;
+mod stats;
+mod store;
+mod tags;
+mod txn;
+mod types;
+mod validate;
+
+// tooling around the checker
+pub mod bench;
+pub mod diff;
+pub mod generator;
+pub mod run;
+pub mod sim;
+
+#[cfg(test)]
+mod oracle;
+
+pub use append::{AppendChecker, ListAnomaly};
+pub use check::{CheckScope, Checker, LevelVerdict, result_level};
+pub use counter::CounterChecker;
+pub use error::{Error, ParseErrorKind, Result};
+pub use json::Json;
+pub use model::{Counter, Fifo, GrowSet, ListAppend, Model, ModelKind, RegState, Register};
+pub use nemesis::{Fault, FaultWindow, Period, Side, parse_faults, period_at, periods};
+pub use nodes::{ClientNodes, JEPSEN_LOG_FILE};
+pub use queue::{TotalQueueReport, check_total_queue};
+pub use repair::{Anomaly, Repair, RepairAction, RepairPolicy};
+pub use report::{Attribution, PeriodVerdict, Report, Violation, Witness};
+pub use rmw::RmwFunc;
+pub use stats::{Latencies, OpGroup, PeriodRate, TimelineStats, timeline_stats};
+pub use store::{HISTORY_FILE, HistoryWriter, parse_history, parse_history_with};
+pub use txn::TxnChecker;
+pub use types::{
+    ClientId, Consistency, Event, EventType, KeyType, Mop, OpData, OpSpan, Timeline, Timestamp,
+    UniqueTag, ValType, Value,
+};
+pub use validate::{DataAnomaly, validate_registers};
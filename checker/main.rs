@@ -1,30 +1,292 @@
-//! Rust implementation of a checker based on the SOP model.
-//!
-//! The current implementation uses an offline procesesing style just like
-//! original Jepsen, but should be easy to adapt to an online style.
+//! Command line interface of the SOP model checker, see the `sop_checker`
+//! library for the implementation.
 
 use std::error::Error;
 use std::path::Path;
 use std::process;
-use std::time::Instant;
+use std::time::{Duration, Instant};
 
-use clap::Parser;
+use clap::{Parser, Subcommand, ValueEnum};
 
-mod store;
-use store::parse_history;
-
-mod types;
-use types::{Consistency, Timeline};
-
-mod check;
-use check::Checker;
+use sop_checker::bench::{
+    BenchConfig, compare_results, load_results, run_benchmarks, save_results,
+};
+use sop_checker::generator::{AnomalyKind, GenConfig, generate};
+use sop_checker::run::{
+    Attributing, Limits, Relaxation, Reporting, check_run, diff_run, history_writer,
+};
+use sop_checker::sim::{FaultKind, ReplicationMode, SimConfig, SimLine, simulate};
+use sop_checker::{Consistency, ModelKind, RepairPolicy, Timestamp};
 
 /// Command line arguments.
 #[derive(Parser, Debug)]
+#[command(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
 struct Args {
+    #[command(subcommand)]
+    command: Option<Command>,
+
     /// Jepsen test store directory.
+    #[arg(short, long, required = true)]
+    test_dir: Option<String>,
+
+    /// Consistency levels to check (weak, causal, sequential, delta-atomic,
+    /// k-atomic, linearizable; or snapshot-isolation, serializable,
+    /// strict-serializable for transactions) [default: linearizable, or
+    /// strict-serializable for transactions].
+    #[arg(short, long, value_delimiter = ',')]
+    levels: Vec<Consistency>,
+
+    /// How to handle malformed lines and protocol violations in the history
+    /// (by default, malformed lines are skipped with a warning).
+    #[arg(short, long, value_enum, default_value_t = RepairPolicy::Skip)]
+    repair: RepairPolicy,
+
+    /// Data type the history operates on.
+    #[arg(short, long, value_enum, default_value_t = ModelKind::Register)]
+    model: ModelKind,
+
+    /// Clock-skew tolerance of real-time order, in :time units: an operation
+    /// only precedes another if it completed more than this before the other
+    /// was invoked.
+    #[arg(long, default_value_t = 0)]
+    epsilon: Timestamp,
+
+    /// Keep checking the other keys after one is found in violation, to
+    /// attribute all violations to the fault windows of the nemesis.
+    #[arg(long)]
+    all_keys: bool,
+
+    /// File of the node each client was bound to, as an EDN map from process
+    /// to node, or a vector of the nodes to bind processes to modulo their
+    /// count [default: the test's nodes, from its jepsen.log].
+    #[arg(long)]
+    client_nodes: Option<String>,
+
+    /// Greatest k accepted as k-atomic [default: any].
+    #[arg(long)]
+    max_k: Option<u64>,
+
+    /// Greatest staleness accepted as delta-atomic, in :time units [default:
+    /// any].
+    #[arg(long)]
+    max_delta: Option<Timestamp>,
+
+    /// Fail the check once it has run longer than this, in seconds [default:
+    /// no limit].
+    #[arg(long)]
+    time_limit: Option<u64>,
+
+    /// Fail the check once more possibilities than this are pending in its
+    /// search, which bounds its memory usage [default: no limit, except
+    /// 100000 for the whole-history search of multi-key histories].
+    #[arg(long)]
+    possib_limit: Option<usize>,
+
+    /// File to write a JSON report to: the verdict, the fault windows, and
+    /// latency and throughput statistics.
+    #[arg(long)]
+    report: Option<String>,
+
+    /// Width of the throughput buckets, in :time units.
+    #[arg(long, default_value_t = 1_000_000_000)]
+    rate_bucket: Timestamp,
+}
+
+/// Subcommands beside the default checking mode.
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Check and compare per-key verdicts against the stored Knossos results.
+    Diff {
+        /// Jepsen test store directory.
+        #[arg(short, long)]
+        test_dir: String,
+
+        /// Consistency level to check each key against, a key being valid if
+        /// it satisfies the level.
+        #[arg(short, long, default_value_t = Consistency::Linearizable)]
+        level: Consistency,
+
+        /// How to handle malformed lines and protocol violations in the
+        /// history (by default, malformed lines are skipped with a warning).
+        #[arg(short, long, value_enum, default_value_t = RepairPolicy::Skip)]
+        repair: RepairPolicy,
+    },
+
+    /// Generate a synthetic register history in the history.edn format.
+    Gen(GenArgs),
+
+    /// Simulate a replicated key-value store under faults, recording history.
+    Sim(SimArgs),
+
+    /// Benchmark parsing, timeline construction, and checking separately.
+    Bench(BenchArgs),
+}
+
+/// Arguments of the history generator.
+#[derive(clap::Args, Debug)]
+struct GenArgs {
+    /// Output directory to write history.edn into (stdout if not given).
     #[arg(short, long)]
-    test_dir: String,
+    out_dir: Option<String>,
+
+    /// Random seed.
+    #[arg(short, long, default_value_t = 0)]
+    seed: u64,
+
+    /// Number of concurrent clients.
+    #[arg(short, long, default_value_t = 5)]
+    clients: usize,
+
+    /// Number of distinct keys.
+    #[arg(short, long, default_value_t = 1)]
+    keys: usize,
+
+    /// Total number of operations.
+    #[arg(short = 'n', long, default_value_t = 1000)]
+    ops: usize,
+
+    /// Total number of distinct values.
+    #[arg(short, long, default_value_t = 10)]
+    value_range: u64,
+
+    /// Relative weights of :read, :write, and :cas operations.
+    #[arg(short, long, value_delimiter = ',', default_values_t = [1, 1, 1])]
+    mix: Vec<u64>,
+
+    /// Probability of an operation failing with :fail.
+    #[arg(long, default_value_t = 0.0)]
+    fail_rate: f64,
+
+    /// Probability of an operation failing with :info, having taken effect
+    /// or not at random (the checker allows for either).
+    #[arg(long, default_value_t = 0.0)]
+    info_rate: f64,
+
+    /// Anomaly to inject as KIND=COUNT, can be given multiple times.
+    #[arg(short, long, value_parser = parse_anomaly)]
+    anomaly: Vec<(AnomalyKind, usize)>,
+}
+
+/// Arguments of the replicated key-value store simulator.
+#[derive(clap::Args, Debug)]
+struct SimArgs {
+    /// Output directory to write history.edn into (stdout if not given).
+    #[arg(short, long)]
+    out_dir: Option<String>,
+
+    /// Random seed.
+    #[arg(short, long, default_value_t = 0)]
+    seed: u64,
+
+    /// Replication mode of the store.
+    #[arg(short = 'M', long, value_enum, default_value_t = ReplicationMode::Leader)]
+    mode: ReplicationMode,
+
+    /// Number of replica nodes.
+    #[arg(short = 'N', long, default_value_t = 5)]
+    nodes: usize,
+
+    /// Number of concurrent clients.
+    #[arg(short, long, default_value_t = 5)]
+    clients: usize,
+
+    /// Number of distinct keys.
+    #[arg(short, long, default_value_t = 1)]
+    keys: usize,
+
+    /// Total number of operations.
+    #[arg(short = 'n', long, default_value_t = 1000)]
+    ops: usize,
+
+    /// Total number of distinct values.
+    #[arg(short, long, default_value_t = 10)]
+    value_range: u64,
+
+    /// Relative weights of :read, :write, and :cas operations.
+    #[arg(short, long, value_delimiter = ',', default_values_t = [1, 1, 1])]
+    mix: Vec<u64>,
+
+    /// Faults to inject, picked randomly per fault window.
+    #[arg(short, long, value_enum, value_delimiter = ',')]
+    faults: Vec<FaultKind>,
+
+    /// Length of each fault window in msecs.
+    #[arg(short = 'w', long, default_value_t = 200)]
+    fault_window: u64,
+
+    /// Base one-way message latency in usecs.
+    #[arg(short, long, default_value_t = 1000)]
+    latency: u64,
+
+    /// Client-side operation timeout in msecs.
+    #[arg(short, long, default_value_t = 50)]
+    timeout: u64,
+}
+
+/// Arguments of the benchmark harness.
+#[derive(clap::Args, Debug)]
+struct BenchArgs {
+    /// Store directory whose latest runs are benchmarked.
+    #[arg(long, default_value = "store")]
+    store_dir: String,
+
+    /// Skip the stored histories, only benchmark the generated ones.
+    #[arg(long)]
+    no_store: bool,
+
+    /// Only benchmark histories whose names contain this string.
+    #[arg(short, long)]
+    filter: Option<String>,
+
+    /// Directory of saved benchmark results.
+    #[arg(long, default_value = "result/bench")]
+    results_dir: String,
+
+    /// Save results under this label (e.g., the git revision).
+    #[arg(short, long)]
+    label: Option<String>,
+
+    /// Compare results against those saved under this label.
+    #[arg(short, long)]
+    baseline: Option<String>,
+
+    /// Warm-up time per stage in msecs.
+    #[arg(long, default_value_t = 500)]
+    warmup: u64,
+
+    /// Target measuring time per stage in msecs.
+    #[arg(long, default_value_t = 3000)]
+    measure: u64,
+
+    /// Minimum number of samples per stage.
+    #[arg(long, default_value_t = 5)]
+    min_samples: usize,
+
+    /// Maximum number of samples per stage.
+    #[arg(long, default_value_t = 100)]
+    max_samples: usize,
+
+    /// Time limit of a single check in secs, beyond which the stage is skipped.
+    #[arg(long, default_value_t = 20)]
+    check_limit: u64,
+
+    /// Limit on pending possibilities of a single check (bounding memory),
+    /// beyond which the stage is skipped.
+    #[arg(long, default_value_t = 50000)]
+    check_possib_limit: usize,
+
+    /// Relative change of median regarded as noise, in percent.
+    #[arg(long, default_value_t = 5.0)]
+    noise: f64,
+}
+
+/// Parse an anomaly injection argument of the form KIND=COUNT.
+fn parse_anomaly(s: &str) -> Result<(AnomalyKind, usize), String> {
+    let (kind, count) = s.split_once('=').unwrap_or((s, "1"));
+    Ok((
+        AnomalyKind::from_str(kind, true)?,
+        count.parse::<usize>().map_err(|e| e.to_string())?,
+    ))
 }
 
 // Return codes.
@@ -36,94 +298,159 @@ const CHECK_ERROR: i32 = 101; // since panicking produces exit code 101
 fn main_inner() -> Result<bool, Box<dyn Error>> {
     let start_ts = Instant::now();
     let args = Args::parse();
-    eprintln!("Test directory: '{}'", args.test_dir);
 
-    let (events, max_client) = parse_history(Path::new(&args.test_dir))?;
-    if events.is_empty() {
-        return Err("input history is empty".into());
+    match args.command {
+        None => Ok(check_run(
+            &args.test_dir.unwrap_or_default(),
+            &args.levels,
+            args.repair,
+            args.model,
+            Attributing {
+                all_keys: args.all_keys,
+                client_nodes: args.client_nodes,
+            },
+            Relaxation {
+                epsilon: args.epsilon,
+                max_k: args.max_k,
+                max_delta: args.max_delta,
+            },
+            Limits {
+                time: args.time_limit.map(Duration::from_secs),
+                possib: args.possib_limit,
+            },
+            Reporting {
+                path: args.report,
+                rate_bucket: args.rate_bucket,
+            },
+            start_ts,
+        )?),
+        Some(Command::Diff {
+            test_dir,
+            level,
+            repair,
+        }) => Ok(diff_run(&test_dir, level, repair)?),
+        Some(Command::Gen(gen_args)) => gen_run(gen_args),
+        Some(Command::Sim(sim_args)) => sim_run(sim_args),
+        Some(Command::Bench(bench_args)) => bench_run(bench_args),
     }
+}
 
-    let timeline = Timeline::new(events, max_client)?;
-    print_timeline_stats(&timeline);
-
-    let check_ts = Instant::now();
-    let mut checker = Checker::new(timeline);
-    let level = checker.check()?;
-    let finish_ts = Instant::now();
+/// Bench mode: benchmark the pipeline stages, saving and comparing results.
+fn bench_run(args: BenchArgs) -> Result<bool, Box<dyn Error>> {
+    let cfg = BenchConfig {
+        warmup: Duration::from_millis(args.warmup),
+        measure: Duration::from_millis(args.measure),
+        min_samples: args.min_samples.max(1),
+        max_samples: args.max_samples.max(args.min_samples.max(1)),
+        check_limit: Duration::from_secs(args.check_limit),
+        check_possib_limit: args.check_possib_limit,
+        noise: args.noise / 100.0,
+    };
+    let results_path = |label: &str| Path::new(&args.results_dir).join(format!("{}.tsv", label));
 
-    println!(
-        "Checker result: {}",
-        if level == Consistency::Linearizable {
-            "== Linearizable, nice 👌".into()
-        } else {
-            format!(">= {:?} but < Linearizable 🤔", level)
+    // load baseline first to fail early if missing
+    let baseline = match &args.baseline {
+        Some(label) => {
+            let path = results_path(label);
+            Some(load_results(&path).map_err(|err| match err {
+                sop_checker::Error::Io(err) => format!("{}: {}", path.display(), err),
+                err => err.to_string(),
+            })?)
         }
-    );
-    println!("    based on this specific history,");
-    println!("    could just be a loose upper bound");
+        None => None,
+    };
 
-    println!(
-        "Time spent excluding I/O: {:.2} msecs",
-        (finish_ts.duration_since(check_ts).as_nanos() as f64) / 1_000_000.0
-    );
-    println!(
-        "Time spent in Rust total: {:.2} msecs",
-        (finish_ts.duration_since(start_ts).as_nanos() as f64) / 1_000_000.0
-    );
+    let store_dir = (!args.no_store).then(|| Path::new(&args.store_dir));
+    let results = run_benchmarks(&cfg, store_dir, args.filter.as_deref())?;
 
-    Ok(level == Consistency::Linearizable)
+    if let Some(label) = &args.label {
+        let path = results_path(label);
+        save_results(&path, &results)?;
+        println!("Bench results saved to '{}'", path.display());
+    }
+
+    match baseline {
+        Some(baseline) => Ok(compare_results(&cfg, &baseline, &results)),
+        None => Ok(true),
+    }
 }
 
-fn print_timeline_stats(timeline: &Timeline) {
-    println!(
-        "Parsed timeline: {} clients, {} keys, {} total ops",
-        timeline.num_clients(),
-        timeline.num_keys(),
-        timeline.stats_ops_sum,
-    );
+/// Gen mode: generate a synthetic history.
+fn gen_run(args: GenArgs) -> Result<bool, Box<dyn Error>> {
+    if args.mix.len() != 3 {
+        return Err("--mix expects 3 comma-separated weights".into());
+    }
+    let cfg = GenConfig {
+        seed: args.seed,
+        clients: args.clients,
+        keys: args.keys,
+        ops: args.ops,
+        value_range: args.value_range,
+        mix: [args.mix[0], args.mix[1], args.mix[2]],
+        fail_rate: args.fail_rate,
+        info_rate: args.info_rate,
+        anomalies: args.anomaly,
+    };
+    let generated = generate(&cfg)?;
 
-    println!("Call stats:  {:>5}  {:>5}  {:>5}", "call", "okay", "fail");
-    println!(
-        "       read  {:5}  {:5}  {:5}",
-        timeline.stats_ops_r[0], timeline.stats_ops_r[1], timeline.stats_ops_r[2]
-    );
-    println!(
-        "      write  {:5}  {:5}  {:5}",
-        timeline.stats_ops_w[0], timeline.stats_ops_w[1], timeline.stats_ops_w[2]
-    );
-    println!(
-        "        cas  {:5}  {:5}  {:5}",
-        timeline.stats_ops_cas[0], timeline.stats_ops_cas[1], timeline.stats_ops_cas[2]
-    );
+    let mut writer = history_writer(args.out_dir.as_deref())?;
+    for event in &generated.events {
+        writer.write_event(event)?;
+    }
+    writer.flush()?;
 
-    println!(
-        "Keyops dist.:  {:>4}  {:>4}  {:>4}  {:>4}",
-        "min", "med", "avg", "max"
-    );
-    println!(
-        "               {:4}  {:4}  {:4}  {:4}",
-        timeline.stats_key_min,
-        timeline.stats_key_med,
-        timeline.stats_key_avg,
-        timeline.stats_key_max
+    eprintln!(
+        "Generated {} events, injected anomalies: {:?}",
+        generated.events.len(),
+        generated.injected
     );
+    Ok(true)
+}
 
-    println!(
-        "Client dist.:  {:>4}  {:>4}  {:>4}  {:>4}",
-        "min", "med", "avg", "max"
-    );
-    println!(
-        "               {:4}  {:4}  {:4}  {:4}",
-        timeline.stats_cli_min,
-        timeline.stats_cli_med,
-        timeline.stats_cli_avg,
-        timeline.stats_cli_max
+/// Sim mode: simulate a replicated key-value store.
+fn sim_run(args: SimArgs) -> Result<bool, Box<dyn Error>> {
+    if args.mix.len() != 3 {
+        return Err("--mix expects 3 comma-separated weights".into());
+    }
+    let cfg = SimConfig {
+        seed: args.seed,
+        mode: args.mode,
+        nodes: args.nodes,
+        clients: args.clients,
+        keys: args.keys,
+        ops: args.ops,
+        value_range: args.value_range,
+        mix: [args.mix[0], args.mix[1], args.mix[2]],
+        faults: args.faults,
+        fault_window: args.fault_window * 1_000_000,
+        latency: args.latency * 1_000,
+        timeout: args.timeout * 1_000_000,
+    };
+    let simulated = simulate(&cfg)?;
+
+    let mut writer = history_writer(args.out_dir.as_deref())?;
+    for line in &simulated.lines {
+        match line {
+            SimLine::Client(event) => writer.write_event(event)?,
+            SimLine::Nemesis { time, f, value } => writer.write_nemesis(*time, f, value)?,
+        }
+    }
+    writer.flush()?;
+
+    eprintln!(
+        "Simulated {:?} mode, {} history lines, {} faults injected",
+        cfg.mode,
+        simulated.lines.len(),
+        simulated.num_faults
     );
+    Ok(true)
 }
 
 /// Error code returned should follows this convention:
-///   - 0: linearizability passed
-///   - 1: not linearizable, but may satisfy a weaker level (check output)
+///   - 0: strongest level checked passed (diff mode: both checkers agree)
+///   - 1: strongest level checked failed, but may satisfy a weaker level;
+///     in diff mode, the two checkers disagree on some key; in bench mode,
+///     some stage regressed against the baseline
 ///   - higher: error in checker, result unknown
 fn main() {
     match main_inner() {
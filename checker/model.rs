@@ -0,0 +1,348 @@
+//! Sequential specifications of the data types a history may operate on.
+//!
+//! The checker's search is generic over a `Model`, which decides whether an
+//! operation (with its observed results) is legal in a state, and what state
+//! it leaves behind. Supporting another data type means implementing `Model`
+//! for it and adding a `ModelKind` variant to choose it by; data types whose
+//! operations commute, or reveal their order, may be better served by a
+//! checker of their own (see `counter` and `append`).
+
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+use std::fmt;
+use std::hash::Hash;
+
+use clap::ValueEnum;
+
+use crate::counter::{counter_delta, counter_value};
+use crate::types::{KeyType, OpData, UniqueTag, ValType, Value};
+
+/// Data types with a model, for choosing one at run time.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum)]
+pub enum ModelKind {
+    /// Read/write register with compare-and-swap (`:read`, `:write`, `:cas`,
+    /// `:delete`).
+    #[default]
+    Register,
+    /// Grow-only set (`:add`, and `:read` of all elements).
+    Set,
+    /// FIFO queue (`:enqueue`, and `:dequeue` of the head element).
+    Queue,
+    /// Counter (`:add` or `:incr`, and `:read`), checked by interval
+    /// arithmetic instead of the search.
+    Counter,
+    /// List with appends (`:append`, and `:read` of the whole list), checked
+    /// by dependency-graph cycle detection instead of the search.
+    List,
+    /// Multi-key transactions (`:txn` of `[:r k v]` and `[:w k v]`), checked
+    /// by a search over all keys at once.
+    Txn,
+}
+
+/// Sequential specification of a data type.
+pub trait Model {
+    /// State of a single object (key) of the data type. Possibilities of the
+    /// search are deduplicated on it, so it should be kept small.
+    type State: Clone + Eq + Hash + fmt::Debug;
+
+    /// Do reads and writes carry the tags (`:tstag`) of the versions they
+    /// observe and create, so that the tag fast path applies?
+    const TAGGED: bool = false;
+
+    /// State of an object before any operation.
+    fn initial(&self) -> Self::State;
+
+    /// Apply an operation to the state, returning the new state if the
+    /// operation's observed results are legal in it.
+    fn step(&self, state: &Self::State, op: &OpData) -> Option<Self::State>;
+}
+
+/// Read/write register with read-modify-writes (see `RmwFunc`), initially
+/// nil. A delete leaves a tombstone, which reads as nil again, but carries the
+/// tag of the delete. A read (or RMW) reporting the tag of the write (or
+/// delete) it observed must observe exactly that one.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Register;
+
+/// State of a register: its value, its version, i.e., the number of
+/// modifications since it was last deleted (or never written), and the tag of
+/// the write of the value, if any. A tombstone keeps the version and tag of
+/// the delete, the delete counting as a modification, which tells it apart
+/// from a register never written.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Default)]
+pub struct RegState {
+    pub val: ValType,
+    pub version: u64,
+    pub tag: Option<UniqueTag>,
+    pub tombstone: bool,
+}
+
+impl RegState {
+    /// State after a modification writing the value.
+    fn modified(&self, val: &ValType, tag: Option<UniqueTag>) -> Self {
+        RegState {
+            val: val.clone(),
+            version: self.live_version() + 1,
+            tag,
+            tombstone: false,
+        }
+    }
+
+    /// Tombstone left by a delete.
+    fn deleted(&self, tag: UniqueTag) -> Self {
+        RegState {
+            val: Value::Nil,
+            version: self.live_version() + 1,
+            tag: Some(tag),
+            tombstone: true,
+        }
+    }
+
+    /// Number of modifications since the register was last deleted.
+    fn live_version(&self) -> u64 {
+        if self.tombstone { 0 } else { self.version }
+    }
+
+    /// Is the state written by the tagged write, if a tag is given?
+    fn has_tag(&self, tag: Option<UniqueTag>) -> bool {
+        tag.is_none_or(|tag| self.tag == Some(tag))
+    }
+}
+
+impl Model for Register {
+    type State = RegState;
+
+    const TAGGED: bool = true;
+
+    fn initial(&self) -> RegState {
+        RegState::default()
+    }
+
+    fn step(&self, state: &RegState, op: &OpData) -> Option<RegState> {
+        match op {
+            OpData::Read { val, tag, .. } => {
+                (*val == state.val && state.has_tag(*tag)).then(|| state.clone())
+            }
+            OpData::Write { val, tag, .. } => Some(state.modified(val, Some(*tag))),
+            OpData::Rmw {
+                func,
+                rval,
+                rtag,
+                wval,
+                wtag,
+                ..
+            } => (func.legal(&state.val, state.live_version(), rval) && state.has_tag(*rtag))
+                .then(|| state.modified(wval, *wtag)),
+            OpData::Delete { tag, .. } => Some(state.deleted(*tag)),
+            OpData::Add { .. }
+            | OpData::Enqueue { .. }
+            | OpData::Dequeue { .. }
+            | OpData::Append { .. }
+            | OpData::Txn { .. } => None,
+        }
+    }
+}
+
+/// Grow-only set, initially empty. A read observes all elements, given as a
+/// set or a list; a nil read counts as empty. Adds are always legal and
+/// commute, so only reads constrain the order: each must observe exactly the
+/// adds placed before it.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct GrowSet;
+
+impl Model for GrowSet {
+    type State = BTreeSet<ValType>;
+
+    fn initial(&self) -> Self::State {
+        BTreeSet::new()
+    }
+
+    fn step(&self, state: &Self::State, op: &OpData) -> Option<Self::State> {
+        match op {
+            OpData::Add { val, .. } => {
+                let mut new_state = state.clone();
+                new_state.insert(val.clone());
+                Some(new_state)
+            }
+            OpData::Read { val, .. } => {
+                let legal = match val {
+                    Value::Nil => state.is_empty(),
+                    Value::Set(elems) => **elems == *state,
+                    // duplicates are illegal
+                    Value::List(elems) => {
+                        elems.len() == state.len()
+                            && elems.iter().cloned().collect::<BTreeSet<_>>() == *state
+                    }
+                    _ => false,
+                };
+                legal.then(|| state.clone())
+            }
+            OpData::Write { .. }
+            | OpData::Rmw { .. }
+            | OpData::Enqueue { .. }
+            | OpData::Dequeue { .. }
+            | OpData::Append { .. }
+            | OpData::Delete { .. }
+            | OpData::Txn { .. } => None,
+        }
+    }
+}
+
+/// FIFO queue, initially empty. A dequeue finding the queue empty returns nil.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Fifo;
+
+impl Model for Fifo {
+    type State = VecDeque<ValType>;
+
+    fn initial(&self) -> Self::State {
+        VecDeque::new()
+    }
+
+    fn step(&self, state: &Self::State, op: &OpData) -> Option<Self::State> {
+        match op {
+            OpData::Enqueue { val, .. } => {
+                let mut new_state = state.clone();
+                new_state.push_back(val.clone());
+                Some(new_state)
+            }
+            OpData::Dequeue { val, .. } => match (state.front(), val) {
+                (None, Value::Nil) => Some(state.clone()),
+                (Some(head), val) if head == val => {
+                    let mut new_state = state.clone();
+                    new_state.pop_front();
+                    Some(new_state)
+                }
+                _ => None,
+            },
+            OpData::Read { .. }
+            | OpData::Write { .. }
+            | OpData::Rmw { .. }
+            | OpData::Add { .. }
+            | OpData::Append { .. }
+            | OpData::Delete { .. }
+            | OpData::Txn { .. } => None,
+        }
+    }
+}
+
+/// Counter, initially 0. An add with a nil delta increments by 1, and a nil
+/// read observes 0.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Counter;
+
+impl Model for Counter {
+    type State = i64;
+
+    fn initial(&self) -> i64 {
+        0
+    }
+
+    fn step(&self, state: &i64, op: &OpData) -> Option<i64> {
+        match op {
+            OpData::Add { val, .. } => state.checked_add(counter_delta(val)?),
+            OpData::Read { val, .. } => (counter_value(val)? == *state).then_some(*state),
+            OpData::Write { .. }
+            | OpData::Rmw { .. }
+            | OpData::Enqueue { .. }
+            | OpData::Dequeue { .. }
+            | OpData::Append { .. }
+            | OpData::Delete { .. }
+            | OpData::Txn { .. } => None,
+        }
+    }
+}
+
+/// List of appended elements, initially empty. A read observes the whole
+/// list; a nil read counts as empty.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ListAppend;
+
+impl Model for ListAppend {
+    type State = Vec<ValType>;
+
+    fn initial(&self) -> Self::State {
+        vec![]
+    }
+
+    fn step(&self, state: &Self::State, op: &OpData) -> Option<Self::State> {
+        match op {
+            OpData::Append { val, .. } => {
+                let mut new_state = state.clone();
+                new_state.push(val.clone());
+                Some(new_state)
+            }
+            OpData::Read { val, .. } => {
+                let legal = match val {
+                    Value::Nil => state.is_empty(),
+                    Value::List(elems) => **elems == **state,
+                    _ => false,
+                };
+                legal.then(|| state.clone())
+            }
+            OpData::Write { .. }
+            | OpData::Rmw { .. }
+            | OpData::Add { .. }
+            | OpData::Enqueue { .. }
+            | OpData::Dequeue { .. }
+            | OpData::Delete { .. }
+            | OpData::Txn { .. } => None,
+        }
+    }
+}
+
+/// Model of a whole multi-key history, with an independent object of the
+/// inner model per key. Keys not operated on yet are left out of the state.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Keyed<'a, M>(pub &'a M);
+
+impl<M: Model> Model for Keyed<'_, M> {
+    type State = BTreeMap<KeyType, M::State>;
+
+    fn initial(&self) -> Self::State {
+        BTreeMap::new()
+    }
+
+    fn step(&self, state: &Self::State, op: &OpData) -> Option<Self::State> {
+        let key = op.key();
+        let new_obj = match state.get(&key) {
+            Some(obj) => self.0.step(obj, op)?,
+            None => self.0.step(&self.0.initial(), op)?,
+        };
+        let mut new_state = state.clone();
+        new_state.insert(key, new_obj);
+        Some(new_state)
+    }
+}
+
+/// Model relaxed for k-atomicity: a read-only operation may observe any of
+/// the last `k` states, i.e., those after each of the last `k` modifications
+/// (or the initial state among them), instead of only the latest one. With
+/// `k` of 1, this is the inner model itself.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct KAtomic<'a, M> {
+    pub model: &'a M,
+    pub k: usize,
+}
+
+impl<M: Model> Model for KAtomic<'_, M> {
+    /// The last `k` states, latest last.
+    type State = Vec<M::State>;
+
+    fn initial(&self) -> Self::State {
+        vec![self.model.initial()]
+    }
+
+    fn step(&self, state: &Self::State, op: &OpData) -> Option<Self::State> {
+        if op.read_only() {
+            return state
+                .iter()
+                .any(|obj| self.model.step(obj, op).is_some())
+                .then(|| state.clone());
+        }
+        let new_obj = self.model.step(state.last().unwrap(), op)?;
+        let skip = (state.len() + 1).saturating_sub(self.k);
+        let mut new_state: Vec<_> = state.iter().skip(skip).cloned().collect();
+        new_state.push(new_obj);
+        Some(new_state)
+    }
+}
@@ -0,0 +1,302 @@
+//! Fault windows from the nemesis events of a history.
+//!
+//! A Jepsen nemesis logs each fault as a `:start` invocation and completion
+//! (the latter with e.g. the `:isolated` partition map as `:value`), then
+//! likewise a `:stop`. The fault is taken as active from the first of its
+//! `:start` events to the last of its `:stop` ones, splitting the history
+//! into periods before, during, and after each fault, so that violations can
+//! be attributed to the fault active at the time.
+
+use std::cmp;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufRead};
+use std::path::Path;
+
+use crate::edn::Edn;
+use crate::error::{Error, ParseErrorKind, Result};
+use crate::store::{HISTORY_FILE, split_segments};
+use crate::types::Timestamp;
+
+/// Fault injected by the nemesis.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Fault {
+    /// Network partition, each node mapped to the nodes it cannot reach.
+    Isolated(BTreeMap<String, BTreeSet<String>>),
+    /// Any other fault, by its recorded `:value`.
+    Other(String),
+}
+
+impl fmt::Display for Fault {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Fault::Isolated(grudge) => {
+                write!(f, "partition")?;
+                for (i, side) in sides(grudge).iter().enumerate() {
+                    write!(f, "{}{}", if i == 0 { " " } else { " | " }, side.join(" "))?;
+                }
+                Ok(())
+            }
+            Fault::Other(value) => write!(f, "{}", value),
+        }
+    }
+}
+
+/// Sides of a partition: the nodes cut off from the same others.
+fn sides(grudge: &BTreeMap<String, BTreeSet<String>>) -> Vec<Vec<&str>> {
+    let mut sides: BTreeMap<&BTreeSet<String>, Vec<&str>> = BTreeMap::new();
+    for (node, others) in grudge {
+        sides.entry(others).or_default().push(node);
+    }
+    sides.into_values().collect()
+}
+
+/// Side of a partition a node is on, by its share of the nodes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Side {
+    Minority,
+    Majority,
+    Half,
+}
+
+impl fmt::Display for Side {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Side::Minority => write!(f, "minority-side"),
+            Side::Majority => write!(f, "majority-side"),
+            Side::Half => write!(f, "half-side"),
+        }
+    }
+}
+
+impl Fault {
+    /// Side of the partition the node is on, if the fault is one and the node
+    /// takes part in it.
+    pub fn side_of(&self, node: &str) -> Option<Side> {
+        let Fault::Isolated(grudge) = self else {
+            return None;
+        };
+        let side = sides(grudge)
+            .into_iter()
+            .find(|side| side.contains(&node))?;
+        Some(match (2 * side.len()).cmp(&grudge.len()) {
+            cmp::Ordering::Less => Side::Minority,
+            cmp::Ordering::Greater => Side::Majority,
+            cmp::Ordering::Equal => Side::Half,
+        })
+    }
+
+    /// Interpret the `:value` of a `:start` completion.
+    fn from_value(s: &str) -> Self {
+        let name = |edn: &Edn| match edn {
+            Edn::Str(s) | Edn::Keyword(s) | Edn::Symbol(s) => Some(s.clone()),
+            Edn::Int(i) => Some(i.to_string()),
+            _ => None,
+        };
+        if let Ok(Edn::Vector(items)) = Edn::parse(s)
+            && let [Edn::Keyword(kind), Edn::Map(pairs)] = items.as_slice()
+            && kind == "isolated"
+        {
+            let grudge: Option<BTreeMap<_, _>> = pairs
+                .iter()
+                .map(|(node, others)| match others {
+                    Edn::Set(others) | Edn::Vector(others) | Edn::List(others) => {
+                        Some((name(node)?, others.iter().map(name).collect::<Option<_>>()?))
+                    }
+                    _ => None,
+                })
+                .collect();
+            if let Some(grudge) = grudge {
+                return Fault::Isolated(grudge);
+            }
+        }
+        Fault::Other(s.to_string())
+    }
+}
+
+/// Time window over which a fault was active.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FaultWindow {
+    pub start: Timestamp,
+    /// End of the window, or `None` if the fault was never stopped.
+    pub stop: Option<Timestamp>,
+    pub fault: Fault,
+}
+
+/// Period of a history relative to its faults (numbered from 1).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Period {
+    Before,
+    During(usize),
+    /// After the fault, and before the next one if any.
+    After(usize),
+}
+
+impl fmt::Display for Period {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Period::Before => write!(f, "before faults"),
+            Period::During(nth) => write!(f, "during fault {}", nth),
+            Period::After(nth) => write!(f, "after fault {}", nth),
+        }
+    }
+}
+
+/// Periods of a history with the given faults, in order.
+pub fn periods(windows: &[FaultWindow]) -> Vec<Period> {
+    let mut periods = vec![Period::Before];
+    for nth in 1..=windows.len() {
+        periods.push(Period::During(nth));
+        periods.push(Period::After(nth));
+    }
+    periods
+}
+
+/// Period of the given time, fault windows including both of their ends.
+pub fn period_at(windows: &[FaultWindow], time: Timestamp) -> Period {
+    let started = windows.partition_point(|window| window.start <= time);
+    match started.checked_sub(1).map(|idx| &windows[idx]) {
+        None => Period::Before,
+        Some(window) if window.stop.is_none_or(|stop| time <= stop) => Period::During(started),
+        Some(_) => Period::After(started),
+    }
+}
+
+/// Reads the fault windows out of the nemesis `:start` and `:stop` events of
+/// the history file, in order. Other nemesis events are ignored, and so are
+/// client ones.
+pub fn parse_faults(test_dir: &Path) -> Result<Vec<FaultWindow>> {
+    let file = File::open(test_dir.join(HISTORY_FILE))?;
+    let reader = io::BufReader::new(file);
+
+    let mut windows: Vec<FaultWindow> = vec![];
+    for (lineno, line) in reader.lines().enumerate() {
+        let line = line?;
+        if !line.contains(":nemesis") {
+            continue;
+        }
+        let line = line.trim();
+        let line = line.strip_prefix('{').unwrap_or(line);
+        let line = line.strip_suffix('}').unwrap_or(line);
+
+        let mut nemesis = false;
+        let (mut time, mut f, mut value) = (None, None, None);
+        for seg in split_segments(line) {
+            match seg.split_once(' ') {
+                Some((":process", stuff)) => nemesis = stuff.trim() == ":nemesis",
+                Some((":time", stuff)) => time = Some(stuff.trim()),
+                Some((":f", stuff)) => f = Some(stuff.trim()),
+                Some((":value", stuff)) => value = Some(stuff.trim()),
+                _ => {}
+            }
+        }
+        if !nemesis {
+            continue;
+        }
+        let time = time.ok_or(Error::Parse {
+            line: lineno + 1,
+            kind: ParseErrorKind::MissingFields,
+        })?;
+        let time = time.parse::<Timestamp>().map_err(|_| Error::Parse {
+            line: lineno + 1,
+            kind: ParseErrorKind::InvalidField {
+                field: ":time".to_string(),
+                value: time.to_string(),
+            },
+        })?;
+        let value = value.filter(|&value| value != "nil");
+
+        let open = windows.last_mut().filter(|window| window.stop.is_none());
+        match (f, open) {
+            // the completion of a start tells the fault
+            (Some(":start"), Some(window)) => {
+                if let Some(value) = value {
+                    window.fault = Fault::from_value(value);
+                }
+            }
+            (Some(":start"), None) => windows.push(FaultWindow {
+                start: time,
+                stop: None,
+                fault: Fault::from_value(value.unwrap_or("nil")),
+            }),
+            (Some(":stop"), Some(window)) => window.stop = Some(time),
+            // the completion of a stop extends the window to it
+            (Some(":stop"), None) => {
+                if let Some(window) = windows.last_mut()
+                    && window.stop.is_some()
+                {
+                    window.stop = Some(time);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    Ok(windows)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::fixture_dir;
+
+    /// The nemesis `:start` and `:stop` events of a history make fault
+    /// windows, from the first start to the last stop, told by the `:value`
+    /// of the start completion; a window never stopped is left open.
+    #[test]
+    fn nemesis_events_make_fault_windows() {
+        let history = r#"{:index 0, :time 10, :type :invoke, :process 0, :f :write, :value [0 1]}
+{:index 1, :time 20, :type :ok, :process 0, :f :write, :value [0 1]}
+{:index 2, :time 30, :type :info, :process :nemesis, :f :start, :value nil}
+{:index 3, :time 31, :type :info, :process :nemesis, :f :start, :value [:isolated {"n1" #{"n2" "n3"}, "n2" #{"n1"}, "n3" #{"n1"}}]}
+{:index 4, :time 40, :type :invoke, :process 1, :f :write, :value [0 2]}
+{:index 5, :time 50, :type :ok, :process 1, :f :write, :value [0 2]}
+{:index 6, :time 80, :type :info, :process :nemesis, :f :stop, :value nil}
+{:index 7, :time 81, :type :info, :process :nemesis, :f :stop, :value :network-healed}
+{:index 8, :time 90, :type :info, :process :nemesis, :f :start, :value nil}
+{:index 9, :time 91, :type :info, :process :nemesis, :f :start, :value :killed}
+"#;
+        let dir = fixture_dir("faults", &[("history.edn", history)]);
+        let windows = parse_faults(&dir).expect("cannot parse faults");
+        let grudge = BTreeMap::from([
+            (
+                "n1".to_string(),
+                BTreeSet::from(["n2".to_string(), "n3".to_string()]),
+            ),
+            ("n2".to_string(), BTreeSet::from(["n1".to_string()])),
+            ("n3".to_string(), BTreeSet::from(["n1".to_string()])),
+        ]);
+        assert_eq!(
+            windows,
+            [
+                FaultWindow {
+                    start: 30,
+                    stop: Some(81),
+                    fault: Fault::Isolated(grudge),
+                },
+                FaultWindow {
+                    start: 90,
+                    stop: None,
+                    fault: Fault::Other(":killed".to_string()),
+                },
+            ]
+        );
+        assert_eq!(windows[0].fault.to_string(), "partition n2 n3 | n1");
+        assert_eq!(windows[0].fault.side_of("n1"), Some(Side::Minority));
+        assert_eq!(windows[0].fault.side_of("n2"), Some(Side::Majority));
+        assert_eq!(windows[0].fault.side_of("n4"), None);
+        assert_eq!(windows[1].fault.side_of("n1"), None);
+        assert_eq!(periods(&windows).len(), 5);
+        for (time, period) in [
+            (20, Period::Before),
+            (30, Period::During(1)),
+            (81, Period::During(1)),
+            (85, Period::After(1)),
+            (1000, Period::During(2)),
+        ] {
+            assert_eq!(period_at(&windows, time), period, "at {}", time);
+        }
+        let _ = std::fs::remove_dir_all(dir);
+    }
+}
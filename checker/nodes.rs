@@ -0,0 +1,189 @@
+//! Nodes the clients of a history were bound to.
+//!
+//! Jepsen binds each client process to a node of the test, by default the
+//! one at the process number modulo the node count in the test's `:nodes`.
+//! The nodes are read from the test config logged at the start of
+//! `jepsen.log`, unless given explicitly by a file.
+
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{self, BufRead};
+use std::path::Path;
+
+use crate::edn::Edn;
+use crate::error::{Error, Result};
+use crate::types::ClientId;
+
+/// Jepsen log file name, starting with the test config.
+pub const JEPSEN_LOG_FILE: &str = "jepsen.log";
+
+/// Node each client of a history was bound to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ClientNodes {
+    /// Explicit node of each client.
+    Map(BTreeMap<ClientId, String>),
+    /// Nodes of the test, a client bound to the one at its number modulo
+    /// their count.
+    Nodes(Vec<String>),
+}
+
+/// Node name out of an EDN form.
+fn node_name(edn: &Edn) -> Option<String> {
+    match edn {
+        Edn::Str(s) | Edn::Keyword(s) | Edn::Symbol(s) => Some(s.clone()),
+        _ => None,
+    }
+}
+
+/// Nodes out of an EDN vector (or list) of them.
+fn node_names(edn: &Edn) -> Option<Vec<String>> {
+    match edn {
+        Edn::Vector(nodes) | Edn::List(nodes) if !nodes.is_empty() => {
+            nodes.iter().map(node_name).collect()
+        }
+        _ => None,
+    }
+}
+
+impl ClientNodes {
+    /// Node the client was bound to, if known.
+    pub fn node_of(&self, client: ClientId) -> Option<&str> {
+        match self {
+            ClientNodes::Map(nodes) => nodes.get(&client).map(String::as_str),
+            ClientNodes::Nodes(nodes) => Some(&nodes[client % nodes.len()]),
+        }
+    }
+
+    /// Parse an EDN map from client process to node, or a vector of the
+    /// test's nodes.
+    pub fn parse(s: &str) -> Result<Self> {
+        let invalid = || Error::Config(format!("invalid client nodes: {}", s.trim()));
+        match Edn::parse(s)? {
+            Edn::Map(pairs) => pairs
+                .iter()
+                .map(|(client, node)| match client {
+                    Edn::Int(client) if *client >= 0 => {
+                        Some((*client as ClientId, node_name(node)?))
+                    }
+                    _ => None,
+                })
+                .collect::<Option<_>>()
+                .map(ClientNodes::Map)
+                .ok_or_else(invalid),
+            edn => node_names(&edn).map(ClientNodes::Nodes).ok_or_else(invalid),
+        }
+    }
+
+    /// Read the client nodes given by a file (see `parse()`).
+    pub fn read_file(path: &Path) -> Result<Self> {
+        Self::parse(&std::fs::read_to_string(path)?)
+    }
+
+    /// Read the test's nodes from the config logged in `jepsen.log` of the
+    /// store directory, `None` if there is no such log or it lacks them.
+    pub fn from_test_dir(test_dir: &Path) -> Result<Option<Self>> {
+        let file = match File::open(test_dir.join(JEPSEN_LOG_FILE)) {
+            Ok(file) => file,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(err) => return Err(err.into()),
+        };
+
+        // the config is pretty-printed after the "Running test:" log line,
+        // each key on a line of its own indented by one, with the vector of
+        // the ` :nodes` key up to the next key
+        let mut in_config = false;
+        let mut nodes: Option<String> = None;
+        for line in io::BufReader::new(file).lines() {
+            let line = line?;
+            if !in_config {
+                in_config = line.ends_with("Running test:");
+                continue;
+            }
+            let next_key = !line.starts_with(' ') || line.starts_with(" :");
+            match &mut nodes {
+                Some(_) if next_key => break,
+                Some(text) => text.push_str(&line),
+                None if !line.starts_with(' ') && !line.starts_with('{') => break,
+                None => {
+                    let key = line.trim_start_matches([' ', '{']);
+                    if let Some(rest) = key.strip_prefix(":nodes")
+                        && (rest.is_empty() || rest.starts_with(' '))
+                    {
+                        nodes = Some(rest.to_string());
+                    }
+                }
+            }
+        }
+        Ok(nodes
+            .and_then(|text| Edn::parse(&text).ok())
+            .and_then(|edn| node_names(&edn))
+            .map(ClientNodes::Nodes))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::fixture_dir;
+
+    /// Clients are bound to the test's nodes from the config in `jepsen.log`
+    /// by their process number modulo the node count, even above it, unless
+    /// a file maps them; a client it leaves out has no node, and a malformed
+    /// file is an error.
+    #[test]
+    fn client_nodes_come_from_config_or_file() {
+        let log = |nodes: &str| {
+            format!(
+                "\
+2024-05-01 12:00:00,000{{GMT}}\tINFO\t[jepsen test runner] jepsen.core: Running test:
+{{:concurrency 3,
+ :name \"register\",
+{} :ssh {{:username \"root\"}}}}
+2024-05-01 12:00:01,000{{GMT}}\tINFO\t[jepsen test runner] jepsen.core: Done
+",
+                nodes
+            )
+        };
+        let nodes = ClientNodes::Nodes(vec!["n1".to_string(), "n2".to_string(), "n3".to_string()]);
+        assert_eq!(nodes.node_of(1), Some("n2"));
+        assert_eq!(nodes.node_of(5), Some("n3"));
+
+        for (name, config, expected) in [
+            ("inline", " :nodes [\"n1\" \"n2\" \"n3\"],\n", Some(&nodes)),
+            (
+                "wrapped",
+                " :nodes\n [\"n1\"\n  \"n2\"\n  \"n3\"],\n",
+                Some(&nodes),
+            ),
+            ("missing", "", None),
+        ] {
+            let dir = fixture_dir(
+                &format!("nodes-{}", name),
+                &[(JEPSEN_LOG_FILE, &log(config))],
+            );
+            let found = ClientNodes::from_test_dir(&dir).expect("cannot read config");
+            assert_eq!(found.as_ref(), expected, "{}", name);
+            let _ = std::fs::remove_dir_all(dir);
+        }
+
+        let dir = fixture_dir(
+            "nodes",
+            &[
+                ("mapped.edn", "{5 \"n1\", 1 :n3}"),
+                ("malformed.edn", "{5 [\"n1\"]}"),
+                ("truncated.edn", "{5 \"n1\", 1"),
+            ],
+        );
+        assert_eq!(ClientNodes::from_test_dir(&dir).ok(), Some(None));
+        let mapped = ClientNodes::read_file(&dir.join("mapped.edn")).expect("cannot read nodes");
+        assert_eq!(mapped.node_of(5), Some("n1"));
+        assert_eq!(mapped.node_of(1), Some("n3"));
+        assert_eq!(mapped.node_of(2), None);
+        assert!(matches!(
+            ClientNodes::read_file(&dir.join("malformed.edn")),
+            Err(Error::Config(_))
+        ));
+        assert!(ClientNodes::read_file(&dir.join("truncated.edn")).is_err());
+        let _ = std::fs::remove_dir_all(dir);
+    }
+}
@@ -0,0 +1,2119 @@
+//! Randomized comparison of the checker against a brute-force oracle on small
+//! histories (at most `MAX_OPS` operations).
+//!
+//! The oracle enumerates every permutation of the completed operations of the
+//! whole history (with an object of the model per key) and accepts the
+//! history iff some permutation satisfies the level's ordering constraint and
+//! the data type's sequential semantics (`Model`). Each level the checker
+//! supports gets an entry in `LEVELS`. Indeterminate operations (from
+//! invocations that never completed) may be left out of the permutation.
+//!
+//! Beside the comparison, property tests hold the checker's other analyses
+//! (total queues, counter bounds, data anomalies, violation witnesses, and
+//! staleness bounds) to the oracle on the same random histories. Tests of
+//! parsing, repair, and the tooling live with their modules.
+
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+use std::fs;
+use std::sync::Arc;
+
+use crate::append::AppendChecker;
+use crate::check::{Checker, CkSpan};
+use crate::counter::{CounterChecker, counter_delta};
+use crate::generator::{AnomalyKind, GenConfig, Rng, generate};
+use crate::model::{Counter, Fifo, GrowSet, KAtomic, Keyed, ListAppend, Model, Register};
+use crate::queue::check_total_queue;
+use crate::repair::{Repair, RepairPolicy};
+use crate::rmw::RmwFunc;
+use crate::store::{fixture_dir, parse_history};
+use crate::tags::check_tags;
+use crate::txn::TxnChecker;
+use crate::types::{
+    ClientId, Consistency, Event, EventType, KeyType, Mop, OpData, OpSpan, Timeline, Timestamp,
+    UniqueTag, Value,
+};
+use crate::validate::validate_registers;
+
+/// Maximum number of operations in a generated history.
+const MAX_OPS: usize = 8;
+
+/// Number of random histories per harness run.
+const NUM_CASES: u64 = 400;
+
+/// Brute-force oracle of one level: may `b` be ordered after `a`?
+type OrderOk = fn(a: &OpSpan, b: &OpSpan) -> bool;
+
+/// Levels covered by the comparison, each with its ordering constraint,
+/// weakest first.
+const LEVELS: &[(Consistency, OrderOk)] = &[
+    (Consistency::Sequential, program_order_ok),
+    (Consistency::Linearizable, real_time_ok),
+];
+
+/// Program order: `b` may follow `a` unless both are from the same client and
+/// `b` was invoked earlier. Indeterminate operations are outside of their
+/// client's program order, as the checker puts them on queues of their own.
+fn program_order_ok(a: &OpSpan, b: &OpSpan) -> bool {
+    a.client != b.client || a.indeterminate || b.indeterminate || b.invoke > a.invoke
+}
+
+/// Real-time order: `b` cannot precede `a` if `a` finished before `b` invoked,
+/// so `b` may follow `a` unless `b` finished before `a` invoked.
+fn real_time_ok(a: &OpSpan, b: &OpSpan) -> bool {
+    b.finish >= a.invoke
+}
+
+/// Clock-skew tolerance of `skewed_real_time_ok()`, a few events' worth in the
+/// random histories.
+const EPSILON: Timestamp = 3;
+
+/// Real-time order relaxed by `EPSILON`, which leaves program order intact:
+/// `b` may follow `a` unless `b` finished more than `EPSILON` before `a`
+/// invoked, or precedes it on the same client.
+fn skewed_real_time_ok(a: &OpSpan, b: &OpSpan) -> bool {
+    b.finish.saturating_add(EPSILON) >= a.invoke && program_order_ok(a, b)
+}
+
+/// Depth-first enumeration of permutations, extending a prefix only while it
+/// stays legal; returns true if a legal permutation of all but possibly the
+/// indeterminate operations exists.
+fn permute<M: Model>(
+    model: &M,
+    spans: &[OpSpan],
+    order_ok: OrderOk,
+    placed: &mut Vec<usize>,
+    used: &mut [bool],
+    state: &M::State,
+) -> bool {
+    if spans
+        .iter()
+        .zip(used.iter())
+        .all(|(s, &u)| u || s.indeterminate)
+    {
+        return true;
+    }
+
+    for i in 0..spans.len() {
+        if used[i] || !placed.iter().all(|&p| order_ok(&spans[p], &spans[i])) {
+            continue;
+        }
+        let Some(new_state) = model.step(state, &spans[i].data) else {
+            continue;
+        };
+
+        used[i] = true;
+        placed.push(i);
+        let found = permute(model, spans, order_ok, placed, used, &new_state);
+        placed.pop();
+        used[i] = false;
+        if found {
+            return true;
+        }
+    }
+
+    false
+}
+
+/// Run the oracle on a history, over all keys at once.
+fn oracle<M: Model>(timeline: &Timeline, model: &M, order_ok: OrderOk) -> bool {
+    let spans: Vec<OpSpan> = timeline.queues.iter().flatten().cloned().collect();
+    let keyed = Keyed(model);
+    permute(
+        &keyed,
+        &spans,
+        order_ok,
+        &mut vec![],
+        &mut vec![false; spans.len()],
+        &keyed.initial(),
+    )
+}
+
+/// Generate a history of arbitrarily interleaved operations with random
+/// results over a small value domain, so that both verdicts are common. Every
+/// event gets a distinct timestamp. Some operations never complete, left to be
+/// closed as indeterminate.
+fn random_history(rng: &mut Rng) -> (Vec<Event>, ClientId) {
+    let num_clients = rng.between(1, 3) as usize;
+    let num_keys = rng.between(1, 2);
+    let num_ops = rng.between(1, MAX_OPS as u64) as usize;
+
+    let value = |rng: &mut Rng| Value::Int(rng.between(1, 3) as i64);
+    let mut events = vec![];
+    let mut flying: Vec<Option<OpData>> = vec![None; num_clients];
+    let mut invoked = 0;
+    let mut time = 0;
+
+    while invoked < num_ops || flying.iter().any(|f| f.is_some()) {
+        let client = rng.below(num_clients as u64) as usize;
+        time += 1;
+
+        if let Some(opdata) = flying[client].take() {
+            if rng.chance(0.05) {
+                continue;
+            }
+            let etype = if rng.chance(0.1) {
+                EventType::Fail
+            } else {
+                EventType::Okay
+            };
+            events.push(Event::new(events.len() as u64, time, etype, client, opdata));
+        } else if invoked < num_ops {
+            let key = Value::Int(rng.below(num_keys) as i64);
+            let opdata = match rng.below(3) {
+                0 => OpData::Read {
+                    key,
+                    val: if rng.chance(0.25) {
+                        Value::Nil
+                    } else {
+                        value(rng)
+                    },
+                    tag: None,
+                },
+                1 if rng.chance(0.2) => OpData::Delete { key, tag: 0 },
+                1 => OpData::Write {
+                    key,
+                    val: value(rng),
+                    tag: 0,
+                },
+                _ => {
+                    let func = RmwFunc::ALL[rng.below(RmwFunc::ALL.len() as u64) as usize];
+                    let int = |rng: &mut Rng| rng.between(1, 3) as i64;
+                    let (arg, rval, wval) = match func {
+                        RmwFunc::Cas | RmwFunc::Swap => (Value::Nil, value(rng), value(rng)),
+                        RmwFunc::FetchAdd => {
+                            let (delta, old) = (int(rng), int(rng));
+                            (Value::Int(delta), Value::Int(old), Value::Int(old + delta))
+                        }
+                        // compared against the version instead of the value
+                        RmwFunc::PutIfVersion => {
+                            (Value::Nil, Value::Int(rng.below(3) as i64), value(rng))
+                        }
+                        // from 0 on a nil register
+                        RmwFunc::IncrIf => {
+                            let old = rng.below(3) as i64;
+                            (Value::Nil, Value::Int(old), Value::Int(old + 1))
+                        }
+                    };
+                    OpData::Rmw {
+                        key,
+                        func,
+                        arg,
+                        rval,
+                        rtag: None,
+                        wval,
+                        wtag: None,
+                    }
+                }
+            };
+            events.push(Event::new(
+                events.len() as u64,
+                time,
+                EventType::Invoke,
+                client,
+                opdata.clone(),
+            ));
+            flying[client] = Some(opdata);
+            invoked += 1;
+        }
+    }
+
+    (events, num_clients - 1)
+}
+
+/// Generate a history of arbitrarily interleaved set adds and reads, each read
+/// returning a random subset of the elements added so far. Like in
+/// `random_history()`, some operations never complete.
+fn random_set_history(rng: &mut Rng) -> (Vec<Event>, ClientId) {
+    let num_clients = rng.between(1, 3) as usize;
+    let num_ops = rng.between(1, MAX_OPS as u64) as usize;
+
+    let mut events = vec![];
+    let mut flying: Vec<Option<OpData>> = vec![None; num_clients];
+    let mut added = BTreeSet::new();
+    let mut invoked = 0;
+    let mut time = 0;
+
+    while invoked < num_ops || flying.iter().any(|f| f.is_some()) {
+        let client = rng.below(num_clients as u64) as usize;
+        time += 1;
+
+        if let Some(mut opdata) = flying[client].take() {
+            if rng.chance(0.05) {
+                continue;
+            }
+            if let OpData::Read { val, .. } = &mut opdata {
+                let seen = added.iter().filter(|_| rng.chance(0.75)).cloned();
+                *val = Value::Set(Arc::new(seen.collect()));
+            }
+            events.push(Event::new(
+                events.len() as u64,
+                time,
+                EventType::Okay,
+                client,
+                opdata,
+            ));
+        } else if invoked < num_ops {
+            let key = Value::Int(0);
+            let opdata = if rng.chance(0.5) {
+                let val = Value::Int(rng.between(1, 3) as i64);
+                added.insert(val.clone());
+                OpData::Add { key, val }
+            } else {
+                OpData::Read {
+                    key,
+                    val: Value::Nil,
+                    tag: None,
+                }
+            };
+            events.push(Event::new(
+                events.len() as u64,
+                time,
+                EventType::Invoke,
+                client,
+                opdata.clone(),
+            ));
+            flying[client] = Some(opdata);
+            invoked += 1;
+        }
+    }
+
+    (events, num_clients - 1)
+}
+
+/// Generate a random small queue history. Dequeues mostly return the head of
+/// the queue at completion, and sometimes an arbitrary message (or nil).
+fn random_queue_history(rng: &mut Rng) -> (Vec<Event>, ClientId) {
+    let num_clients = rng.between(1, 3) as usize;
+    let num_ops = rng.between(1, MAX_OPS as u64) as usize;
+
+    let mut events = vec![];
+    let mut flying: Vec<Option<OpData>> = vec![None; num_clients];
+    let mut queue = VecDeque::new();
+    let mut sent = vec![Value::Nil];
+    let mut invoked = 0;
+    let mut time = 0;
+
+    while invoked < num_ops || flying.iter().any(|f| f.is_some()) {
+        let client = rng.below(num_clients as u64) as usize;
+        time += 1;
+
+        if let Some(mut opdata) = flying[client].take() {
+            if rng.chance(0.05) {
+                continue;
+            }
+            match &mut opdata {
+                OpData::Enqueue { val, .. } => queue.push_back(val.clone()),
+                OpData::Dequeue { val, .. } => {
+                    let head = queue.pop_front().unwrap_or(Value::Nil);
+                    *val = if rng.chance(0.8) {
+                        head
+                    } else {
+                        sent[rng.below(sent.len() as u64) as usize].clone()
+                    };
+                }
+                _ => unreachable!(),
+            }
+            events.push(Event::new(
+                events.len() as u64,
+                time,
+                EventType::Okay,
+                client,
+                opdata,
+            ));
+        } else if invoked < num_ops {
+            let key = Value::Int(0);
+            let opdata = if rng.chance(0.5) {
+                let val = Value::Int(rng.between(1, 4) as i64);
+                sent.push(val.clone());
+                OpData::Enqueue { key, val }
+            } else {
+                OpData::Dequeue {
+                    key,
+                    val: Value::Nil,
+                }
+            };
+            events.push(Event::new(
+                events.len() as u64,
+                time,
+                EventType::Invoke,
+                client,
+                opdata.clone(),
+            ));
+            flying[client] = Some(opdata);
+            invoked += 1;
+        }
+    }
+
+    (events, num_clients - 1)
+}
+
+/// Generate a random small counter history. Reads mostly return the value at
+/// completion, and sometimes one off by one.
+fn random_counter_history(rng: &mut Rng) -> (Vec<Event>, ClientId) {
+    let num_clients = rng.between(1, 3) as usize;
+    let num_ops = rng.between(1, MAX_OPS as u64) as usize;
+
+    let mut events = vec![];
+    let mut flying: Vec<Option<OpData>> = vec![None; num_clients];
+    let mut counter = 0;
+    let mut invoked = 0;
+    let mut time = 0;
+
+    while invoked < num_ops || flying.iter().any(|f| f.is_some()) {
+        let client = rng.below(num_clients as u64) as usize;
+        time += 1;
+
+        if let Some(mut opdata) = flying[client].take() {
+            if rng.chance(0.05) {
+                continue;
+            }
+            match &mut opdata {
+                OpData::Add { val, .. } => counter += counter_delta(val).unwrap(),
+                OpData::Read { val, .. } => {
+                    let off = [-1, 0, 0, 0, 1][rng.below(5) as usize];
+                    *val = Value::Int(counter + off);
+                }
+                _ => unreachable!(),
+            }
+            events.push(Event::new(
+                events.len() as u64,
+                time,
+                EventType::Okay,
+                client,
+                opdata,
+            ));
+        } else if invoked < num_ops {
+            let key = Value::Int(0);
+            let opdata = if rng.chance(0.5) {
+                let val = match rng.below(4) {
+                    0 => Value::Nil,
+                    1 => Value::Int(-1),
+                    n => Value::Int(n as i64),
+                };
+                OpData::Add { key, val }
+            } else {
+                OpData::Read {
+                    key,
+                    val: Value::Nil,
+                    tag: None,
+                }
+            };
+            events.push(Event::new(
+                events.len() as u64,
+                time,
+                EventType::Invoke,
+                client,
+                opdata.clone(),
+            ));
+            flying[client] = Some(opdata);
+            invoked += 1;
+        }
+    }
+
+    (events, num_clients - 1)
+}
+
+/// Generate a random small list-append history of unique elements. Reads
+/// mostly return the list at completion, and sometimes a stale prefix of it or
+/// one with the last two elements swapped.
+fn random_list_history(rng: &mut Rng) -> (Vec<Event>, ClientId) {
+    let num_clients = rng.between(1, 3) as usize;
+    let num_ops = rng.between(1, MAX_OPS as u64) as usize;
+
+    let mut events = vec![];
+    let mut flying: Vec<Option<OpData>> = vec![None; num_clients];
+    let mut list = vec![];
+    let mut invoked = 0;
+    let mut time = 0;
+
+    while invoked < num_ops || flying.iter().any(|f| f.is_some()) {
+        let client = rng.below(num_clients as u64) as usize;
+        time += 1;
+
+        if let Some(mut opdata) = flying[client].take() {
+            if rng.chance(0.05) {
+                continue;
+            }
+            match &mut opdata {
+                OpData::Append { val, .. } => list.push(val.clone()),
+                OpData::Read { val, .. } => {
+                    let mut seen = list.clone();
+                    match rng.below(8) {
+                        0 => {
+                            seen.pop();
+                        }
+                        1 if seen.len() >= 2 => {
+                            let n = seen.len();
+                            seen.swap(n - 2, n - 1);
+                        }
+                        _ => {}
+                    }
+                    *val = Value::List(seen.into());
+                }
+                _ => unreachable!(),
+            }
+            events.push(Event::new(
+                events.len() as u64,
+                time,
+                EventType::Okay,
+                client,
+                opdata,
+            ));
+        } else if invoked < num_ops {
+            let key = Value::Int(0);
+            let opdata = if rng.chance(0.5) {
+                OpData::Append {
+                    key,
+                    val: Value::Int(invoked as i64),
+                }
+            } else {
+                OpData::Read {
+                    key,
+                    val: Value::Nil,
+                    tag: None,
+                }
+            };
+            events.push(Event::new(
+                events.len() as u64,
+                time,
+                EventType::Invoke,
+                client,
+                opdata.clone(),
+            ));
+            flying[client] = Some(opdata);
+            invoked += 1;
+        }
+    }
+
+    (events, num_clients - 1)
+}
+
+/// Generate a small history with the synthetic generator, possibly with an
+/// injected anomaly.
+fn generated_history(rng: &mut Rng) -> (Vec<Event>, ClientId) {
+    let anomaly = match rng.below(4) {
+        0 => Some(AnomalyKind::StaleRead),
+        1 => Some(AnomalyKind::LostWrite),
+        2 => Some(AnomalyKind::ReorderedWrites),
+        _ => None,
+    };
+    let cfg = GenConfig {
+        seed: rng.next_u64(),
+        clients: rng.between(1, 3) as usize,
+        keys: 1,
+        ops: MAX_OPS,
+        value_range: 3,
+        fail_rate: 0.0,
+        info_rate: 0.0,
+        anomalies: anomaly.map(|kind| (kind, 1)).into_iter().collect(),
+        ..GenConfig::default()
+    };
+
+    let generated = generate(&cfg).expect("generator failed");
+    let max_client = generated.events.iter().map(|e| e.client).max().unwrap_or(0);
+    (generated.events, max_client)
+}
+
+/// Compare the checker against the oracle of every level on histories of the
+/// model from the given source. Returns the number of (accepted, rejected)
+/// histories by the strongest level's oracle.
+fn compare_with_oracle<M: Model + Copy>(
+    seed: u64,
+    source: fn(&mut Rng) -> (Vec<Event>, ClientId),
+    model: M,
+) -> (u64, u64) {
+    let mut rng = Rng::new(seed);
+    let (mut accepted, mut rejected) = (0, 0);
+
+    for case in 0..NUM_CASES {
+        let (events, max_client) = source(&mut rng);
+        let history: Vec<String> = events
+            .iter()
+            .map(|e| format!("{:?} {} @{} {}", e.etype, e.client, e.time, e.opdata))
+            .collect();
+
+        let mut repair = Repair::new(RepairPolicy::Close);
+        let timeline =
+            Timeline::with_repair(events, max_client, &mut repair).expect("invalid history");
+        let expected: Vec<bool> = LEVELS
+            .iter()
+            .map(|&(_, order_ok)| oracle(&timeline, &model, order_ok))
+            .collect();
+
+        let levels: Vec<Consistency> = LEVELS.iter().map(|&(level, _)| level).collect();
+        let mut checker = Checker::with_model(timeline, model);
+        checker.set_quiet();
+        checker.set_levels(&levels);
+        let result = checker.check().expect("checker failed");
+        for (&(level, _), &valid) in LEVELS.iter().zip(&expected) {
+            assert_eq!(
+                result >= level,
+                valid,
+                "seed {} case {}: checker says {:?}, oracle says {:?} {}:\n  {}",
+                seed,
+                case,
+                result,
+                level,
+                if valid { "holds" } else { "violated" },
+                history.join("\n  ")
+            );
+        }
+
+        if *expected.last().unwrap() {
+            accepted += 1;
+        } else {
+            rejected += 1;
+        }
+    }
+
+    (accepted, rejected)
+}
+
+/// Compare the checker against the oracle on histories from the source over
+/// the given number of seeds, each of which must give some histories the
+/// strongest level accepts, and if `rejected_too`, some it rejects.
+fn assert_matches_oracle<M: Model + Copy>(
+    seeds: u64,
+    source: fn(&mut Rng) -> (Vec<Event>, ClientId),
+    model: M,
+    rejected_too: bool,
+) {
+    for seed in 0..seeds {
+        let (accepted, rejected) = compare_with_oracle(seed, source, model);
+        assert!(accepted > 0, "seed {}: no accepted case", seed);
+        assert!(
+            !rejected_too || rejected > 0,
+            "seed {}: no rejected case",
+            seed
+        );
+    }
+}
+
+/// Generated histories whose `:info` operations may have taken effect are
+/// linearizable as those are kept as indeterminate, though not always when
+/// they complete with `:fail` instead.
+#[test]
+fn generated_info_ops_are_indeterminate() {
+    let mut rng = Rng::new(0);
+    let mut observed = 0;
+
+    for case in 0..NUM_CASES / 4 {
+        let cfg = GenConfig {
+            seed: rng.next_u64(),
+            clients: rng.between(1, 3) as usize,
+            ops: MAX_OPS,
+            value_range: 3,
+            info_rate: 0.3,
+            ..GenConfig::default()
+        };
+        let generated = generate(&cfg).expect("generator failed");
+        let max_client = generated.events.iter().map(|e| e.client).max().unwrap_or(0);
+
+        let mut verdicts = vec![];
+        for failed in [false, true] {
+            let mut events = generated.events.clone();
+            if failed {
+                for event in events.iter_mut().filter(|e| e.etype == EventType::Error) {
+                    event.etype = EventType::Fail;
+                }
+            }
+            let timeline = Timeline::new(events, max_client).expect("invalid history");
+            let expected = oracle(&timeline, &Register, real_time_ok);
+            let mut checker = Checker::new(timeline);
+            checker.set_quiet();
+            let holds = checker.check().expect("checker failed") == Consistency::Linearizable;
+            assert_eq!(holds, expected, "case {}: failed {}", case, failed);
+            verdicts.push(holds);
+        }
+        assert!(verdicts[0], "case {}: :info ops not indeterminate", case);
+        if !verdicts[1] {
+            observed += 1;
+        }
+    }
+    assert!(observed > 0, "no :info ops observed taking effect");
+}
+
+#[test]
+fn random_histories_match_oracle() {
+    assert_matches_oracle(4, random_history, Register, true);
+}
+
+#[test]
+fn generated_histories_match_oracle() {
+    assert_matches_oracle(4, generated_history, Register, false);
+}
+
+#[test]
+fn random_set_histories_match_oracle() {
+    assert_matches_oracle(4, random_set_history, GrowSet, true);
+}
+
+#[test]
+fn random_queue_histories_match_oracle() {
+    assert_matches_oracle(4, random_queue_history, Fifo, true);
+}
+
+/// Linearizable queue histories deliver no message twice, unexpectedly, or
+/// out of producer order (but may leave messages in the queue).
+#[test]
+fn linearizable_queue_histories_pass_total_queue() {
+    let mut rng = Rng::new(0);
+    let mut flagged = 0;
+
+    for case in 0..NUM_CASES {
+        let (events, max_client) = random_queue_history(&mut rng);
+        let mut repair = Repair::new(RepairPolicy::Close);
+        let timeline =
+            Timeline::with_repair(events, max_client, &mut repair).expect("invalid history");
+        let report = check_total_queue(&timeline);
+
+        if oracle(&timeline, &Fifo, real_time_ok) {
+            assert!(
+                report.duplicated.is_empty()
+                    && report.unexpected.is_empty()
+                    && report.reordered.is_empty(),
+                "case {}: linearizable but {:?}",
+                case,
+                report
+            );
+        } else if !report.is_valid() {
+            flagged += 1;
+        }
+    }
+    assert!(flagged > 0, "no total-queue violation flagged");
+}
+
+/// The counter checker's bounds never reject a history the oracle accepts at
+/// the same level, but for linearizability with negative deltas, and catch
+/// some violations; among them reads that go back in real time, each within
+/// its bounds.
+#[test]
+fn counter_bounds_are_sound() {
+    let mut rng = Rng::new(0);
+    let mut caught = 0;
+
+    for case in 0..NUM_CASES {
+        let (events, max_client) = random_counter_history(&mut rng);
+        let mut repair = Repair::new(RepairPolicy::Close);
+        let timeline =
+            Timeline::with_repair(events, max_client, &mut repair).expect("invalid history");
+        let expected: Vec<bool> = LEVELS
+            .iter()
+            .map(|&(_, order_ok)| oracle(&timeline, &Counter, order_ok))
+            .collect();
+        let decreasing = timeline
+            .queues
+            .iter()
+            .flatten()
+            .any(|span| match &span.data {
+                OpData::Add { val, .. } => counter_delta(val).unwrap() < 0,
+                _ => false,
+            });
+
+        let mut checker = CounterChecker::new(timeline).expect("not a counter history");
+        checker.set_quiet();
+        for (&(level, _), &valid) in LEVELS.iter().zip(&expected) {
+            checker.set_levels(&[level]);
+            let holds = checker.check() >= level;
+            assert!(
+                holds || !valid || (decreasing && level == Consistency::Linearizable),
+                "case {}: oracle says {:?} holds, checker rejects",
+                case,
+                level
+            );
+            if !holds && !valid {
+                caught += 1;
+            }
+        }
+    }
+    assert!(caught > 0, "no counter violation caught");
+
+    // adds over [1, 10] and [2, 11], then reads of 2 over [3, 4] and of 1 over
+    // [5, 6]
+    let (key, one) = (Value::Int(0), Value::Int(1));
+    let add = OpData::Add {
+        key: key.clone(),
+        val: one.clone(),
+    };
+    let read = |val| OpData::Read {
+        key: key.clone(),
+        val,
+        tag: None,
+    };
+    let events: Vec<Event> = [
+        (1, EventType::Invoke, 0, add.clone()),
+        (2, EventType::Invoke, 1, add.clone()),
+        (3, EventType::Invoke, 2, read(Value::Nil)),
+        (4, EventType::Okay, 2, read(Value::Int(2))),
+        (5, EventType::Invoke, 3, read(Value::Nil)),
+        (6, EventType::Okay, 3, read(one)),
+        (10, EventType::Okay, 0, add.clone()),
+        (11, EventType::Okay, 1, add),
+    ]
+    .into_iter()
+    .enumerate()
+    .map(|(index, (time, etype, client, opdata))| {
+        Event::new(index as u64, time, etype, client, opdata)
+    })
+    .collect();
+    let timeline = Timeline::new(events, 3).expect("invalid history");
+    assert!(!oracle(&timeline, &Counter, real_time_ok));
+    let mut checker = CounterChecker::new(timeline).expect("not a counter history");
+    checker.set_quiet();
+    checker.set_levels(&[Consistency::Linearizable, Consistency::Sequential]);
+    assert_eq!(checker.check(), Consistency::Sequential);
+}
+
+#[test]
+fn random_list_histories_match_oracle() {
+    assert_matches_oracle(4, random_list_history, ListAppend, true);
+}
+
+/// The dependency graph of a list-append history is cyclic iff the oracle
+/// finds no valid ordering, at every level.
+#[test]
+fn append_graph_matches_oracle() {
+    let mut rng = Rng::new(0);
+    let mut rejected = 0;
+
+    for case in 0..NUM_CASES {
+        let (events, max_client) = random_list_history(&mut rng);
+        let mut repair = Repair::new(RepairPolicy::Close);
+        let timeline =
+            Timeline::with_repair(events, max_client, &mut repair).expect("invalid history");
+        let expected: Vec<bool> = LEVELS
+            .iter()
+            .map(|&(_, order_ok)| oracle(&timeline, &ListAppend, order_ok))
+            .collect();
+
+        let checker = AppendChecker::new(timeline).expect("not a list-append history");
+        for (&(level, _), &valid) in LEVELS.iter().zip(&expected) {
+            let anomalies = checker.anomalies(level);
+            assert_eq!(
+                anomalies.is_empty(),
+                valid,
+                "case {}: oracle says {:?} {}, checker found {:?}",
+                case,
+                level,
+                if valid { "holds" } else { "violated" },
+                anomalies
+            );
+        }
+        if !expected.last().unwrap() {
+            rejected += 1;
+        }
+    }
+    assert!(rejected > 0, "no list-append violation");
+}
+
+/// Maximum number of transactions in a generated transactional history, kept
+/// lower as the snapshot isolation oracle goes through every commit order.
+const MAX_TXNS: usize = 5;
+
+/// Generate a random small transactional history over two keys. Writes take
+/// effect at completion, while reads observe the state at completion, at
+/// invocation (like a snapshot), or a random value.
+fn random_txn_history(rng: &mut Rng) -> (Vec<Event>, ClientId) {
+    let num_clients = rng.between(1, 3) as usize;
+    let num_txns = rng.between(1, MAX_TXNS as u64) as usize;
+
+    let mut events = vec![];
+    let mut flying: Vec<Option<(OpData, BTreeMap<KeyType, Value>)>> = vec![None; num_clients];
+    let mut store: BTreeMap<KeyType, Value> = BTreeMap::new();
+    let mut invoked = 0;
+    let mut time = 0;
+
+    while invoked < num_txns || flying.iter().any(|f| f.is_some()) {
+        let client = rng.below(num_clients as u64) as usize;
+        time += 1;
+
+        if let Some((mut opdata, snapshot)) = flying[client].take() {
+            if rng.chance(0.05) {
+                continue;
+            }
+            let OpData::Txn { mops } = &mut opdata else {
+                unreachable!();
+            };
+            let mut own = BTreeMap::new();
+            for mop in mops.iter_mut() {
+                match mop {
+                    Mop::Read { key, val } => {
+                        let seen = match rng.below(5) {
+                            0 | 1 => store.get(key),
+                            2 | 3 => snapshot.get(key),
+                            _ => None,
+                        };
+                        *val = match own.get(key).or(seen) {
+                            Some(v) => v.clone(),
+                            None if rng.chance(0.5) => Value::Int(rng.between(1, 3) as i64),
+                            None => Value::Nil,
+                        };
+                    }
+                    Mop::Write { key, val } => {
+                        own.insert(key.clone(), val.clone());
+                    }
+                }
+            }
+            store.extend(own);
+            events.push(Event::new(
+                events.len() as u64,
+                time,
+                EventType::Okay,
+                client,
+                opdata,
+            ));
+        } else if invoked < num_txns {
+            // writes are often preceded by a read of the same key, making for
+            // lost updates when concurrent
+            let mut mops = vec![];
+            for _ in 0..rng.between(1, 2) {
+                let key = Value::Int(rng.below(2) as i64);
+                if rng.chance(0.7) {
+                    mops.push(Mop::Read {
+                        key: key.clone(),
+                        val: Value::Nil,
+                    });
+                }
+                if mops.is_empty() || rng.chance(0.6) {
+                    mops.push(Mop::Write {
+                        key,
+                        val: Value::Int(rng.between(1, 3) as i64),
+                    });
+                }
+            }
+            let opdata = OpData::Txn { mops };
+            events.push(Event::new(
+                events.len() as u64,
+                time,
+                EventType::Invoke,
+                client,
+                opdata.clone(),
+            ));
+            flying[client] = Some((opdata, store.clone()));
+            invoked += 1;
+        }
+    }
+
+    (events, num_clients - 1)
+}
+
+/// Run a transaction on the state micro-op by micro-op, returning the new
+/// state if all its reads match.
+fn run_txn(state: &BTreeMap<KeyType, Value>, mops: &[Mop]) -> Option<BTreeMap<KeyType, Value>> {
+    let mut state = state.clone();
+    for mop in mops {
+        match mop {
+            Mop::Read { key, val } => {
+                if state.get(key).unwrap_or(&Value::Nil) != val {
+                    return None;
+                }
+            }
+            Mop::Write { key, val } => {
+                state.insert(key.clone(), val.clone());
+            }
+        }
+    }
+    Some(state)
+}
+
+fn txn_mops(span: &OpSpan) -> &[Mop] {
+    let OpData::Txn { mops } = &span.data else {
+        panic!("not a transaction: {}", span.data);
+    };
+    mops
+}
+
+fn txn_writes(span: &OpSpan) -> BTreeSet<&KeyType> {
+    txn_mops(span)
+        .iter()
+        .filter_map(|mop| match mop {
+            Mop::Write { key, .. } => Some(key),
+            Mop::Read { .. } => None,
+        })
+        .collect()
+}
+
+/// Call `visit` with every order of every subset of the transactions that
+/// includes all but possibly the indeterminate ones, until it returns true.
+fn txn_orders(
+    spans: &[OpSpan],
+    order: &mut Vec<usize>,
+    visit: &mut dyn FnMut(&[usize]) -> bool,
+) -> bool {
+    if spans
+        .iter()
+        .enumerate()
+        .all(|(i, s)| s.indeterminate || order.contains(&i))
+        && visit(order)
+    {
+        return true;
+    }
+    for i in 0..spans.len() {
+        if order.contains(&i) {
+            continue;
+        }
+        order.push(i);
+        let found = txn_orders(spans, order, visit);
+        order.pop();
+        if found {
+            return true;
+        }
+    }
+    false
+}
+
+/// Brute-force oracle of the transactional levels. (Strict) serializability
+/// asks for a serial order (respecting real time); snapshot isolation for a
+/// commit order where each transaction reads the state after some prefix of
+/// it, and no transaction committing after that prefix and before it writes
+/// any of the same keys.
+fn txn_oracle(timeline: &Timeline, level: Consistency) -> bool {
+    let spans: Vec<OpSpan> = timeline.queues.iter().flatten().cloned().collect();
+    txn_orders(&spans, &mut vec![], &mut |order| {
+        let mut states = vec![BTreeMap::new()];
+        for (pos, &i) in order.iter().enumerate() {
+            let (snapshots, writes) = match level {
+                Consistency::StrictSerializable
+                    if order[..pos]
+                        .iter()
+                        .any(|&p| !real_time_ok(&spans[p], &spans[i])) =>
+                {
+                    return false;
+                }
+                Consistency::SnapshotIsolation => (0..=pos, txn_writes(&spans[i])),
+                _ => (pos..=pos, BTreeSet::new()),
+            };
+            let mut snapshots = snapshots.filter(|&s| {
+                order[s..pos]
+                    .iter()
+                    .all(|&o| txn_writes(&spans[o]).is_disjoint(&writes))
+            });
+            if !snapshots.any(|s| run_txn(&states[s], txn_mops(&spans[i])).is_some()) {
+                return false;
+            }
+            // the writes take effect on the latest state
+            let writes = txn_mops(&spans[i])
+                .iter()
+                .filter(|mop| matches!(mop, Mop::Write { .. }));
+            let new_state = run_txn(&states[pos], &writes.cloned().collect::<Vec<_>>());
+            states.push(new_state.unwrap());
+        }
+        true
+    })
+}
+
+/// The transaction checker agrees with the brute-force oracle at every
+/// transactional level.
+#[test]
+fn random_txn_histories_match_oracle() {
+    const TXN_LEVELS: [Consistency; 3] = [
+        Consistency::SnapshotIsolation,
+        Consistency::Serializable,
+        Consistency::StrictSerializable,
+    ];
+    let mut rng = Rng::new(0);
+    let mut accepted = [0; 3];
+    let mut rejected = [0; 3];
+
+    for case in 0..NUM_CASES {
+        let (events, max_client) = random_txn_history(&mut rng);
+        let mut repair = Repair::new(RepairPolicy::Close);
+        let timeline =
+            Timeline::with_repair(events, max_client, &mut repair).expect("invalid history");
+
+        for (i, &level) in TXN_LEVELS.iter().enumerate() {
+            let expected = txn_oracle(&timeline, level);
+            let mut checker = TxnChecker::new(timeline.clone()).expect("not transactional");
+            checker.set_quiet();
+            checker.set_levels(&[level]);
+            let result = checker.check().expect("check failed");
+            assert_eq!(
+                result == level,
+                expected,
+                "case {}: oracle says {:?} {}, checker says {:?}\n{:?}",
+                case,
+                level,
+                if expected { "holds" } else { "violated" },
+                result,
+                timeline.queues
+            );
+            if expected {
+                accepted[i] += 1;
+            } else {
+                rejected[i] += 1;
+            }
+        }
+    }
+    assert!(
+        accepted.iter().chain(&rejected).all(|&n| n > 0),
+        "one-sided cases: {:?} accepted, {:?} rejected",
+        accepted,
+        rejected
+    );
+    // some histories only hold under the weaker levels
+    assert!(rejected[2] > rejected[1] && rejected[1] > rejected[0]);
+}
+
+/// Generate a random small register history over two keys, with every value
+/// written at most once per key. Reads observe the latest value at completion,
+/// or any value written to the key so far (possibly by a pending write), so
+/// that histories often hold on every key but not overall.
+fn random_multikey_history(rng: &mut Rng) -> (Vec<Event>, ClientId) {
+    let num_clients = rng.between(2, 3) as usize;
+    let num_ops = rng.between(2, MAX_OPS as u64) as usize;
+
+    let mut events = vec![];
+    let mut flying: Vec<Option<OpData>> = vec![None; num_clients];
+    let mut store: BTreeMap<KeyType, Value> = BTreeMap::new();
+    let mut written: BTreeMap<KeyType, Vec<Value>> = BTreeMap::new();
+    let mut next_val = 1;
+    let mut invoked = 0;
+    let mut time = 0;
+
+    fn observe(
+        rng: &mut Rng,
+        store: &BTreeMap<KeyType, Value>,
+        written: &[Value],
+        key: &KeyType,
+    ) -> Value {
+        if rng.chance(0.5) || written.is_empty() {
+            store.get(key).cloned().unwrap_or_default()
+        } else {
+            let idx = rng.below(written.len() as u64 + 1) as usize;
+            written.get(idx).cloned().unwrap_or_default()
+        }
+    }
+
+    while invoked < num_ops || flying.iter().any(|f| f.is_some()) {
+        let client = rng.below(num_clients as u64) as usize;
+        time += 1;
+
+        if let Some(mut opdata) = flying[client].take() {
+            if rng.chance(0.05) {
+                continue;
+            }
+            let mut etype = EventType::Okay;
+            match &mut opdata {
+                OpData::Read { key, val, .. } => {
+                    let seen = written.get(key).map(Vec::as_slice).unwrap_or_default();
+                    *val = observe(rng, &store, seen, key);
+                }
+                OpData::Write { key, val, .. } => {
+                    store.insert(key.clone(), val.clone());
+                }
+                OpData::Rmw {
+                    key, rval, wval, ..
+                } => {
+                    if store.get(key).cloned().unwrap_or_default() == *rval {
+                        store.insert(key.clone(), wval.clone());
+                    } else if rng.chance(0.5) {
+                        etype = EventType::Fail;
+                    }
+                }
+                _ => unreachable!(),
+            }
+            events.push(Event::new(events.len() as u64, time, etype, client, opdata));
+        } else if invoked < num_ops {
+            let key = Value::Int(rng.below(2) as i64);
+            let opdata = match rng.below(5) {
+                0 | 1 => OpData::Read {
+                    key,
+                    val: Value::Nil,
+                    tag: None,
+                },
+                2 | 3 => {
+                    next_val += 1;
+                    OpData::Write {
+                        key,
+                        val: Value::Int(next_val),
+                        tag: 0,
+                    }
+                }
+                _ => {
+                    next_val += 1;
+                    let seen = written.get(&key).map(Vec::as_slice).unwrap_or_default();
+                    OpData::Rmw {
+                        rval: observe(rng, &store, seen, &key),
+                        key,
+                        func: RmwFunc::Cas,
+                        arg: Value::Nil,
+                        rtag: None,
+                        wval: Value::Int(next_val),
+                        wtag: None,
+                    }
+                }
+            };
+            match &opdata {
+                OpData::Write { key, val, .. } => {
+                    written.entry(key.clone()).or_default().push(val.clone())
+                }
+                OpData::Rmw { key, wval, .. } => {
+                    written.entry(key.clone()).or_default().push(wval.clone())
+                }
+                _ => {}
+            }
+            events.push(Event::new(
+                events.len() as u64,
+                time,
+                EventType::Invoke,
+                client,
+                opdata.clone(),
+            ));
+            flying[client] = Some(opdata);
+            invoked += 1;
+        }
+    }
+
+    (events, num_clients - 1)
+}
+
+/// Read and written (key, value) of a register operation.
+type RegAccess = (Option<(KeyType, Value)>, Option<(KeyType, Value)>);
+
+fn reg_access(data: &OpData) -> RegAccess {
+    match data {
+        OpData::Read { key, val, .. } => (Some((key.clone(), val.clone())), None),
+        OpData::Write { key, val, .. } => (None, Some((key.clone(), val.clone()))),
+        OpData::Rmw {
+            key, rval, wval, ..
+        } => (
+            Some((key.clone(), rval.clone())),
+            Some((key.clone(), wval.clone())),
+        ),
+        other => panic!("not a register operation: {}", other),
+    }
+}
+
+/// Brute-force oracle of causal convergence, by its definition: with every
+/// subset of the indeterminate operations left out, the causal order is
+/// program order and reads-from closed transitively, and some arbitration
+/// order extending it must have each read observe the last write to its key
+/// among its causal past.
+fn causal_oracle(timeline: &Timeline) -> bool {
+    let spans: Vec<&OpSpan> = timeline.queues.iter().flatten().collect();
+    let optional: Vec<usize> = (0..spans.len())
+        .filter(|&i| spans[i].indeterminate)
+        .collect();
+
+    (0..1u32 << optional.len()).any(|mask| {
+        let included: Vec<&OpSpan> = spans
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| {
+                optional
+                    .iter()
+                    .position(|o| o == i)
+                    .is_none_or(|bit| mask & (1 << bit) != 0)
+            })
+            .map(|(_, span)| *span)
+            .collect();
+        let n = included.len();
+        let access: Vec<_> = included.iter().map(|span| reg_access(&span.data)).collect();
+
+        // causal order, closed by Floyd-Warshall
+        let mut co = vec![vec![false; n]; n];
+        let mut writer = vec![None; n];
+        for (r, (read, _)) in access.iter().enumerate() {
+            for w in 0..n {
+                if included[w].client == included[r].client
+                    && !included[w].indeterminate
+                    && !included[r].indeterminate
+                    && included[w].invoke < included[r].invoke
+                {
+                    co[w][r] = true;
+                }
+            }
+            let Some(read) = read else {
+                continue;
+            };
+            if read.1 == Value::Nil {
+                continue;
+            }
+            let Some(w) = access
+                .iter()
+                .position(|(_, write)| write.as_ref() == Some(read))
+            else {
+                return false;
+            };
+            co[w][r] = true;
+            writer[r] = Some(w);
+        }
+        for k in 0..n {
+            for i in 0..n {
+                for j in 0..n {
+                    co[i][j] |= co[i][k] && co[k][j];
+                }
+            }
+        }
+        if (0..n).any(|i| co[i][i]) {
+            return false;
+        }
+
+        // arbitration orders extending it, checking each read when placed
+        fn arbitrate(
+            co: &[Vec<bool>],
+            access: &[RegAccess],
+            writer: &[Option<usize>],
+            placed: &mut Vec<usize>,
+        ) -> bool {
+            let n = co.len();
+            if placed.len() == n {
+                return true;
+            }
+            for op in 0..n {
+                if placed.contains(&op) || (0..n).any(|p| co[p][op] && !placed.contains(&p)) {
+                    continue;
+                }
+                if let Some((key, _)) = &access[op].0 {
+                    let last = placed.iter().rev().copied().find(|&w| {
+                        co[w][op] && access[w].1.as_ref().is_some_and(|(k, _)| k == key)
+                    });
+                    if last != writer[op] {
+                        continue;
+                    }
+                }
+                placed.push(op);
+                let found = arbitrate(co, access, writer, placed);
+                placed.pop();
+                if found {
+                    return true;
+                }
+            }
+            false
+        }
+        arbitrate(&co, &access, &writer, &mut vec![])
+    })
+}
+
+/// Sequential consistency is checked over the whole history, matching the
+/// oracle also when every key holds on its own.
+#[test]
+fn random_multikey_histories_match_oracle() {
+    assert_matches_oracle(4, random_multikey_history, Register, true);
+}
+
+/// The causal bad patterns match the brute-force oracle of the definition.
+#[test]
+fn causal_patterns_match_oracle() {
+    let mut rng = Rng::new(0);
+    let (mut accepted, mut rejected, mut causal_only) = (0, 0, 0);
+
+    for case in 0..NUM_CASES {
+        let (events, max_client) = random_multikey_history(&mut rng);
+        let mut repair = Repair::new(RepairPolicy::Close);
+        let timeline =
+            Timeline::with_repair(events, max_client, &mut repair).expect("invalid history");
+        let expected = causal_oracle(&timeline);
+        let sequential = oracle(&timeline, &Register, program_order_ok);
+
+        let mut checker = Checker::new(timeline.clone());
+        checker.set_quiet();
+        checker.set_levels(&[Consistency::Causal]);
+        let result = checker.check().expect("checker failed");
+        assert_eq!(
+            result == Consistency::Causal,
+            expected,
+            "case {}: oracle says causal {}, checker says {:?}\n{:?}",
+            case,
+            if expected { "holds" } else { "violated" },
+            result,
+            timeline.queues
+        );
+        assert!(
+            expected || !sequential,
+            "case {}: sequential but not causal",
+            case
+        );
+
+        if !expected {
+            rejected += 1;
+        } else if sequential {
+            accepted += 1;
+        } else {
+            causal_only += 1;
+        }
+    }
+    assert!(
+        accepted > 0 && rejected > 0 && causal_only > 0,
+        "one-sided cases: {} accepted, {} rejected, {} causal only",
+        accepted,
+        rejected,
+        causal_only
+    );
+}
+
+/// Generate a random single-key register history whose reads and CASes report
+/// the tag of the write they observed: mostly any write invoked so far, else
+/// any write at all, the initial value, or rarely a tag never written. Values
+/// repeat, so only the tags tell writes apart.
+fn random_tagged_history(rng: &mut Rng) -> (Vec<Event>, ClientId) {
+    let num_clients = rng.between(1, 3) as usize;
+    let num_ops = rng.between(1, MAX_OPS as u64) as usize;
+    let key = Value::Int(0);
+
+    let mut events = vec![];
+    let mut flying: Vec<Option<OpData>> = vec![None; num_clients];
+    let mut written: Vec<(UniqueTag, Value)> = vec![];
+    let mut invoked = 0;
+    let mut time = 0;
+
+    fn observe(rng: &mut Rng, written: &[(UniqueTag, Value)]) -> (Option<UniqueTag>, Value) {
+        if rng.chance(0.05) {
+            return (Some(UniqueTag::MAX), Value::Int(1));
+        }
+        let idx = rng.below(written.len() as u64 + 1) as usize;
+        match written.get(idx) {
+            Some((tag, val)) => (Some(*tag), val.clone()),
+            None => (None, Value::Nil),
+        }
+    }
+
+    while invoked < num_ops || flying.iter().any(|f| f.is_some()) {
+        let client = rng.below(num_clients as u64) as usize;
+        time += 1;
+
+        if let Some(mut opdata) = flying[client].take() {
+            if rng.chance(0.05) {
+                continue;
+            }
+            let mut etype = EventType::Okay;
+            match &mut opdata {
+                OpData::Read { val, tag, .. } => (*tag, *val) = observe(rng, &written),
+                OpData::Rmw { rval, rtag, .. } => {
+                    (*rtag, *rval) = observe(rng, &written);
+                    if rng.chance(0.3) {
+                        etype = EventType::Fail;
+                    }
+                }
+                _ => {}
+            }
+            events.push(Event::new(events.len() as u64, time, etype, client, opdata));
+        } else if invoked < num_ops {
+            let val = Value::Int(rng.between(1, 3) as i64);
+            let opdata = match rng.below(5) {
+                0 | 1 => OpData::Read {
+                    key: key.clone(),
+                    val: Value::Nil,
+                    tag: None,
+                },
+                2 | 3 => OpData::Write {
+                    key: key.clone(),
+                    val,
+                    tag: time,
+                },
+                _ => OpData::Rmw {
+                    key: key.clone(),
+                    func: RmwFunc::Cas,
+                    arg: Value::Nil,
+                    rval: Value::Nil,
+                    rtag: None,
+                    wval: val,
+                    wtag: Some(time),
+                },
+            };
+            if let OpData::Write { tag, val, .. }
+            | OpData::Rmw {
+                wtag: Some(tag),
+                wval: val,
+                ..
+            } = &opdata
+            {
+                written.push((*tag, val.clone()));
+            }
+            events.push(Event::new(
+                events.len() as u64,
+                time,
+                EventType::Invoke,
+                client,
+                opdata.clone(),
+            ));
+            flying[client] = Some(opdata);
+            invoked += 1;
+        }
+    }
+
+    // some observe writes invoked only later
+    for event in &mut events {
+        if event.etype == EventType::Invoke || !rng.chance(0.1) {
+            continue;
+        }
+        match &mut event.opdata {
+            OpData::Read { val, tag, .. } => (*tag, *val) = observe(rng, &written),
+            OpData::Rmw { rval, rtag, .. } => (*rtag, *rval) = observe(rng, &written),
+            _ => {}
+        }
+    }
+
+    (events, num_clients - 1)
+}
+
+/// The tag fast path (or the search, where it falls back) matches the oracle,
+/// which takes tags into account through the register model.
+#[test]
+fn random_tagged_histories_match_oracle() {
+    assert_matches_oracle(4, random_tagged_history, Register, true);
+}
+
+/// Generate a tagged history of reads, writes, and CASes on a single key that
+/// observe the versions of a linearizable execution, each operation taking
+/// effect between its invocation and completion, except that some reads and
+/// CASes are then made to observe an earlier version instead.
+fn random_tagged_cas_history(rng: &mut Rng) -> (Vec<Event>, ClientId) {
+    let num_clients = rng.between(1, 3) as usize;
+    let num_ops = rng.between(1, MAX_OPS as u64) as usize;
+    let key = Value::Int(0);
+
+    let mut events = vec![];
+    // flying operation of each client, with its completion once taken effect
+    let mut flying: Vec<Option<(OpData, Option<EventType>)>> = vec![None; num_clients];
+    let mut versions: Vec<(Option<UniqueTag>, Value)> = vec![(None, Value::Nil)];
+    let mut invoked = 0;
+    let mut time = 0;
+
+    while invoked < num_ops || flying.iter().any(|f| f.is_some()) {
+        let client = rng.below(num_clients as u64) as usize;
+        time += 1;
+
+        match flying[client].take() {
+            Some((mut opdata, None)) => {
+                let current = versions.last().unwrap().clone();
+                let mut etype = EventType::Okay;
+                match &mut opdata {
+                    OpData::Read { val, tag, .. } => (*tag, *val) = current,
+                    OpData::Write { val, tag, .. } => versions.push((Some(*tag), val.clone())),
+                    OpData::Rmw {
+                        rval,
+                        rtag,
+                        wval,
+                        wtag,
+                        ..
+                    } => {
+                        if rng.chance(0.8) {
+                            (*rtag, *rval) = current;
+                            versions.push((*wtag, wval.clone()));
+                        } else {
+                            etype = EventType::Fail;
+                        }
+                    }
+                    _ => {}
+                }
+                flying[client] = Some((opdata, Some(etype)));
+            }
+            Some((mut opdata, Some(etype))) => {
+                if rng.chance(0.05) {
+                    continue;
+                }
+                if etype == EventType::Okay && rng.chance(0.15) {
+                    let stale = versions[rng.below(versions.len() as u64) as usize].clone();
+                    match &mut opdata {
+                        OpData::Read { val, tag, .. } => (*tag, *val) = stale,
+                        OpData::Rmw { rval, rtag, .. } => (*rtag, *rval) = stale,
+                        _ => {}
+                    }
+                }
+                events.push(Event::new(events.len() as u64, time, etype, client, opdata));
+            }
+            None if invoked < num_ops => {
+                let val = Value::Int(rng.between(1, 3) as i64);
+                let opdata = match rng.below(5) {
+                    0 | 1 => OpData::Read {
+                        key: key.clone(),
+                        val: Value::Nil,
+                        tag: None,
+                    },
+                    2 => OpData::Write {
+                        key: key.clone(),
+                        val,
+                        tag: time,
+                    },
+                    _ => OpData::Rmw {
+                        key: key.clone(),
+                        func: RmwFunc::Cas,
+                        arg: Value::Nil,
+                        rval: Value::Nil,
+                        rtag: None,
+                        wval: val,
+                        wtag: Some(time),
+                    },
+                };
+                events.push(Event::new(
+                    events.len() as u64,
+                    time,
+                    EventType::Invoke,
+                    client,
+                    opdata.clone(),
+                ));
+                flying[client] = Some((opdata, None));
+                invoked += 1;
+            }
+            None => {}
+        }
+    }
+
+    (events, num_clients - 1)
+}
+
+/// Tagged CAS histories are decided by their tags, chains of CASes
+/// included, in agreement with the oracle both ways.
+#[test]
+fn tagged_cas_histories_match_oracle() {
+    assert_matches_oracle(2, random_tagged_cas_history, Register, true);
+
+    let mut rng = Rng::new(0);
+    let (mut accepted, mut rejected) = (0, 0);
+    for case in 0..NUM_CASES {
+        let (events, max_client) = random_tagged_cas_history(&mut rng);
+        let mut repair = Repair::new(RepairPolicy::Close);
+        let timeline =
+            Timeline::with_repair(events, max_client, &mut repair).expect("invalid history");
+        // a chain needs a CAS following another's version
+        let chained = timeline.queues.iter().flatten().any(|span| {
+            matches!(span.data, OpData::Rmw { rtag: Some(rtag), .. }
+            if timeline.queues.iter().flatten().any(|other| {
+                matches!(other.data, OpData::Rmw { wtag, .. } if wtag == Some(rtag))
+            }))
+        });
+        if !chained {
+            continue;
+        }
+
+        let queues: Vec<Vec<CkSpan>> = timeline
+            .queues
+            .iter()
+            .map(|queue| {
+                queue
+                    .iter()
+                    .map(|span| CkSpan {
+                        invoke: span.invoke,
+                        finish: span.finish,
+                        data: span.data.clone(),
+                        optional: span.indeterminate,
+                    })
+                    .collect()
+            })
+            .collect();
+        let Some(verdict) = check_tags(&queues, Consistency::Linearizable, 0) else {
+            continue;
+        };
+        let expected = oracle(&timeline, &Register, real_time_ok);
+        assert_eq!(
+            verdict.is_ok(),
+            expected,
+            "case {}: {:?}",
+            case,
+            timeline.queues
+        );
+        if expected {
+            accepted += 1;
+        } else {
+            rejected += 1;
+        }
+    }
+    assert!(
+        accepted > NUM_CASES / 20 && rejected > NUM_CASES / 20,
+        "{} chains accepted and {} rejected by tags",
+        accepted,
+        rejected
+    );
+}
+
+/// Histories with anomalies refuting sequential consistency are never
+/// sequentially consistent.
+#[test]
+fn data_anomalies_are_violations() {
+    let mut rng = Rng::new(0);
+    let mut flagged = 0;
+
+    for source in [random_history, random_tagged_history] {
+        for case in 0..NUM_CASES {
+            let (events, max_client) = source(&mut rng);
+            let mut repair = Repair::new(RepairPolicy::Close);
+            let timeline =
+                Timeline::with_repair(events, max_client, &mut repair).expect("invalid history");
+            let anomalies = validate_registers(&timeline);
+            if !anomalies
+                .iter()
+                .any(|anomaly| anomaly.refutes(Consistency::Sequential))
+            {
+                continue;
+            }
+            flagged += 1;
+            assert!(
+                !oracle(&timeline, &Register, program_order_ok),
+                "case {}: {} in a sequential history",
+                case,
+                anomalies[0]
+            );
+        }
+    }
+    assert!(flagged > 0, "no anomalies flagged");
+}
+
+/// An anomaly fails the levels it refutes on its own key only, the other
+/// keys being searched as before.
+#[test]
+fn anomalies_refute_their_key_only() {
+    let write = |key, val| OpData::Write {
+        key: Value::Int(key),
+        val: Value::Int(val),
+        tag: 0,
+    };
+    let read = |key, val| OpData::Read {
+        key: Value::Int(key),
+        val: Value::Int(val),
+        tag: None,
+    };
+    // a garbage read on key 0, and key 1 reading 1 either in order or stale
+    for (stale, violating) in [(2, vec![0]), (1, vec![0, 1])] {
+        let ops = [
+            write(0, 1),
+            read(0, 3),
+            write(1, 1),
+            write(1, 2),
+            read(1, stale),
+        ];
+        let events: Vec<Event> = ops
+            .iter()
+            .enumerate()
+            .flat_map(|(nth, op)| {
+                let nth = nth as u64;
+                [
+                    Event::new(2 * nth, 20 * nth + 10, EventType::Invoke, 0, op.clone()),
+                    Event::new(2 * nth + 1, 20 * nth + 20, EventType::Okay, 0, op.clone()),
+                ]
+            })
+            .collect();
+        let timeline = Timeline::new(events, 0).expect("invalid history");
+        let anomalies = validate_registers(&timeline);
+        assert_eq!(anomalies.len(), 1, "{:?}", anomalies);
+        assert!(!anomalies[0].refutes(Consistency::Weak));
+
+        let mut checker = Checker::new(timeline);
+        checker.set_quiet();
+        checker.set_exhaustive();
+        checker.set_levels(&[Consistency::Linearizable, Consistency::Sequential]);
+        for level in [Consistency::Linearizable, Consistency::Sequential] {
+            checker.refute(level, anomalies[0].key().clone(), 0);
+        }
+        let verdicts = checker.check_report().expect("checker failed");
+        assert_eq!(verdicts.len(), 2);
+        assert!(verdicts.iter().all(|verdict| !verdict.holds));
+        let keys: Vec<_> = verdicts[0].violations.keys().cloned().collect();
+        assert_eq!(
+            keys,
+            violating.into_iter().map(Value::Int).collect::<Vec<_>>()
+        );
+    }
+}
+
+/// An incr-if takes a nil register as 0, like a fetch-add, whether it
+/// records the value read as 0 or nil; but not a register written since.
+#[test]
+fn incr_if_takes_nil_as_zero() {
+    // the nth operation of a single client, on key 0
+    let op = |nth: u64, f: &str, invoked: &str, completed: &str| {
+        format!(
+            "{{:index {0}, :time {1}, :type :invoke, :process 0, :f {2}, :value [0 {3}]}}\n\
+             {{:index {4}, :time {5}, :type :ok, :process 0, :f {2}, :value [0 {6}]}}\n",
+            2 * nth,
+            20 * nth + 10,
+            f,
+            invoked,
+            2 * nth + 1,
+            20 * nth + 20,
+            completed
+        )
+    };
+    let incr = |nth, old| {
+        let value = format!("[{} 1]", old);
+        op(nth, ":incr-if", &value, &value)
+    };
+    for (history, linearizable) in [
+        (incr(0, "0") + &op(1, ":read", "nil", "1"), true),
+        (incr(0, "nil"), true),
+        (
+            op(0, ":write", "5", "5") + &incr(1, "0") + &op(2, ":read", "nil", "1"),
+            false,
+        ),
+    ] {
+        let dir = fixture_dir("incr-if", &[("history.edn", &history)]);
+        let (events, max_client) = parse_history(&dir).expect("cannot parse");
+        let timeline = Timeline::new(events, max_client).expect("invalid history");
+        assert_eq!(
+            oracle(&timeline, &Register, real_time_ok),
+            linearizable,
+            "{}",
+            history
+        );
+        let mut checker = Checker::new(timeline);
+        checker.set_quiet();
+        let level = checker.check().expect("checker failed");
+        assert_eq!(
+            level == Consistency::Linearizable,
+            linearizable,
+            "{}",
+            history
+        );
+        let _ = fs::remove_dir_all(dir);
+    }
+}
+
+/// A read of nil carrying the tag of a delete observes its tombstone, which
+/// a register never written, or written since, is not.
+#[test]
+fn deletes_leave_tagged_tombstones() {
+    let key = Value::Int(0);
+    let write = |val, tag| OpData::Write {
+        key: key.clone(),
+        val: Value::Int(val),
+        tag,
+    };
+    let delete = |tag| OpData::Delete {
+        key: key.clone(),
+        tag,
+    };
+    let read = |tag| OpData::Read {
+        key: key.clone(),
+        val: Value::Nil,
+        tag,
+    };
+    for (ops, linearizable) in [
+        (vec![write(1, 1), delete(2), read(Some(2))], true),
+        (vec![write(1, 1), delete(2), read(None)], true),
+        (vec![read(Some(2)), write(1, 1), delete(2)], false),
+        (
+            vec![write(1, 1), delete(2), delete(3), read(Some(2))],
+            false,
+        ),
+    ] {
+        // the ops of a single client, one after the other
+        let events: Vec<Event> = ops
+            .iter()
+            .enumerate()
+            .flat_map(|(nth, op)| {
+                let nth = nth as u64;
+                [
+                    Event::new(2 * nth, 20 * nth + 10, EventType::Invoke, 0, op.clone()),
+                    Event::new(2 * nth + 1, 20 * nth + 20, EventType::Okay, 0, op.clone()),
+                ]
+            })
+            .collect();
+        let timeline = Timeline::new(events, 0).expect("invalid history");
+        assert_eq!(
+            oracle(&timeline, &Register, real_time_ok),
+            linearizable,
+            "{:?}",
+            ops
+        );
+        let mut checker = Checker::new(timeline);
+        checker.set_quiet();
+        let level = checker.check().expect("checker failed");
+        assert_eq!(
+            level == Consistency::Linearizable,
+            linearizable,
+            "{:?}",
+            ops
+        );
+    }
+}
+
+/// Reads-from follows the tags when values repeat, and untagged reads of a
+/// value written more than once are rejected rather than guessed.
+#[test]
+fn causal_reads_from_follows_tags() {
+    let key = Value::Int(0);
+    let write = |tag| OpData::Write {
+        key: key.clone(),
+        val: Value::Int(1),
+        tag,
+    };
+    let read = |tag| OpData::Read {
+        key: key.clone(),
+        val: Value::Int(1),
+        tag,
+    };
+    for (ops, causal) in [
+        (
+            vec![(0, write(1)), (0, write(2)), (1, read(Some(1)))],
+            Some(true),
+        ),
+        (
+            vec![
+                (0, write(1)),
+                (0, write(2)),
+                (1, read(Some(2))),
+                (1, read(Some(2))),
+            ],
+            Some(true),
+        ),
+        (
+            vec![
+                (0, write(1)),
+                (0, write(2)),
+                (1, read(Some(2))),
+                (1, read(Some(1))),
+            ],
+            Some(false),
+        ),
+        (vec![(0, write(1)), (0, write(2)), (1, read(None))], None),
+    ] {
+        // the ops one after the other, by the given clients
+        let events: Vec<Event> = ops
+            .iter()
+            .enumerate()
+            .flat_map(|(nth, (client, op))| {
+                let nth = nth as u64;
+                [
+                    Event::new(
+                        2 * nth,
+                        20 * nth + 10,
+                        EventType::Invoke,
+                        *client,
+                        op.clone(),
+                    ),
+                    Event::new(
+                        2 * nth + 1,
+                        20 * nth + 20,
+                        EventType::Okay,
+                        *client,
+                        op.clone(),
+                    ),
+                ]
+            })
+            .collect();
+        let timeline = Timeline::new(events, 1).expect("invalid history");
+        let mut checker = Checker::new(timeline);
+        checker.set_quiet();
+        checker.set_levels(&[Consistency::Causal]);
+        let result = checker
+            .check()
+            .ok()
+            .map(|level| level == Consistency::Causal);
+        assert_eq!(result, causal, "{:?}", ops);
+    }
+}
+
+/// Each key found in violation is so already by the time reported: its
+/// operations invoked by then are not linearizable on their own.
+#[test]
+fn violation_witnesses_are_violated_prefixes() {
+    let mut rng = Rng::new(0);
+    let mut located = 0;
+
+    for source in [random_history, random_tagged_history] {
+        for case in 0..NUM_CASES {
+            let (events, max_client) = source(&mut rng);
+            let mut repair = Repair::new(RepairPolicy::Close);
+            let timeline =
+                Timeline::with_repair(events, max_client, &mut repair).expect("invalid history");
+
+            let mut checker = Checker::new(timeline.clone());
+            checker.set_quiet();
+            checker.set_exhaustive();
+            let verdicts = checker.check_report().expect("checker failed");
+            for (key, &witness) in &verdicts[0].violations {
+                let mut prefix = timeline.clone();
+                for queue in prefix.queues.iter_mut() {
+                    queue.retain(|span| span.key() == *key && span.invoke <= witness);
+                }
+                assert!(
+                    !oracle(&prefix, &Register, real_time_ok),
+                    "case {}: key {} linearizable by {}",
+                    case,
+                    key,
+                    witness
+                );
+                located += 1;
+            }
+        }
+    }
+    assert!(located > 0, "no violations located");
+}
+
+/// Linearizability within a clock-skew tolerance matches the oracle of the
+/// relaxed real-time order, and verdicts are marked as skewed exactly when
+/// they hold only within it.
+#[test]
+fn skewed_histories_match_oracle() {
+    let mut rng = Rng::new(0);
+    let mut skewed = 0;
+
+    for source in [random_history, random_tagged_history] {
+        for case in 0..NUM_CASES {
+            let (events, max_client) = source(&mut rng);
+            let mut repair = Repair::new(RepairPolicy::Close);
+            let timeline =
+                Timeline::with_repair(events, max_client, &mut repair).expect("invalid history");
+            let expected = oracle(&timeline, &Register, skewed_real_time_ok);
+            let strict = oracle(&timeline, &Register, real_time_ok);
+
+            let mut checker = Checker::new(timeline);
+            checker.set_quiet();
+            checker.set_epsilon(EPSILON);
+            let verdicts = checker.check_report().expect("checker failed");
+            assert_eq!(verdicts[0].holds, expected, "case {}: holds", case);
+            assert_eq!(
+                verdicts[0].skewed,
+                expected && !strict,
+                "case {}: skewed",
+                case
+            );
+            if verdicts[0].skewed {
+                skewed += 1;
+            }
+        }
+    }
+    assert!(skewed > 0, "no verdict relied on epsilon");
+}
+
+/// Real-time order of reads started early, which leaves program order (by
+/// `:index`, as invokes no longer tell) intact.
+fn shifted_real_time_ok(a: &OpSpan, b: &OpSpan) -> bool {
+    real_time_ok(a, b)
+        && (a.client != b.client || a.indeterminate || b.indeterminate || b.index > a.index)
+}
+
+/// Least k, or Δ, by which each key of the history is k-atomic, or Δ-atomic,
+/// tried one by one from the least; the greatest over all keys, `None` if no
+/// bound suffices for some key.
+fn staleness_oracle(timeline: &Timeline, level: Consistency) -> Option<u64> {
+    let spans: Vec<OpSpan> = timeline.queues.iter().flatten().cloned().collect();
+    let keys: BTreeSet<KeyType> = spans.iter().map(|span| span.key()).collect();
+    let mut worst = 0;
+    for key in keys {
+        let spans: Vec<OpSpan> = spans.iter().filter(|s| s.key() == key).cloned().collect();
+        let bound = if level == Consistency::KAtomic {
+            let writes = spans.iter().filter(|s| !s.data.read_only()).count();
+            (1..=writes + 1).find(|&k| {
+                let model = Keyed(&KAtomic {
+                    model: &Register,
+                    k,
+                });
+                permute(
+                    &model,
+                    &spans,
+                    real_time_ok,
+                    &mut vec![],
+                    &mut vec![false; spans.len()],
+                    &model.initial(),
+                )
+            })?
+        } else {
+            let first = spans.iter().map(|s| s.invoke).min().unwrap();
+            let finishes = spans
+                .iter()
+                .map(|s| s.finish)
+                .filter(|&f| f != Timestamp::MAX);
+            let last = finishes.max().unwrap_or(0);
+            let model = Keyed(&Register);
+            (0..=last.saturating_sub(first) as usize).find(|&delta| {
+                let mut shifted = spans.clone();
+                for span in shifted.iter_mut().filter(|s| s.data.read_only()) {
+                    span.invoke = span.invoke.saturating_sub(delta as Timestamp);
+                }
+                permute(
+                    &model,
+                    &shifted,
+                    shifted_real_time_ok,
+                    &mut vec![],
+                    &mut vec![false; shifted.len()],
+                    &model.initial(),
+                )
+            })?
+        };
+        worst = worst.max(bound as u64);
+    }
+    Some(worst)
+}
+
+/// The least k and Δ reported agree with the ones found by brute force, and
+/// the levels hold iff some bound does.
+#[test]
+fn staleness_bounds_match_oracle() {
+    let mut rng = Rng::new(0);
+    let mut stale = [0; 2];
+
+    for source in [random_history, random_tagged_history] {
+        for case in 0..NUM_CASES {
+            let (events, max_client) = source(&mut rng);
+            let mut repair = Repair::new(RepairPolicy::Close);
+            let timeline =
+                Timeline::with_repair(events, max_client, &mut repair).expect("invalid history");
+
+            for (i, level) in [Consistency::KAtomic, Consistency::DeltaAtomic]
+                .into_iter()
+                .enumerate()
+            {
+                let expected = staleness_oracle(&timeline, level);
+                let mut checker = Checker::new(timeline.clone());
+                checker.set_quiet();
+                checker.set_levels(&[level]);
+                let verdicts = checker.check_report().expect("checker failed");
+                assert_eq!(verdicts[0].bound, expected, "case {}: {}", case, level);
+                assert_eq!(verdicts[0].holds, expected.is_some(), "case {}", case);
+                if expected.is_some_and(|bound| bound > u64::from(i == 0)) {
+                    stale[i] += 1;
+                }
+            }
+        }
+    }
+    assert!(stale.iter().all(|&n| n > 0), "no stale reads: {:?}", stale);
+}
+
+/// A staleness level is only the result, of the history or of a key, if it
+/// is sequentially consistent too, as the level does not imply it.
+#[test]
+fn staleness_levels_need_sequential_consistency() {
+    let mut rng = Rng::new(0);
+    let mut not_sequential = 0;
+
+    for case in 0..NUM_CASES {
+        let (events, max_client) = random_history(&mut rng);
+        let mut repair = Repair::new(RepairPolicy::Close);
+        let timeline =
+            Timeline::with_repair(events, max_client, &mut repair).expect("invalid history");
+        let sequential = oracle(&timeline, &Register, program_order_ok);
+        let single_key = timeline
+            .queues
+            .iter()
+            .flatten()
+            .map(|span| span.key())
+            .collect::<BTreeSet<_>>()
+            .len()
+            == 1;
+
+        for level in [Consistency::KAtomic, Consistency::DeltaAtomic] {
+            let stale = staleness_oracle(&timeline, level).is_some();
+            if stale && !sequential {
+                not_sequential += 1;
+            }
+            let mut checker = Checker::new(timeline.clone());
+            checker.set_quiet();
+            checker.set_levels(&[level]);
+            let result = checker.check().expect("checker failed");
+            assert_eq!(
+                result == level,
+                stale && sequential,
+                "case {}: {}",
+                case,
+                level
+            );
+
+            if single_key {
+                let mut checker = Checker::new(timeline.clone());
+                checker.set_quiet();
+                checker.set_levels(&[level]);
+                let results = checker.check_per_key().expect("checker failed");
+                assert!(
+                    results
+                        .values()
+                        .all(|&r| (r == level) == (stale && sequential)),
+                    "case {}: {} per key",
+                    case,
+                    level
+                );
+            }
+        }
+    }
+    assert!(not_sequential > 0, "no stale but non-sequential case");
+}
@@ -0,0 +1,256 @@
+//! Printing of the checker's findings by the command line modes, see
+//! `run`.
+
+use crate::nemesis::{FaultWindow, Period, periods};
+use crate::queue::TotalQueueReport;
+use crate::repair::{Repair, RepairPolicy};
+use crate::report::PeriodVerdict;
+use crate::stats::TimelineStats;
+use crate::types::{EventType, Timeline, Timestamp};
+use crate::validate::DataAnomaly;
+
+/// Print the number of operations of each type and outcome in a timeline,
+/// then how they are distributed over keys and clients.
+pub(crate) fn print_timeline_stats(timeline: &Timeline) {
+    println!(
+        "Parsed timeline: {} clients, {} keys, {} total ops",
+        timeline.num_clients(),
+        timeline.num_keys(),
+        timeline.stats_ops_sum,
+    );
+
+    println!("Call stats:  {:>5}  {:>5}  {:>5}", "call", "okay", "fail");
+    println!(
+        "       read  {:5}  {:5}  {:5}",
+        timeline.stats_ops_r[0], timeline.stats_ops_r[1], timeline.stats_ops_r[2]
+    );
+    println!(
+        "      write  {:5}  {:5}  {:5}",
+        timeline.stats_ops_w[0], timeline.stats_ops_w[1], timeline.stats_ops_w[2]
+    );
+    println!(
+        "        cas  {:5}  {:5}  {:5}",
+        timeline.stats_ops_cas[0], timeline.stats_ops_cas[1], timeline.stats_ops_cas[2]
+    );
+    if timeline.stats_ops_del[0] > 0 {
+        println!(
+            "     delete  {:5}  {:5}  {:5}",
+            timeline.stats_ops_del[0], timeline.stats_ops_del[1], timeline.stats_ops_del[2]
+        );
+    }
+    if timeline.stats_ops_add[0] > 0 {
+        println!(
+            "        add  {:5}  {:5}  {:5}",
+            timeline.stats_ops_add[0], timeline.stats_ops_add[1], timeline.stats_ops_add[2]
+        );
+    }
+    if timeline.stats_ops_app[0] > 0 {
+        println!(
+            "     append  {:5}  {:5}  {:5}",
+            timeline.stats_ops_app[0], timeline.stats_ops_app[1], timeline.stats_ops_app[2]
+        );
+    }
+    if timeline.stats_ops_txn[0] > 0 {
+        println!(
+            "        txn  {:5}  {:5}  {:5}",
+            timeline.stats_ops_txn[0], timeline.stats_ops_txn[1], timeline.stats_ops_txn[2]
+        );
+    }
+    if timeline.stats_ops_enq[0] + timeline.stats_ops_deq[0] > 0 {
+        println!(
+            "        enq  {:5}  {:5}  {:5}",
+            timeline.stats_ops_enq[0], timeline.stats_ops_enq[1], timeline.stats_ops_enq[2]
+        );
+        println!(
+            "        deq  {:5}  {:5}  {:5}",
+            timeline.stats_ops_deq[0], timeline.stats_ops_deq[1], timeline.stats_ops_deq[2]
+        );
+    }
+
+    println!(
+        "Keyops dist.:  {:>4}  {:>4}  {:>4}  {:>4}",
+        "min", "med", "avg", "max"
+    );
+    println!(
+        "               {:4}  {:4}  {:4}  {:4}",
+        timeline.stats_key_min,
+        timeline.stats_key_med,
+        timeline.stats_key_avg,
+        timeline.stats_key_max
+    );
+
+    println!(
+        "Client dist.:  {:>4}  {:>4}  {:>4}  {:>4}",
+        "min", "med", "avg", "max"
+    );
+    println!(
+        "               {:4}  {:4}  {:4}  {:4}",
+        timeline.stats_cli_min,
+        timeline.stats_cli_med,
+        timeline.stats_cli_avg,
+        timeline.stats_cli_max
+    );
+}
+
+/// Print each anomaly repaired, then their number of each kind and action
+/// if the policy repairs more than malformed lines.
+pub(crate) fn print_repairs(repair: &Repair) {
+    for anomaly in repair.anomalies() {
+        eprintln!("Repair ({}): {}", anomaly.action, anomaly.error);
+    }
+    if matches!(repair.policy(), RepairPolicy::Abort | RepairPolicy::Skip) {
+        return;
+    }
+
+    let counts = repair.summary();
+    println!(
+        "Repaired {} anomalies ({}){}",
+        repair.anomalies().len(),
+        repair.policy(),
+        if counts.is_empty() { "" } else { ":" }
+    );
+    for ((kind, action), count) in counts {
+        println!("  {:>6} {:<24} -> {}", count, kind, action);
+    }
+}
+
+/// Print the latency quantiles of each type of operation, in msecs assuming
+/// :time in nsecs, then the throughput of completions in each period of the
+/// faults, if any.
+pub(crate) fn print_latency_stats(stats: &TimelineStats, windows: &[FaultWindow]) {
+    let msecs = |time: Timestamp| time as f64 / 1_000_000.0;
+    println!(
+        "Latency (ms): {:>5}  {:>8}  {:>8}  {:>8}  {:>8}",
+        "count", "p50", "p95", "p99", "max"
+    );
+    for (group, l) in &stats.latency {
+        println!(
+            "  {:>6} {:<4}  {:5}  {:8.2}  {:8.2}  {:8.2}  {:8.2}",
+            group.f,
+            group.outcome(),
+            l.count,
+            msecs(l.p50),
+            msecs(l.p95),
+            msecs(l.p99),
+            msecs(l.max)
+        );
+    }
+    if windows.is_empty() {
+        return;
+    }
+
+    println!("Throughput (ok ops/s):");
+    for period in periods(windows) {
+        let mut span = None;
+        let mut count = 0;
+        for ((_, group), rate) in stats
+            .period_throughput
+            .iter()
+            .filter(|((p, _), _)| *p == period)
+        {
+            span = Some((rate.start, rate.end));
+            if group.etype == EventType::Okay {
+                count += rate.count;
+            }
+        }
+        if let Some((start, end)) = span {
+            let secs = (end - start + 1) as f64 / 1_000_000_000.0;
+            println!(
+                "    {:<15} {:8.1}  ({} in {:.2} s)",
+                period.to_string(),
+                count as f64 / secs,
+                count,
+                secs
+            );
+        }
+    }
+}
+
+/// Print the anomalies found by validating a register history, those that
+/// are violations failing their keys without a search.
+pub(crate) fn print_data_anomalies(anomalies: &[DataAnomaly]) {
+    if anomalies.is_empty() {
+        return;
+    }
+    println!(
+        "Data anomalies: {} found{}",
+        anomalies.len(),
+        if anomalies.iter().any(DataAnomaly::is_violation) {
+            ", failing their keys without a search"
+        } else {
+            ""
+        }
+    );
+    for anomaly in anomalies {
+        println!("    {}", anomaly);
+    }
+}
+
+/// Print the violations found grouped by the period of the faults they
+/// happened in, each period failing if any did.
+pub(crate) fn print_periods(windows: &[FaultWindow], periods: &[PeriodVerdict]) {
+    if periods.is_empty() {
+        return;
+    }
+    println!("Fault windows: {} found", windows.len());
+    for verdict in periods {
+        let fault = match verdict.period {
+            Period::During(nth) => {
+                let window = &windows[nth - 1];
+                let stop = window
+                    .stop
+                    .map_or("...".to_string(), |stop| stop.to_string());
+                format!("  {}  [{}, {}]", window.fault, window.start, stop)
+            }
+            _ => String::new(),
+        };
+        println!(
+            "    {:<15} {}{}",
+            verdict.period.to_string(),
+            match verdict.holds() {
+                Some(false) => "❌",
+                Some(true) => "✅",
+                None => "❔",
+            },
+            fault
+        );
+        for violation in &verdict.violations {
+            println!("        at {:<12} {}", violation.time, violation.what);
+            if let Some(witness) = &violation.witness {
+                println!("            by {}", witness);
+            }
+        }
+    }
+    if periods.iter().any(|verdict| verdict.holds().is_none()) {
+        println!("    (first violation only, pass --all-keys for all)");
+    }
+}
+
+/// Print the outcome of the total-queue check.
+pub(crate) fn print_total_queue(report: &TotalQueueReport) {
+    println!(
+        "Total-queue:   {} enqueued, {} dequeued, {}",
+        report.enqueued,
+        report.dequeued,
+        if report.is_valid() {
+            "valid"
+        } else {
+            "INVALID"
+        }
+    );
+    for (key, val) in &report.lost {
+        println!("    lost        [{} {}]", key, val);
+    }
+    for (key, val) in &report.duplicated {
+        println!("    duplicated  [{} {}]", key, val);
+    }
+    for (key, val) in &report.unexpected {
+        println!("    unexpected  [{} {}]", key, val);
+    }
+    for (key, earlier, later) in &report.reordered {
+        println!(
+            "    reordered   [{} {}] after [{} {}]",
+            key, earlier, key, later
+        );
+    }
+}
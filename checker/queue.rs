@@ -0,0 +1,135 @@
+//! Relaxed total-queue check of queue histories, in the spirit of Jepsen's
+//! `total-queue` checker.
+//!
+//! Unlike the linearizable (or sequential) search under the `Fifo` model, this
+//! only asks that every acknowledged message gets delivered exactly once,
+//! that nothing never enqueued gets delivered, and that messages of a single
+//! producer get delivered in the order they were sent. A queue that is not
+//! linearizable, e.g., reordering the messages of concurrent producers, may
+//! still satisfy it. Enqueues that completed with `:info`, or never completed, are
+//! indeterminate like in the rest of the checker: their messages may or may
+//! not get delivered.
+
+use std::collections::HashMap;
+
+use crate::types::{KeyType, OpData, OpSpan, Timeline, Timestamp, ValType, Value};
+
+/// Problems found by the total-queue check, each as (key, message) pairs.
+#[derive(Debug, Default)]
+pub struct TotalQueueReport {
+    /// Acknowledged enqueues.
+    pub enqueued: usize,
+    /// Dequeues that returned a message.
+    pub dequeued: usize,
+
+    /// Acknowledged messages never dequeued.
+    pub lost: Vec<(KeyType, ValType)>,
+    /// Messages dequeued more times than they were enqueued.
+    pub duplicated: Vec<(KeyType, ValType)>,
+    /// Messages dequeued but never enqueued.
+    pub unexpected: Vec<(KeyType, ValType)>,
+    /// Messages of one producer dequeued out of order, as (key, earlier
+    /// enqueued, later enqueued but surely dequeued before).
+    pub reordered: Vec<(KeyType, ValType, ValType)>,
+}
+
+impl TotalQueueReport {
+    pub fn is_valid(&self) -> bool {
+        self.lost.is_empty()
+            && self.duplicated.is_empty()
+            && self.unexpected.is_empty()
+            && self.reordered.is_empty()
+    }
+}
+
+/// Enqueue and dequeue occurrences of one message.
+#[derive(Default)]
+struct Deliveries<'a> {
+    acked: usize,
+    indeterminate: usize,
+    enqueues: Vec<&'a OpSpan>,
+    dequeues: Vec<&'a OpSpan>,
+}
+
+/// Check the queue operations of a timeline under total-queue semantics.
+/// Operations of other types are ignored.
+pub fn check_total_queue(timeline: &Timeline) -> TotalQueueReport {
+    let mut report = TotalQueueReport::default();
+    let mut messages: HashMap<(KeyType, ValType), Deliveries> = HashMap::new();
+
+    for span in timeline.queues.iter().flatten() {
+        match &span.data {
+            OpData::Enqueue { key, val } => {
+                let entry = messages.entry((key.clone(), val.clone())).or_default();
+                if span.indeterminate {
+                    entry.indeterminate += 1;
+                } else {
+                    entry.acked += 1;
+                    report.enqueued += 1;
+                }
+                entry.enqueues.push(span);
+            }
+            OpData::Dequeue { key, val } if *val != Value::Nil => {
+                let entry = messages.entry((key.clone(), val.clone())).or_default();
+                entry.dequeues.push(span);
+                report.dequeued += 1;
+            }
+            _ => {}
+        }
+    }
+
+    for (msg, deliveries) in &messages {
+        let dequeued = deliveries.dequeues.len();
+        if deliveries.acked + deliveries.indeterminate == 0 {
+            report.unexpected.push(msg.clone());
+        } else if dequeued > deliveries.acked + deliveries.indeterminate {
+            report.duplicated.push(msg.clone());
+        } else if dequeued < deliveries.acked {
+            report.lost.push(msg.clone());
+        }
+    }
+
+    // per-producer order, for messages enqueued and dequeued exactly once
+    let delivery = |span: &OpSpan| -> Option<(Timestamp, Timestamp)> {
+        let OpData::Enqueue { key, val } = &span.data else {
+            return None;
+        };
+        let deliveries = &messages[&(key.clone(), val.clone())];
+        match (
+            deliveries.enqueues.as_slice(),
+            deliveries.dequeues.as_slice(),
+        ) {
+            ([_], [deq]) => Some((deq.invoke, deq.finish)),
+            _ => None,
+        }
+    };
+    for queue in &timeline.queues {
+        // earlier message of the producer whose dequeue was invoked latest
+        let mut latest: HashMap<KeyType, (&ValType, Timestamp)> = HashMap::new();
+        for span in queue.iter().filter(|span| !span.indeterminate) {
+            let Some((deq_invoke, deq_finish)) = delivery(span) else {
+                continue;
+            };
+            let OpData::Enqueue { key, val } = &span.data else {
+                unreachable!();
+            };
+            match latest.get(key) {
+                Some(&(prev, prev_deq_invoke)) if deq_finish < prev_deq_invoke => {
+                    report
+                        .reordered
+                        .push((key.clone(), prev.clone(), val.clone()));
+                }
+                Some(&(_, prev_deq_invoke)) if deq_invoke <= prev_deq_invoke => {}
+                _ => {
+                    latest.insert(key.clone(), (val, deq_invoke));
+                }
+            }
+        }
+    }
+
+    report.lost.sort();
+    report.duplicated.sort();
+    report.unexpected.sort();
+    report.reordered.sort();
+    report
+}
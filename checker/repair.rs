@@ -0,0 +1,226 @@
+//! Lenient handling of malformed histories, e.g., ones left partially
+//! corrupted by a crashed test controller.
+//!
+//! Every anomaly found while parsing the history or building its timeline is
+//! reported to a `Repair`, which either aborts with it as the error or records
+//! it and lets the caller repair the history according to the policy. The
+//! anomalies recorded, with how each got repaired, are left to the caller to
+//! report (see `Repair::anomalies()` and `Repair::summary()`).
+
+use std::collections::BTreeMap;
+use std::fmt;
+
+use clap::ValueEnum;
+
+use crate::error::{Error, Result};
+
+/// Policy of handling anomalies in a history.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum)]
+pub enum RepairPolicy {
+    /// Stop at the first anomaly, returning it as the error.
+    #[default]
+    Abort,
+    /// Skip malformed lines, but stop at the first violation of the client
+    /// protocol like `abort`, invocations left flying at the end of the
+    /// history aside: those are kept unterminated.
+    Skip,
+    /// Drop the offending line, event, or operation.
+    Drop,
+    /// Like `drop`, but close a dangling invocation as indeterminate, i.e.,
+    /// it may or may not have taken effect, as if it completed with `:info`.
+    Close,
+}
+
+impl fmt::Display for RepairPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                RepairPolicy::Abort => "abort",
+                RepairPolicy::Skip => "skip",
+                RepairPolicy::Drop => "drop",
+                RepairPolicy::Close => "close",
+            }
+        )
+    }
+}
+
+/// How an anomaly got repaired.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum RepairAction {
+    /// Malformed line skipped.
+    SkippedLine,
+    /// Completion event ignored.
+    DroppedEvent,
+    /// Operation removed from the timeline.
+    DroppedOp,
+    /// Operation kept as indeterminate.
+    ClosedOp,
+}
+
+impl fmt::Display for RepairAction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                RepairAction::SkippedLine => "skipped line",
+                RepairAction::DroppedEvent => "dropped event",
+                RepairAction::DroppedOp => "dropped op",
+                RepairAction::ClosedOp => "closed op",
+            }
+        )
+    }
+}
+
+/// An anomaly found in the history, with the action taken.
+#[derive(Debug)]
+pub struct Anomaly {
+    pub error: Error,
+    pub action: RepairAction,
+}
+
+impl Anomaly {
+    /// Short name of the anomaly kind, for summarizing.
+    pub fn kind(&self) -> &'static str {
+        match self.error {
+            Error::Parse { .. } => "malformed line",
+            Error::InvokeWhileFlying { .. } => "invoke while flying",
+            Error::CompleteWhenIdle { .. } => "completion when idle",
+            Error::MismatchedCompletion { .. } => "mismatched completion",
+            Error::DanglingInvoke { .. } => "dangling invoke",
+            Error::InvalidRmwResult { .. } => "invalid rmw result",
+            _ => "other",
+        }
+    }
+}
+
+/// Log of anomalies found under a repair policy.
+#[derive(Debug, Default)]
+pub struct Repair {
+    policy: RepairPolicy,
+    anomalies: Vec<Anomaly>,
+}
+
+impl Repair {
+    pub fn new(policy: RepairPolicy) -> Self {
+        Repair {
+            policy,
+            anomalies: vec![],
+        }
+    }
+
+    pub fn policy(&self) -> RepairPolicy {
+        self.policy
+    }
+
+    pub fn anomalies(&self) -> &[Anomaly] {
+        &self.anomalies
+    }
+
+    /// Report an anomaly to be repaired with the given action. Returns the
+    /// anomaly back as the error unless the policy repairs it.
+    pub(crate) fn report(&mut self, error: Error, action: RepairAction) -> Result<()> {
+        match (self.policy, action) {
+            (RepairPolicy::Abort, _) => return Err(error),
+            (RepairPolicy::Skip, action) if action != RepairAction::SkippedLine => {
+                return Err(error);
+            }
+            _ => {}
+        }
+        self.anomalies.push(Anomaly { error, action });
+        Ok(())
+    }
+
+    /// Number of anomalies of each kind and action.
+    pub fn summary(&self) -> BTreeMap<(&'static str, RepairAction), usize> {
+        let mut counts = BTreeMap::new();
+        for anomaly in &self.anomalies {
+            *counts.entry((anomaly.kind(), anomaly.action)).or_default() += 1;
+        }
+        counts
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::check::Checker;
+    use crate::store::{fixture_dir, parse_history_with};
+    use crate::types::{Consistency, Timeline};
+
+    /// Each repair policy handles a malformed line and a dangling write its own
+    /// way: `abort` stops at the line, `skip` skips it and keeps the write left
+    /// flying at the end unterminated, passing a read of its value, `drop`
+    /// drops the write, failing the read, and `close` keeps it as
+    /// indeterminate, passing the read.
+    #[test]
+    fn repair_policies_handle_anomalies() {
+        let history = "\
+    {:index 0, :time 10, :type :invoke, :process 0, :f :write, :value [0 1]}
+    {:index 1, :time 20, :ty
+    {:index 2, :time 30, :type :ok, :process 0, :f :write, :value [0 1]}
+    {:index 3, :time 40, :type :invoke, :process 1, :f :write, :value [0 2]}
+    {:index 4, :time 50, :type :invoke, :process 2, :f :read, :value [0 nil]}
+    {:index 5, :time 60, :type :ok, :process 2, :f :read, :value [0 2]}
+    ";
+        let dir = fixture_dir("repair", &[("history.edn", history)]);
+        let skipped = ("malformed line", RepairAction::SkippedLine);
+
+        let mut repair = Repair::new(RepairPolicy::Abort);
+        assert!(matches!(
+            parse_history_with(&dir, &mut repair),
+            Err(Error::Parse { line: 2, .. })
+        ));
+        assert!(repair.anomalies().is_empty());
+
+        let mut repair = Repair::new(RepairPolicy::Skip);
+        let (events, max_client) = parse_history_with(&dir, &mut repair).expect("cannot parse");
+        assert_eq!(events.len(), 5);
+        assert_eq!(repair.summary(), BTreeMap::from([(skipped, 1)]));
+        let timeline =
+            Timeline::with_repair(events, max_client, &mut repair).expect("invalid history");
+        assert_eq!(repair.summary(), BTreeMap::from([(skipped, 1)]));
+        assert!(!timeline.queues[1][0].terminated());
+        let mut checker = Checker::new(timeline);
+        checker.set_quiet();
+        assert_eq!(
+            checker.check().expect("checker failed"),
+            Consistency::Linearizable
+        );
+
+        for (policy, action, level) in [
+            (
+                RepairPolicy::Drop,
+                RepairAction::DroppedOp,
+                Consistency::Weak,
+            ),
+            (
+                RepairPolicy::Close,
+                RepairAction::ClosedOp,
+                Consistency::Linearizable,
+            ),
+        ] {
+            let mut repair = Repair::new(policy);
+            let (events, max_client) = parse_history_with(&dir, &mut repair).expect("cannot parse");
+            let timeline =
+                Timeline::with_repair(events, max_client, &mut repair).expect("invalid history");
+            assert_eq!(
+                repair.summary(),
+                BTreeMap::from([(skipped, 1), (("dangling invoke", action), 1)]),
+                "{}",
+                policy
+            );
+            let mut checker = Checker::new(timeline);
+            checker.set_quiet();
+            assert_eq!(
+                checker.check().expect("checker failed"),
+                level,
+                "{}",
+                policy
+            );
+        }
+        let _ = std::fs::remove_dir_all(dir);
+    }
+}
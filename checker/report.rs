@@ -0,0 +1,440 @@
+//! Attribution of violations to the nemesis faults of a history, and the
+//! machine-readable report of a check.
+//!
+//! A violation is located by the `:time` of an event, which falls in a period
+//! of the faults (see `Period`). It was witnessed by the client of the
+//! operation with that event, bound to a node that may be on one side of a
+//! partition then.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::path::Path;
+
+use crate::error::Result;
+use crate::json::Json;
+use crate::nemesis::{FaultWindow, Period, Side, parse_faults, period_at, periods};
+use crate::nodes::ClientNodes;
+use crate::stats::TimelineStats;
+use crate::types::{ClientId, Consistency, OpData, OpSpan, Timeline, Timestamp};
+use crate::validate::DataAnomaly;
+
+/// Who witnessed a violation.
+#[derive(Debug, Clone)]
+pub struct Witness {
+    pub client: ClientId,
+    /// Node the client was bound to, if known.
+    pub node: Option<String>,
+    /// Side of the node in a partition active then, if any.
+    pub side: Option<Side>,
+    /// Operation with an event at the time of the violation.
+    pub op: OpData,
+}
+
+impl fmt::Display for Witness {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "client {}", self.client)?;
+        if let Some(node) = &self.node {
+            write!(f, " on ")?;
+            if let Some(side) = self.side {
+                write!(f, "{} ", side)?;
+            }
+            write!(f, "{}", node)?;
+        }
+        write!(f, ": {}", self.op)
+    }
+}
+
+impl Witness {
+    fn to_json(&self) -> Json {
+        Json::object([
+            ("client", self.client.into()),
+            ("node", self.node.clone().into()),
+            ("side", self.side.map(|side| side.to_string()).into()),
+            ("op", self.op.to_string().into()),
+        ])
+    }
+}
+
+/// Violation found at a time, with who witnessed it if known.
+#[derive(Debug, Clone)]
+pub struct Violation {
+    pub time: Timestamp,
+    pub what: String,
+    pub witness: Option<Witness>,
+}
+
+/// Violations found in a period of the faults.
+#[derive(Debug, Clone)]
+pub struct PeriodVerdict {
+    pub period: Period,
+    /// Violations found in the period, by time.
+    pub violations: Vec<Violation>,
+    /// Were all violations looked for? If not, a period without any found
+    /// may still have some.
+    pub complete: bool,
+}
+
+impl PeriodVerdict {
+    /// Whether the period is free of violations, unless unknown.
+    pub fn holds(&self) -> Option<bool> {
+        if !self.violations.is_empty() {
+            Some(false)
+        } else if self.complete {
+            Some(true)
+        } else {
+            None
+        }
+    }
+
+    fn to_json(&self) -> Json {
+        let violations = self
+            .violations
+            .iter()
+            .map(|violation| {
+                Json::object([
+                    ("time", violation.time.into()),
+                    ("what", violation.what.as_str().into()),
+                    (
+                        "witness",
+                        violation
+                            .witness
+                            .as_ref()
+                            .map_or(Json::Null, Witness::to_json),
+                    ),
+                ])
+            })
+            .collect();
+        Json::object([
+            ("period", self.period.to_string().into()),
+            ("holds", self.holds().into()),
+            ("violations", Json::Array(violations)),
+        ])
+    }
+}
+
+/// Fault windows of a history, with what tells who witnessed a violation
+/// during them.
+#[derive(Debug, Clone, Default)]
+pub struct Attribution {
+    pub windows: Vec<FaultWindow>,
+    /// Operations by the `:time` of their events, which are distinct.
+    ops: HashMap<Timestamp, OpSpan>,
+    /// Times of the events by `:index`.
+    times: HashMap<u64, Timestamp>,
+    /// Node each client was bound to, if known.
+    nodes: Option<ClientNodes>,
+}
+
+impl Attribution {
+    /// Attribute the violations in the timeline to the given faults, with
+    /// the node of each client if known.
+    pub fn new(windows: Vec<FaultWindow>, timeline: &Timeline, nodes: Option<ClientNodes>) -> Self {
+        let mut attribution = Attribution {
+            windows,
+            nodes,
+            ..Default::default()
+        };
+        for span in timeline.queues.iter().flatten() {
+            attribution.times.insert(span.index, span.invoke);
+            attribution.ops.insert(span.invoke, span.clone());
+            if let Some(index) = span.finish_index {
+                attribution.times.insert(index, span.finish);
+                attribution.ops.insert(span.finish, span.clone());
+            }
+        }
+        attribution
+    }
+
+    /// Read the faults of the history in the store directory, and the node
+    /// of each client from the given file, or else from the test config.
+    pub fn load(test_dir: &Path, timeline: &Timeline, client_nodes: Option<&Path>) -> Result<Self> {
+        let windows = parse_faults(test_dir)?;
+        if windows.is_empty() {
+            return Ok(Attribution::default());
+        }
+        let nodes = match client_nodes {
+            Some(path) => Some(ClientNodes::read_file(path)?),
+            None => ClientNodes::from_test_dir(test_dir)?,
+        };
+        Ok(Attribution::new(windows, timeline, nodes))
+    }
+
+    /// Time of the event with the given `:index`, if in the timeline.
+    pub fn time_of(&self, index: u64) -> Option<Timestamp> {
+        self.times.get(&index).copied()
+    }
+
+    /// Who witnessed a violation at the time: the client of the operation
+    /// with an event then, and the node it was bound to, with its side of a
+    /// partition.
+    pub fn witness(&self, time: Timestamp) -> Option<Witness> {
+        let span = self.ops.get(&time)?;
+        let node = self
+            .nodes
+            .as_ref()
+            .and_then(|nodes| nodes.node_of(span.client));
+        let side = match (period_at(&self.windows, time), node) {
+            (Period::During(nth), Some(node)) => self.windows[nth - 1].fault.side_of(node),
+            _ => None,
+        };
+        Some(Witness {
+            client: span.client,
+            node: node.map(str::to_string),
+            side,
+            op: span.data.clone(),
+        })
+    }
+
+    /// Group the violations found (with their times) by the period of the
+    /// faults they happened in, for every period in order, or none without
+    /// faults. Unless all violations were looked for, periods without any
+    /// found are left unknown.
+    pub fn attribute(
+        &self,
+        mut violations: Vec<(Timestamp, String)>,
+        complete: bool,
+    ) -> Vec<PeriodVerdict> {
+        if self.windows.is_empty() {
+            return vec![];
+        }
+        let complete = complete || violations.is_empty();
+        violations.sort();
+        let mut by_period: HashMap<Period, Vec<Violation>> = HashMap::new();
+        for (time, what) in violations {
+            by_period
+                .entry(period_at(&self.windows, time))
+                .or_default()
+                .push(Violation {
+                    time,
+                    what,
+                    witness: self.witness(time),
+                });
+        }
+        periods(&self.windows)
+            .into_iter()
+            .map(|period| PeriodVerdict {
+                period,
+                violations: by_period.remove(&period).unwrap_or_default(),
+                complete,
+            })
+            .collect()
+    }
+}
+
+/// Outcome of checking a history, as written by `--report`.
+#[derive(Debug, Clone)]
+pub struct Report {
+    pub test_dir: String,
+    /// Strongest level satisfied among the checked ones.
+    pub result: Consistency,
+    /// Did the history satisfy every checked level?
+    pub valid: bool,
+    /// Anomalies found by validating a register history, violations or not.
+    pub anomalies: Vec<DataAnomaly>,
+    pub faults: Vec<FaultWindow>,
+    /// Violations found in each period of the faults.
+    pub periods: Vec<PeriodVerdict>,
+    pub stats: TimelineStats,
+}
+
+impl Report {
+    /// Render as a JSON object.
+    pub fn to_json(&self) -> Json {
+        let faults = self
+            .faults
+            .iter()
+            .map(|window| {
+                Json::object([
+                    ("fault", window.fault.to_string().into()),
+                    ("start", window.start.into()),
+                    ("stop", window.stop.into()),
+                ])
+            })
+            .collect();
+        Json::object([
+            ("test_dir", self.test_dir.as_str().into()),
+            ("result", format!("{:?}", self.result).into()),
+            ("valid", self.valid.into()),
+            (
+                "anomalies",
+                Json::Array(
+                    self.anomalies
+                        .iter()
+                        .map(|anomaly| {
+                            Json::object([
+                                ("index", anomaly.index().into()),
+                                ("what", anomaly.to_string().into()),
+                                ("violation", anomaly.is_violation().into()),
+                            ])
+                        })
+                        .collect(),
+                ),
+            ),
+            ("faults", Json::Array(faults)),
+            (
+                "periods",
+                Json::Array(self.periods.iter().map(PeriodVerdict::to_json).collect()),
+            ),
+            ("stats", self.stats.to_json()),
+        ])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::check::Checker;
+    use crate::error::Error;
+    use crate::nodes::JEPSEN_LOG_FILE;
+    use crate::store::{fixture_dir, parse_history};
+
+    /// A violation is attributed to the fault window it happened in, witnessed by
+    /// the client on the minority side of the partition.
+    #[test]
+    fn violations_are_attributed_to_fault_windows() {
+        // client 0 reads 1 after client 1 overwrote it with 2, during a partition
+        // isolating n1, the node of client 0
+        let history = r#"{:index 0, :time 10, :type :invoke, :process 0, :f :write, :value [0 1]}
+    {:index 1, :time 20, :type :ok, :process 0, :f :write, :value [0 1]}
+    {:index 2, :time 30, :type :info, :process :nemesis, :f :start, :value nil}
+    {:index 3, :time 31, :type :info, :process :nemesis, :f :start, :value [:isolated {"n1" #{"n2" "n3"}, "n2" #{"n1"}, "n3" #{"n1"}}]}
+    {:index 4, :time 40, :type :invoke, :process 1, :f :write, :value [0 2]}
+    {:index 5, :time 50, :type :ok, :process 1, :f :write, :value [0 2]}
+    {:index 6, :time 60, :type :invoke, :process 0, :f :read, :value [0 nil]}
+    {:index 7, :time 70, :type :ok, :process 0, :f :read, :value [0 1]}
+    {:index 8, :time 80, :type :info, :process :nemesis, :f :stop, :value nil}
+    {:index 9, :time 81, :type :info, :process :nemesis, :f :stop, :value :network-healed}
+    {:index 10, :time 90, :type :info, :process :nemesis, :f :start, :value nil}
+    {:index 11, :time 91, :type :info, :process :nemesis, :f :start, :value :killed}
+    {:index 12, :time 100, :type :invoke, :process 1, :f :read, :value [0 nil]}
+    {:index 13, :time 110, :type :ok, :process 1, :f :read, :value [0 2]}
+    "#;
+        let dir = fixture_dir("faults", &[("history.edn", history)]);
+        let windows = parse_faults(&dir).expect("cannot parse faults");
+        let (events, max_client) = parse_history(&dir).expect("cannot parse");
+        let timeline = Timeline::new(events, max_client).expect("invalid history");
+        let nodes = ClientNodes::Nodes(vec!["n1".to_string(), "n2".to_string(), "n3".to_string()]);
+        let attribution = Attribution::new(windows, &timeline, Some(nodes));
+        let mut checker = Checker::new(timeline);
+        checker.set_quiet();
+        let verdicts = checker.check_report().expect("checker failed");
+        let violations = verdicts[0]
+            .violations
+            .iter()
+            .map(|(key, &time)| (time, format!("non-linearizable key {}", key)))
+            .collect();
+        let periods = attribution.attribute(violations, true);
+
+        assert_eq!(
+            periods
+                .iter()
+                .map(|verdict| verdict.period)
+                .collect::<Vec<_>>(),
+            [
+                Period::Before,
+                Period::During(1),
+                Period::After(1),
+                Period::During(2),
+                Period::After(2),
+            ]
+        );
+        for verdict in &periods {
+            let holds = verdict.period != Period::During(1);
+            assert_eq!(verdict.holds(), Some(holds), "{}", verdict.period);
+        }
+        let witness = periods[1].violations[0]
+            .witness
+            .as_ref()
+            .expect("no witness");
+        assert_eq!(witness.client, 0);
+        assert_eq!(witness.node.as_deref(), Some("n1"));
+        assert_eq!(witness.side, Some(Side::Minority));
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    /// Witnesses are bound to nodes by the test's config in `jepsen.log`, unless a
+    /// client nodes file maps them; a client it leaves out has no node, and a
+    /// malformed file is an error.
+    #[test]
+    fn witnesses_come_from_client_nodes() {
+        // partition isolating n3, the node of process 5 by default
+        let history = r#"{:index 0, :time 10, :type :info, :process :nemesis, :f :start, :value nil}
+    {:index 1, :time 11, :type :info, :process :nemesis, :f :start, :value [:isolated {"n3" #{"n1" "n2"}, "n1" #{"n3"}, "n2" #{"n3"}}]}
+    {:index 2, :time 20, :type :invoke, :process 5, :f :write, :value [0 1]}
+    {:index 3, :time 30, :type :ok, :process 5, :f :write, :value [0 1]}
+    {:index 4, :time 40, :type :invoke, :process 1, :f :read, :value [0 nil]}
+    {:index 5, :time 50, :type :ok, :process 1, :f :read, :value [0 1]}
+    {:index 6, :time 60, :type :info, :process :nemesis, :f :stop, :value nil}
+    {:index 7, :time 61, :type :info, :process :nemesis, :f :stop, :value :network-healed}
+    "#;
+        let log = |nodes: &str| {
+            format!(
+                "\
+    2024-05-01 12:00:00,000{{GMT}}\tINFO\t[jepsen test runner] jepsen.core: Running test:
+    {{:concurrency 3,
+     :name \"register\",
+    {} :ssh {{:username \"root\"}}}}
+    2024-05-01 12:00:01,000{{GMT}}\tINFO\t[jepsen test runner] jepsen.core: Done
+    ",
+                nodes
+            )
+        };
+        let dir = fixture_dir(
+            "nodes",
+            &[
+                ("history.edn", history),
+                (JEPSEN_LOG_FILE, &log(" :nodes [\"n1\" \"n2\" \"n3\"],\n")),
+                ("mapped.edn", "{5 \"n1\", 1 :n3}"),
+                ("incomplete.edn", "{5 \"n1\"}"),
+                ("malformed.edn", "{5 [\"n1\"]}"),
+                ("truncated.edn", "{5 \"n1\", 1"),
+            ],
+        );
+        let (events, max_client) = parse_history(&dir).expect("cannot parse");
+        let timeline = Timeline::new(events, max_client).expect("invalid history");
+        let witnessed = |client_nodes: Option<&str>| {
+            let path = client_nodes.map(|file| dir.join(file));
+            let attribution =
+                Attribution::load(&dir, &timeline, path.as_deref()).expect("cannot load nodes");
+            [20, 40].map(|time| {
+                let witness = attribution.witness(time).expect("no witness");
+                (witness.client, witness.node, witness.side)
+            })
+        };
+        let on = |node: &str| Some(node.to_string());
+
+        assert_eq!(
+            witnessed(None),
+            [
+                (5, on("n3"), Some(Side::Minority)),
+                (1, on("n2"), Some(Side::Majority)),
+            ]
+        );
+        assert_eq!(
+            witnessed(Some("mapped.edn")),
+            [
+                (5, on("n1"), Some(Side::Majority)),
+                (1, on("n3"), Some(Side::Minority)),
+            ]
+        );
+        assert_eq!(
+            witnessed(Some("incomplete.edn")),
+            [(5, on("n1"), Some(Side::Majority)), (1, None, None)]
+        );
+        let attribution = Attribution::load(&dir, &timeline, None).expect("cannot load nodes");
+        let witness = attribution.witness(20).expect("no witness");
+        assert!(
+            witness
+                .to_string()
+                .starts_with("client 5 on minority-side n3: "),
+            "{}",
+            witness
+        );
+        assert!(matches!(
+            Attribution::load(&dir, &timeline, Some(&dir.join("malformed.edn"))),
+            Err(Error::Config(_))
+        ));
+        assert!(ClientNodes::read_file(&dir.join("truncated.edn")).is_err());
+        let _ = std::fs::remove_dir_all(dir);
+    }
+}
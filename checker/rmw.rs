@@ -0,0 +1,128 @@
+//! Registry of read-modify-write functions on registers.
+//!
+//! Every RMW operation records the value it read (`rval`) and the value it
+//! wrote (`wval`), and some functions take an argument as well. A function
+//! decides how these are laid out in the `:value` field, whether the recorded
+//! `wval` indeed follows from `rval`, and in which register states the
+//! operation is legal. Supporting a new function means adding a variant here.
+//!
+//! A completed RMW whose recorded `wval` does not follow from its `rval` (and
+//! argument) is an invalid result, handled like a protocol violation under the
+//! repair policy.
+
+use std::fmt;
+
+use crate::types::{ValType, Value};
+
+/// Read-modify-write function of an `OpData::Rmw`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum RmwFunc {
+    /// `:cas [k [old new]]`, writes `new` if the value is `old`.
+    Cas,
+    /// `:swap [k [old new]]`, writes `new` and returns the `old` value.
+    Swap,
+    /// `:fetch-add [k [delta old new]]`, adds `delta` and returns the `old`
+    /// value; `new` must be `old + delta`.
+    FetchAdd,
+    /// `:put-if-version [k [version new]]`, writes `new` if the register is
+    /// at `version`, i.e., has been modified that many times since it was
+    /// last deleted (like etcd's per-key version).
+    PutIfVersion,
+    /// `:incr-if [k [old new]]`, increments the value if it is `old`; `new`
+    /// must be `old + 1`. Like for `:fetch-add`, a nil register counts as 0.
+    IncrIf,
+}
+
+/// Integer value of a register, where nil counts as 0.
+fn int_or_zero(val: &ValType) -> Option<i64> {
+    match val {
+        Value::Nil => Some(0),
+        Value::Int(i) => Some(*i),
+        _ => None,
+    }
+}
+
+impl RmwFunc {
+    pub const ALL: [RmwFunc; 5] = [
+        RmwFunc::Cas,
+        RmwFunc::Swap,
+        RmwFunc::FetchAdd,
+        RmwFunc::PutIfVersion,
+        RmwFunc::IncrIf,
+    ];
+
+    /// Look up a function by its `:f` keyword.
+    pub fn from_type(s: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|func| func.to_type() == s)
+    }
+
+    pub fn to_type(self) -> &'static str {
+        match self {
+            RmwFunc::Cas => ":cas",
+            RmwFunc::Swap => ":swap",
+            RmwFunc::FetchAdd => ":fetch-add",
+            RmwFunc::PutIfVersion => ":put-if-version",
+            RmwFunc::IncrIf => ":incr-if",
+        }
+    }
+
+    /// Does the `:value` carry an argument before the read and written values?
+    pub fn has_arg(self) -> bool {
+        matches!(self, RmwFunc::FetchAdd)
+    }
+
+    /// Are the read and written values inputs known at invocation, rather
+    /// than results? Only then can an invocation that never completed be
+    /// kept as indeterminate.
+    pub fn inputs_known(self) -> bool {
+        matches!(self, RmwFunc::Cas | RmwFunc::PutIfVersion | RmwFunc::IncrIf)
+    }
+
+    /// Is `rval` exactly the value the operation observed? Not so for
+    /// functions comparing versions, nor for ones taking a nil register as 0
+    /// when `rval` is 0.
+    pub fn reads_value(self, rval: &ValType) -> bool {
+        match self {
+            RmwFunc::Cas | RmwFunc::Swap => true,
+            RmwFunc::IncrIf => *rval != Value::Int(0),
+            RmwFunc::FetchAdd | RmwFunc::PutIfVersion => false,
+        }
+    }
+
+    /// Does the recorded written value follow from the read value?
+    pub fn validate(self, arg: &ValType, rval: &ValType, wval: &ValType) -> bool {
+        match self {
+            RmwFunc::Cas | RmwFunc::Swap => true,
+            RmwFunc::FetchAdd => match (int_or_zero(rval), arg, wval) {
+                (Some(old), Value::Int(delta), Value::Int(new)) => {
+                    old.checked_add(*delta) == Some(*new)
+                }
+                _ => false,
+            },
+            RmwFunc::PutIfVersion => matches!(rval, Value::Int(version) if *version >= 0),
+            RmwFunc::IncrIf => match (int_or_zero(rval), wval) {
+                (Some(old), Value::Int(new)) => old.checked_add(1) == Some(*new),
+                _ => false,
+            },
+        }
+    }
+
+    /// Is the operation legal on a register holding `val` at `version`?
+    pub fn legal(self, val: &ValType, version: u64, rval: &ValType) -> bool {
+        match self {
+            RmwFunc::PutIfVersion => *rval == Value::Int(version as i64),
+            RmwFunc::Cas | RmwFunc::Swap => rval == val,
+            // a nil register counts as 0
+            RmwFunc::FetchAdd | RmwFunc::IncrIf => matches!(
+                (int_or_zero(rval), int_or_zero(val)),
+                (Some(old), Some(cur)) if old == cur
+            ),
+        }
+    }
+}
+
+impl fmt::Display for RmwFunc {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.to_type().trim_start_matches(':'))
+    }
+}
@@ -0,0 +1,361 @@
+//! Checking modes behind the command line interface: loading a stored
+//! history, checking it against the levels or the stored Knossos verdicts,
+//! and printing what was found along the way.
+//!
+//! Each run returns whether the history passed, i.e., whether the strongest
+//! level checked holds (diff mode: whether both checkers agree).
+
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use clap::ValueEnum;
+
+use crate::append::AppendChecker;
+use crate::check::{Checker, result_level};
+use crate::counter::CounterChecker;
+use crate::diff::{compare_verdicts, drop_nil_reads, read_knossos_verdicts};
+use crate::error::{Error, Result};
+use crate::model::{Fifo, GrowSet, Model, ModelKind};
+use crate::print::{
+    print_data_anomalies, print_latency_stats, print_periods, print_repairs, print_timeline_stats,
+    print_total_queue,
+};
+use crate::queue::check_total_queue;
+use crate::repair::{Repair, RepairPolicy};
+use crate::report::{Attribution, PeriodVerdict, Report};
+use crate::stats::timeline_stats;
+use crate::store::{HISTORY_FILE, HistoryWriter, parse_history_with};
+use crate::txn::TxnChecker;
+use crate::types::{Consistency, Timeline, Timestamp};
+use crate::validate::validate_registers;
+
+/// How to attribute violations to the faults of the nemesis.
+#[derive(Debug, Clone)]
+pub struct Attributing {
+    pub all_keys: bool,
+    pub client_nodes: Option<String>,
+}
+
+/// Relaxations of the real-time order accepted by the checked levels.
+#[derive(Debug, Clone, Copy)]
+pub struct Relaxation {
+    pub epsilon: Timestamp,
+    pub max_k: Option<u64>,
+    pub max_delta: Option<Timestamp>,
+}
+
+/// Bounds on the search, past which the check fails with an error rather
+/// than exhausting time or memory.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Limits {
+    pub time: Option<Duration>,
+    pub possib: Option<usize>,
+}
+
+/// What to report beside the verdict.
+#[derive(Debug, Clone)]
+pub struct Reporting {
+    pub path: Option<String>,
+    pub rate_bucket: Timestamp,
+}
+
+/// Load the history of a test directory into a timeline, repairing anomalies
+/// according to the policy.
+pub fn load_timeline(test_dir: &str, policy: RepairPolicy) -> Result<Timeline> {
+    eprintln!("Test directory: '{}'", test_dir);
+
+    let mut repair = Repair::new(policy);
+    let (events, max_client) = parse_history_with(Path::new(test_dir), &mut repair)?;
+    if events.is_empty() {
+        return Err(Error::Malformed {
+            path: Path::new(test_dir).join(HISTORY_FILE),
+            line: None,
+            reason: "empty history".to_string(),
+        });
+    }
+
+    let timeline = Timeline::with_repair(events, max_client, &mut repair);
+    print_repairs(&repair);
+    let timeline = timeline?;
+    print_timeline_stats(&timeline);
+    Ok(timeline)
+}
+
+/// Default mode: check the history and report the strongest level satisfied.
+#[allow(clippy::too_many_arguments)]
+pub fn check_run(
+    test_dir: &str,
+    levels: &[Consistency],
+    repair: RepairPolicy,
+    model: ModelKind,
+    attributing: Attributing,
+    relax: Relaxation,
+    limits: Limits,
+    reporting: Reporting,
+    start_ts: Instant,
+) -> Result<bool> {
+    let default_levels = [if model == ModelKind::Txn {
+        Consistency::StrictSerializable
+    } else {
+        Consistency::Linearizable
+    }];
+    let levels = if levels.is_empty() {
+        &default_levels[..]
+    } else {
+        levels
+    };
+    let searched = !matches!(model, ModelKind::Counter | ModelKind::List | ModelKind::Txn);
+    if let Some(level) = levels.iter().find(|&&level| match level {
+        Consistency::Weak => false,
+        // checked by its bad patterns of register operations
+        Consistency::Causal => model != ModelKind::Register,
+        _ if level.is_staleness() => !searched,
+        _ => level.is_transactional() != (model == ModelKind::Txn),
+    }) {
+        return Err(Error::Config(format!(
+            "level {} does not apply to --model {}",
+            level,
+            model.to_possible_value().unwrap().get_name()
+        )));
+    }
+    if relax.epsilon > 0 && !searched {
+        return Err(Error::Config(format!(
+            "--epsilon does not apply to --model {}",
+            model.to_possible_value().unwrap().get_name()
+        )));
+    }
+
+    let timeline = load_timeline(test_dir, repair)?;
+    let attribution = Attribution::load(
+        Path::new(test_dir),
+        &timeline,
+        attributing.client_nodes.as_deref().map(Path::new),
+    )?;
+    let stats = timeline_stats(&timeline, &attribution.windows, reporting.rate_bucket);
+    print_latency_stats(&stats, &attribution.windows);
+    let all_keys = attributing.all_keys;
+
+    let check_ts = Instant::now();
+    let mut total_queue = None;
+    let mut anomalies = vec![];
+    // violations are only located by the search
+    let mut periods = vec![];
+    // staleness levels that hold above the result, as they do not imply
+    // sequential consistency
+    let mut held = vec![];
+    let level = match model {
+        ModelKind::Register => {
+            anomalies = validate_registers(&timeline);
+            print_data_anomalies(&anomalies);
+            let mut checker = Checker::new(timeline);
+            // the levels an anomaly refutes fail on its key without a search,
+            // sequential consistency too as checked under the staleness ones
+            for anomaly in &anomalies {
+                let time = attribution.time_of(anomaly.index()).unwrap_or_default();
+                for &level in levels
+                    .iter()
+                    .chain(&[Consistency::Sequential])
+                    .filter(|&&level| anomaly.refutes(level))
+                {
+                    checker.refute(level, anomaly.key().clone(), time);
+                }
+            }
+            let level;
+            (level, held, periods) =
+                check_levels(checker, levels, relax, limits, &attribution, all_keys)?;
+            level
+        }
+        ModelKind::Set => {
+            let checker = Checker::with_model(timeline, GrowSet);
+            let level;
+            (level, held, periods) =
+                check_levels(checker, levels, relax, limits, &attribution, all_keys)?;
+            level
+        }
+        ModelKind::Queue => {
+            total_queue = Some(check_total_queue(&timeline));
+            let checker = Checker::with_model(timeline, Fifo);
+            let level;
+            (level, held, periods) =
+                check_levels(checker, levels, relax, limits, &attribution, all_keys)?;
+            level
+        }
+        ModelKind::Counter => {
+            let mut checker = CounterChecker::new(timeline)?;
+            checker.set_levels(levels);
+            checker.check()
+        }
+        ModelKind::List => {
+            let mut checker = AppendChecker::new(timeline)?;
+            checker.set_levels(levels);
+            checker.check()
+        }
+        ModelKind::Txn => {
+            let mut checker = TxnChecker::new(timeline)?;
+            checker.set_levels(levels);
+            if let Some(limit) = limits.time {
+                checker.set_time_limit(limit);
+            }
+            if let Some(limit) = limits.possib {
+                checker.set_possib_limit(limit);
+            }
+            checker.check()?
+        }
+    };
+    let finish_ts = Instant::now();
+
+    if let Some(report) = &total_queue {
+        print_total_queue(report);
+    }
+
+    // next stronger level among the checked ones, if any
+    let stronger = levels
+        .iter()
+        .filter(|&&l| l > level && !held.contains(&l))
+        .min();
+    let also: String = held.iter().map(|l| format!(", also {:?}", l)).collect();
+    println!(
+        "Checker result: {}",
+        match stronger {
+            None => format!("== {:?}{}, nice 👌", level, also),
+            Some(stronger) => format!(">= {:?}{} but < {:?} 🤔", level, also, stronger),
+        }
+    );
+    println!("    based on this specific history,");
+    println!("    could just be a loose upper bound");
+
+    println!(
+        "Time spent excluding I/O: {:.2} msecs",
+        (finish_ts.duration_since(check_ts).as_nanos() as f64) / 1_000_000.0
+    );
+    println!(
+        "Time spent in Rust total: {:.2} msecs",
+        (finish_ts.duration_since(start_ts).as_nanos() as f64) / 1_000_000.0
+    );
+
+    let valid = stronger.is_none() && total_queue.is_none_or(|report| report.is_valid());
+    if let Some(path) = &reporting.path {
+        let report = Report {
+            test_dir: test_dir.to_string(),
+            result: level,
+            valid,
+            anomalies,
+            faults: attribution.windows,
+            periods,
+            stats,
+        };
+        let report = format!("{}\n", report.to_json());
+        fs::write(path, report)?;
+        eprintln!("Report written to '{}'", path);
+    }
+    Ok(valid)
+}
+
+/// Strongest level satisfied, staleness levels that hold above it, and the
+/// violations found in each period of the faults.
+type LevelsOutcome = (Consistency, Vec<Consistency>, Vec<PeriodVerdict>);
+
+/// Check against the given levels, returning the strongest one satisfied and
+/// the staleness levels that hold above it, without sequential consistency.
+/// Prints the verdict of each level checked, and how it was reached, then the
+/// violations found during and around each fault, if any.
+fn check_levels<M: Model>(
+    mut checker: Checker<M>,
+    levels: &[Consistency],
+    relax: Relaxation,
+    limits: Limits,
+    attribution: &Attribution,
+    all_keys: bool,
+) -> Result<LevelsOutcome> {
+    checker.set_levels(levels);
+    if let Some(limit) = limits.time {
+        checker.set_time_limit(limit);
+    }
+    if let Some(limit) = limits.possib {
+        checker.set_possib_limit(limit);
+    }
+    if all_keys {
+        checker.set_exhaustive();
+    }
+    checker.set_epsilon(relax.epsilon);
+    if let Some(k) = relax.max_k {
+        checker.set_max_k(k);
+    }
+    if let Some(delta) = relax.max_delta {
+        checker.set_max_delta(delta);
+    }
+    let verdicts = checker.check_report()?;
+
+    println!("Level verdicts:");
+    for verdict in &verdicts {
+        let bound = match (verdict.level, verdict.bound) {
+            (Consistency::KAtomic, Some(k)) => format!("  (k = {})", k),
+            (Consistency::DeltaAtomic, Some(delta)) => format!("  (Δ = {})", delta),
+            (level, None) if level.is_staleness() => "  (unbounded)".to_string(),
+            _ => String::new(),
+        };
+        println!(
+            "    {:<13} {}  {}{}{}",
+            verdict.level.to_string(),
+            if verdict.holds { "✅" } else { "❌" },
+            verdict.scope,
+            bound,
+            if verdict.skewed {
+                "  (only within --epsilon)"
+            } else {
+                ""
+            }
+        );
+    }
+    let violations = verdicts
+        .iter()
+        .flat_map(|verdict| {
+            verdict
+                .violations
+                .iter()
+                .map(|(key, &time)| (time, format!("non-{} key {}", verdict.level, key)))
+        })
+        .collect();
+    let periods = attribution.attribute(violations, all_keys);
+    print_periods(&attribution.windows, &periods);
+    let level = result_level(&verdicts);
+    let held = verdicts
+        .iter()
+        .filter(|verdict| verdict.holds && verdict.level > level)
+        .map(|verdict| verdict.level)
+        .collect();
+    Ok((level, held, periods))
+}
+
+/// Diff mode: check the history at the level, then compare against Knossos
+/// verdicts.
+pub fn diff_run(test_dir: &str, level: Consistency, repair: RepairPolicy) -> Result<bool> {
+    let knossos = read_knossos_verdicts(Path::new(test_dir))?;
+
+    let mut timeline = load_timeline(test_dir, repair)?;
+    let dropped = drop_nil_reads(&mut timeline);
+    if dropped > 0 {
+        println!("Dropped {} reads of nil, wildcards to Knossos", dropped);
+    }
+    let mut checker = Checker::new(timeline);
+    checker.set_levels(&[level]);
+    let sop = checker.check_per_key()?;
+
+    Ok(compare_verdicts(&knossos, &sop, level))
+}
+
+/// Create a history writer into the given directory, or stdout if not given.
+pub fn history_writer(out_dir: Option<&str>) -> Result<HistoryWriter<Box<dyn Write>>> {
+    let out: Box<dyn Write> = match out_dir {
+        Some(dir) => {
+            fs::create_dir_all(dir)?;
+            Box::new(BufWriter::new(File::create(
+                Path::new(dir).join(HISTORY_FILE),
+            )?))
+        }
+        None => Box::new(BufWriter::new(io::stdout().lock())),
+    };
+    Ok(HistoryWriter::new(out))
+}
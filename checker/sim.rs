@@ -0,0 +1,913 @@
+//! Deterministic, seedable simulator of a small replicated key-value store with
+//! fault injection, serving as a local test target when no CloudLab nodes are
+//! at hand. Jepsen-like clients run against the simulated replicas, producing
+//! histories in the `history.edn` format that carry true `:tstag` values.
+//!
+//! Supported replication modes:
+//!   - `leader`: all ops are served by a fixed leader that only answers when it
+//!     can reach a majority, hence linearizable
+//!   - `primary-backup`: writes are served by a fixed primary and replicated
+//!     to backups asynchronously, while reads are served locally
+//!   - `quorum`: the client's node coordinates writes to a majority of
+//!     replicas (last-writer-wins by tag), while reads are sloppy and served
+//!     by the coordinator's local replica only
+//!
+//! Nodes honor request deadlines, so an operation that times out on the client
+//! side (`:info`) has never taken effect on the leader or primary, and a reply
+//! arriving right at the timeout wins. The timeline still keeps `:info`
+//! writes as indeterminate, as clients of a real store cannot tell either.
+//! In `quorum` mode, a timed-out write might have reached a minority, and so
+//! taken effect after all.
+
+use std::cmp::{Ordering, Reverse};
+use std::collections::{BinaryHeap, HashMap};
+use std::fmt;
+
+use clap::ValueEnum;
+
+use crate::error::{Error, Result};
+use crate::generator::Rng;
+use crate::rmw::RmwFunc;
+use crate::types::{
+    ClientId, Event, EventType, KeyType, OpData, Timestamp, UniqueTag, ValType, Value,
+};
+
+/// Replication mode of the simulated store.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ReplicationMode {
+    /// Leader-based, linearizable.
+    Leader,
+    /// Asynchronous primary-backup with local reads.
+    PrimaryBackup,
+    /// Majority-quorum writes with sloppy local reads.
+    Quorum,
+}
+
+/// Kinds of faults the nemesis can inject.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum FaultKind {
+    /// Partition the nodes into random halves.
+    Partition,
+    /// Add extra latency to all messages between nodes.
+    Delay,
+    /// Crash a random node, restarting it (with its data) on stop.
+    Crash,
+}
+
+/// Simulator configuration.
+#[derive(Debug, Clone)]
+pub struct SimConfig {
+    /// Random seed.
+    pub seed: u64,
+    /// Replication mode.
+    pub mode: ReplicationMode,
+    /// Number of replica nodes.
+    pub nodes: usize,
+    /// Number of concurrent clients, bound to nodes round-robin.
+    pub clients: usize,
+    /// Number of distinct keys.
+    pub keys: usize,
+    /// Total number of operations invoked.
+    pub ops: usize,
+    /// Values are drawn from `[0, value_range)`.
+    pub value_range: u64,
+    /// Relative weights of :read, :write, and :cas operations.
+    pub mix: [u64; 3],
+    /// Faults to pick from; no faults injected if empty.
+    pub faults: Vec<FaultKind>,
+    /// Length of each faulty period, as well as each healthy period between.
+    pub fault_window: Timestamp,
+    /// Base one-way message latency.
+    pub latency: Timestamp,
+    /// Client-side operation timeout.
+    pub timeout: Timestamp,
+}
+
+impl Default for SimConfig {
+    fn default() -> Self {
+        SimConfig {
+            seed: 0,
+            mode: ReplicationMode::Leader,
+            nodes: 5,
+            clients: 5,
+            keys: 1,
+            ops: 1000,
+            value_range: 10,
+            mix: [1, 1, 1],
+            faults: vec![],
+            fault_window: 200_000_000,
+            latency: 1_000_000,
+            timeout: 50_000_000,
+        }
+    }
+}
+
+/// Start time of the simulation, roughly when Jepsen clients start.
+const START_TIME: Timestamp = 100_000_000;
+
+/// Fixed node index of the leader or primary.
+const LEADER: usize = 0;
+
+/// Extra latency of delayed messages, as a factor of the base latency.
+const DELAY_FACTOR: Timestamp = 10;
+
+/// Interval of retrying lost replication messages, as a factor of the base
+/// latency.
+const RETRY_FACTOR: Timestamp = 20;
+
+/// A line of the generated history, either from a client or the nemesis.
+#[derive(Debug)]
+pub enum SimLine {
+    Client(Event),
+    Nemesis {
+        time: Timestamp,
+        f: &'static str,
+        value: String,
+    },
+}
+
+impl SimLine {
+    pub fn time(&self) -> Timestamp {
+        match self {
+            SimLine::Client(e) => e.time,
+            SimLine::Nemesis { time, .. } => *time,
+        }
+    }
+
+    fn set_time(&mut self, t: Timestamp) {
+        match self {
+            SimLine::Client(e) => e.time = t,
+            SimLine::Nemesis { time, .. } => *time = t,
+        }
+    }
+}
+
+/// Result of a simulation run.
+#[derive(Debug)]
+pub struct Simulated {
+    /// History lines sorted by (strictly increasing) time.
+    pub lines: Vec<SimLine>,
+    /// Number of faults injected.
+    pub num_faults: usize,
+}
+
+/// A stored version of a key on one replica.
+#[derive(Debug, Clone)]
+struct Version {
+    val: ValType,
+    tag: UniqueTag,
+    /// Ordering of versions; assigned by the leader or primary, or equal to
+    /// the tag in quorum mode (last-writer-wins).
+    ver: u64,
+}
+
+/// Replica node state.
+#[derive(Debug, Default)]
+struct Node {
+    up: bool,
+    store: HashMap<KeyType, Version>,
+    next_ver: u64,
+}
+
+/// Messages among nodes.
+#[derive(Debug, Clone)]
+enum Msg {
+    /// Client request to its bound node.
+    Request(usize),
+    /// Request forwarded to the leader or primary.
+    Forward(usize),
+    /// Asynchronous replication of a version.
+    Replicate(KeyType, Version),
+    /// Quorum write from a coordinator.
+    QuorumWrite(usize, KeyType, Version),
+    /// Acknowledgement of a quorum write.
+    QuorumAck(usize),
+}
+
+/// Scheduled simulation actions.
+#[derive(Debug)]
+enum Action {
+    /// Client slot invokes its next operation.
+    Invoke(usize),
+    /// Message delivery to a node (`from` is `None` for the client link).
+    Deliver {
+        from: Option<usize>,
+        to: usize,
+        msg: Msg,
+    },
+    /// Operation reply arrives at the client.
+    Reply(usize, EventType, OpData),
+    /// Client-side timeout of an operation.
+    Timeout(usize),
+    FaultStart,
+    FaultStop,
+}
+
+#[derive(Debug)]
+struct Scheduled {
+    time: Timestamp,
+    seq: u64,
+    action: Action,
+}
+
+impl PartialEq for Scheduled {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Scheduled {}
+
+impl PartialOrd for Scheduled {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Scheduled {
+    /// Order of actions at the same time: a reply arriving right at the
+    /// client's timeout beats it, as the leader served the operation within
+    /// its deadline.
+    fn rank(&self) -> u8 {
+        match self.action {
+            Action::Reply(..) => 0,
+            _ => 1,
+        }
+    }
+}
+
+impl Ord for Scheduled {
+    fn cmp(&self, other: &Self) -> Ordering {
+        (self.time, self.rank(), self.seq).cmp(&(other.time, other.rank(), other.seq))
+    }
+}
+
+/// An operation issued by a client.
+#[derive(Debug)]
+struct SimOp {
+    slot: usize,
+    process: ClientId,
+    node: usize,
+    data: OpData,
+    deadline: Timestamp,
+    acks: usize,
+    done: bool,
+}
+
+/// A currently active fault.
+#[derive(Debug)]
+enum ActiveFault {
+    Partition(Vec<bool>), // side of each node
+    Delay,
+    Crash(usize),
+}
+
+/// The simulator.
+struct Sim<'c> {
+    cfg: &'c SimConfig,
+    rng: Rng,
+    now: Timestamp,
+    seq: u64,
+    queue: BinaryHeap<Reverse<Scheduled>>,
+
+    nodes: Vec<Node>,
+    fault: Option<ActiveFault>,
+    num_faults: usize,
+
+    processes: Vec<ClientId>,
+    ops: Vec<SimOp>,
+    lines: Vec<SimLine>,
+}
+
+/// Run a simulation according to the configuration.
+pub fn simulate(cfg: &SimConfig) -> Result<Simulated> {
+    if cfg.nodes == 0 || cfg.clients == 0 || cfg.keys == 0 || cfg.value_range == 0 {
+        return Err(Error::Config(
+            "number of nodes, clients, keys, and value range must be positive".into(),
+        ));
+    }
+    if cfg.mix.iter().sum::<u64>() == 0 {
+        return Err(Error::Config(
+            "operation mix must have a positive weight".into(),
+        ));
+    }
+    if cfg.latency == 0 || cfg.timeout <= 2 * cfg.latency {
+        return Err(Error::Config(
+            "timeout must be larger than twice the (positive) latency".into(),
+        ));
+    }
+
+    let mut sim = Sim {
+        cfg,
+        rng: Rng::new(cfg.seed),
+        now: START_TIME,
+        seq: 0,
+        queue: BinaryHeap::new(),
+        nodes: (0..cfg.nodes)
+            .map(|_| Node {
+                up: true,
+                ..Default::default()
+            })
+            .collect(),
+        fault: None,
+        num_faults: 0,
+        processes: (0..cfg.clients).collect(),
+        ops: vec![],
+        lines: vec![],
+    };
+    sim.run();
+
+    // enforce strictly increasing timestamps, as Jepsen histories have
+    let mut lines = sim.lines;
+    let mut last = 0;
+    for line in lines.iter_mut() {
+        if line.time() <= last {
+            line.set_time(last + 1);
+        }
+        last = line.time();
+    }
+
+    Ok(Simulated {
+        lines,
+        num_faults: sim.num_faults,
+    })
+}
+
+/// Jepsen-style node name.
+fn node_name(node: usize) -> String {
+    format!("n{}", node + 1)
+}
+
+/// Formats a set of node names in EDN.
+struct NodeSet<'a>(&'a [usize]);
+
+impl fmt::Display for NodeSet<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let names: Vec<String> = self
+            .0
+            .iter()
+            .map(|&n| format!("{:?}", node_name(n)))
+            .collect();
+        write!(f, "#{{{}}}", names.join(" "))
+    }
+}
+
+impl Sim<'_> {
+    fn schedule(&mut self, delay: Timestamp, action: Action) {
+        self.seq += 1;
+        self.queue.push(Reverse(Scheduled {
+            time: self.now + delay,
+            seq: self.seq,
+            action,
+        }));
+    }
+
+    fn majority(&self) -> usize {
+        self.cfg.nodes / 2 + 1
+    }
+
+    fn partitioned(&self, a: usize, b: usize) -> bool {
+        matches!(&self.fault, Some(ActiveFault::Partition(side)) if side[a] != side[b])
+    }
+
+    /// Number of up nodes reachable from the given node, itself included.
+    fn reachable(&self, node: usize) -> usize {
+        (0..self.cfg.nodes)
+            .filter(|&n| self.nodes[n].up && !self.partitioned(node, n))
+            .count()
+    }
+
+    fn client_latency(&mut self) -> Timestamp {
+        self.rng.between(self.cfg.latency / 2, self.cfg.latency)
+    }
+
+    fn node_latency(&mut self) -> Timestamp {
+        let lat = self.cfg.latency;
+        let extra = if matches!(self.fault, Some(ActiveFault::Delay)) {
+            lat * DELAY_FACTOR
+        } else {
+            0
+        };
+        self.rng.between(lat / 2, lat * 3 / 2) + extra
+    }
+
+    fn send(&mut self, from: usize, to: usize, msg: Msg) {
+        if from == to {
+            self.schedule(
+                0,
+                Action::Deliver {
+                    from: Some(from),
+                    to,
+                    msg,
+                },
+            );
+        } else {
+            let lat = self.node_latency();
+            self.schedule(
+                lat,
+                Action::Deliver {
+                    from: Some(from),
+                    to,
+                    msg,
+                },
+            );
+        }
+    }
+
+    fn reply(&mut self, op: usize, etype: EventType, data: OpData) {
+        let lat = self.client_latency();
+        self.schedule(lat, Action::Reply(op, etype, data));
+    }
+
+    fn run(&mut self) {
+        for slot in 0..self.cfg.clients {
+            let think = self.rng.below(self.cfg.latency * 2);
+            self.schedule(think, Action::Invoke(slot));
+        }
+        if !self.cfg.faults.is_empty() {
+            self.schedule(self.cfg.fault_window, Action::FaultStart);
+        }
+
+        while let Some(Reverse(next)) = self.queue.pop() {
+            self.now = next.time;
+            match next.action {
+                Action::Invoke(slot) => self.handle_invoke(slot),
+                Action::Deliver { from, to, msg } => self.handle_deliver(from, to, msg),
+                Action::Reply(op, etype, data) => self.complete(op, etype, data),
+                Action::Timeout(op) => self.handle_timeout(op),
+                Action::FaultStart => self.handle_fault_start(),
+                Action::FaultStop => self.handle_fault_stop(),
+            }
+        }
+    }
+
+    fn handle_invoke(&mut self, slot: usize) {
+        if self.ops.len() >= self.cfg.ops {
+            return;
+        }
+
+        let key = Value::Int(self.rng.below(self.cfg.keys as u64) as i64);
+        let tag = self.now as UniqueTag;
+        let mix = self.cfg.mix;
+        let pick = self.rng.below(mix.iter().sum());
+        let data = if pick < mix[0] {
+            OpData::Read {
+                key,
+                val: Value::Nil,
+                tag: None,
+            }
+        } else if pick < mix[0] + mix[1] {
+            OpData::Write {
+                key,
+                val: Value::Int(self.rng.below(self.cfg.value_range) as i64),
+                tag,
+            }
+        } else {
+            OpData::Rmw {
+                key,
+                func: RmwFunc::Cas,
+                arg: Value::Nil,
+                rval: Value::Int(self.rng.below(self.cfg.value_range) as i64),
+                rtag: None,
+                wval: Value::Int(self.rng.below(self.cfg.value_range) as i64),
+                wtag: Some(tag),
+            }
+        };
+
+        let process = self.processes[slot];
+        let id = self.ops.len();
+        self.ops.push(SimOp {
+            slot,
+            process,
+            node: process % self.cfg.nodes,
+            data: data.clone(),
+            deadline: self.now + self.cfg.timeout - self.cfg.latency,
+            acks: 0,
+            done: false,
+        });
+        self.lines.push(SimLine::Client(Event::new(
+            self.lines.len() as u64,
+            self.now,
+            EventType::Invoke,
+            process,
+            data,
+        )));
+
+        let lat = self.client_latency();
+        let node = self.ops[id].node;
+        self.schedule(
+            lat,
+            Action::Deliver {
+                from: None,
+                to: node,
+                msg: Msg::Request(id),
+            },
+        );
+        self.schedule(self.cfg.timeout, Action::Timeout(id));
+    }
+
+    fn handle_deliver(&mut self, from: Option<usize>, to: usize, msg: Msg) {
+        let lost = !self.nodes[to].up || from.is_some_and(|f| self.partitioned(f, to));
+        if lost {
+            // replication is retried until it gets through
+            if let (Some(from), Msg::Replicate(..)) = (from, &msg) {
+                let retry = self.cfg.latency * RETRY_FACTOR;
+                self.schedule(
+                    retry,
+                    Action::Deliver {
+                        from: Some(from),
+                        to,
+                        msg,
+                    },
+                );
+            }
+            return;
+        }
+
+        match msg {
+            Msg::Request(op) => self.handle_request(to, op),
+            Msg::Forward(op) => self.serve_at_leader(to, op),
+
+            Msg::Replicate(key, version) => {
+                self.install(to, key, version);
+            }
+
+            Msg::QuorumWrite(op, key, version) => {
+                self.install(to, key, version);
+                let coord = self.ops[op].node;
+                self.send(to, coord, Msg::QuorumAck(op));
+            }
+
+            Msg::QuorumAck(op) => {
+                self.ops[op].acks += 1;
+                if self.ops[op].acks == self.majority() && self.now <= self.ops[op].deadline {
+                    let data = self.ops[op].data.clone();
+                    self.reply(op, EventType::Okay, data);
+                }
+            }
+        }
+    }
+
+    fn handle_request(&mut self, node: usize, op: usize) {
+        let is_read = matches!(self.ops[op].data, OpData::Read { .. });
+        match self.cfg.mode {
+            ReplicationMode::Leader => {
+                if node == LEADER {
+                    self.serve_at_leader(node, op);
+                } else {
+                    self.send(node, LEADER, Msg::Forward(op));
+                }
+            }
+
+            ReplicationMode::PrimaryBackup => {
+                if is_read {
+                    self.serve_read(node, op);
+                } else if node == LEADER {
+                    self.serve_at_leader(node, op);
+                } else {
+                    self.send(node, LEADER, Msg::Forward(op));
+                }
+            }
+
+            ReplicationMode::Quorum => {
+                if is_read {
+                    self.serve_read(node, op);
+                } else {
+                    self.coordinate_write(node, op);
+                }
+            }
+        }
+    }
+
+    /// Serve a read from the node's local replica.
+    fn serve_read(&mut self, node: usize, op: usize) {
+        if self.now > self.ops[op].deadline {
+            return;
+        }
+        let mut data = self.ops[op].data.clone();
+        if let OpData::Read { key, val, tag } = &mut data {
+            let cur = self.nodes[node].store.get(key);
+            *val = cur.map(|v| v.val.clone()).unwrap_or_default();
+            *tag = cur.map(|v| v.tag);
+        }
+        self.reply(op, EventType::Okay, data);
+    }
+
+    /// Serve an operation atomically at the leader or primary.
+    fn serve_at_leader(&mut self, node: usize, op: usize) {
+        if self.now > self.ops[op].deadline {
+            return;
+        }
+        if self.cfg.mode == ReplicationMode::Leader && self.reachable(node) < self.majority() {
+            return; // cannot commit without a majority
+        }
+
+        let mut data = self.ops[op].data.clone();
+        match &mut data {
+            OpData::Read { .. } => self.serve_read(node, op),
+
+            OpData::Write { key, val, tag } => {
+                let version = self.new_version(node, val.clone(), *tag);
+                self.replicate(node, key.clone(), version);
+                self.reply(op, EventType::Okay, data);
+            }
+
+            OpData::Rmw {
+                key,
+                rval,
+                rtag,
+                wval,
+                wtag,
+                ..
+            } => {
+                let cur = self.nodes[node].store.get(key).cloned();
+                if cur.as_ref().map_or(&Value::Nil, |v| &v.val) == rval {
+                    *rtag = cur.map(|v| v.tag);
+                    let version = self.new_version(node, wval.clone(), wtag.unwrap());
+                    let key = key.clone();
+                    self.replicate(node, key, version);
+                    self.reply(op, EventType::Okay, data);
+                } else {
+                    self.reply(op, EventType::Fail, data);
+                }
+            }
+
+            OpData::Add { .. }
+            | OpData::Enqueue { .. }
+            | OpData::Dequeue { .. }
+            | OpData::Append { .. }
+            | OpData::Delete { .. }
+            | OpData::Txn { .. } => {
+                unreachable!()
+            }
+        }
+    }
+
+    /// Coordinate a quorum write (or CAS, compared against the local replica).
+    fn coordinate_write(&mut self, node: usize, op: usize) {
+        if self.now > self.ops[op].deadline {
+            return;
+        }
+        let mut data = self.ops[op].data.clone();
+        if self.reachable(node) < self.majority() {
+            self.reply(op, EventType::Fail, data);
+            return;
+        }
+
+        let (key, val, tag) = match &mut data {
+            OpData::Write { key, val, tag } => (key.clone(), val.clone(), *tag),
+            OpData::Rmw {
+                key,
+                rval,
+                rtag,
+                wval,
+                wtag,
+                ..
+            } => {
+                let cur = self.nodes[node].store.get(key).cloned();
+                if cur.as_ref().map_or(&Value::Nil, |v| &v.val) != rval {
+                    self.reply(op, EventType::Fail, data);
+                    return;
+                }
+                *rtag = cur.map(|v| v.tag);
+                (key.clone(), wval.clone(), wtag.unwrap())
+            }
+            OpData::Read { .. }
+            | OpData::Add { .. }
+            | OpData::Enqueue { .. }
+            | OpData::Dequeue { .. }
+            | OpData::Append { .. }
+            | OpData::Delete { .. }
+            | OpData::Txn { .. } => unreachable!(),
+        };
+
+        self.ops[op].data = data;
+        let version = Version { val, tag, ver: tag };
+        for to in 0..self.cfg.nodes {
+            self.send(node, to, Msg::QuorumWrite(op, key.clone(), version.clone()));
+        }
+    }
+
+    fn new_version(&mut self, node: usize, val: ValType, tag: UniqueTag) -> Version {
+        let n = &mut self.nodes[node];
+        n.next_ver += 1;
+        Version {
+            val,
+            tag,
+            ver: n.next_ver,
+        }
+    }
+
+    /// Install a version locally, then replicate to all others.
+    fn replicate(&mut self, node: usize, key: KeyType, version: Version) {
+        self.install(node, key.clone(), version.clone());
+        for to in (0..self.cfg.nodes).filter(|&n| n != node) {
+            self.send(node, to, Msg::Replicate(key.clone(), version.clone()));
+        }
+    }
+
+    /// Install a version if it is newer than the current one.
+    fn install(&mut self, node: usize, key: KeyType, version: Version) {
+        let store = &mut self.nodes[node].store;
+        if store.get(&key).is_none_or(|cur| cur.ver < version.ver) {
+            store.insert(key, version);
+        }
+    }
+
+    /// Complete an operation on the client side, if not yet completed.
+    fn complete(&mut self, op: usize, etype: EventType, data: OpData) {
+        let SimOp {
+            slot,
+            process,
+            done,
+            ..
+        } = &mut self.ops[op];
+        if *done {
+            return;
+        }
+        *done = true;
+        let (slot, process) = (*slot, *process);
+
+        let index = self.lines.len() as u64;
+        self.lines.push(SimLine::Client(Event::new(
+            index, self.now, etype, process, data,
+        )));
+
+        // like Jepsen, a crashed process is replaced by a fresh one
+        if etype == EventType::Error {
+            self.processes[slot] += self.cfg.clients;
+        }
+        let think = self.rng.between(1, self.cfg.latency * 2);
+        self.schedule(think, Action::Invoke(slot));
+    }
+
+    fn handle_timeout(&mut self, op: usize) {
+        // like the etcd client, timed-out reads are known to have no effect
+        let data = self.ops[op].data.clone();
+        let etype = if matches!(data, OpData::Read { .. }) {
+            EventType::Fail
+        } else {
+            EventType::Error
+        };
+        self.complete(op, etype, data);
+    }
+
+    fn nemesis(&mut self, f: &'static str, value: String) {
+        self.lines.push(SimLine::Nemesis {
+            time: self.now,
+            f,
+            value: "nil".into(),
+        });
+        self.lines.push(SimLine::Nemesis {
+            time: self.now,
+            f,
+            value,
+        });
+    }
+
+    fn handle_fault_start(&mut self) {
+        let n = self.cfg.nodes;
+        let kind = self.cfg.faults[self.rng.below(self.cfg.faults.len() as u64) as usize];
+        let value = match kind {
+            FaultKind::Partition => {
+                let all: Vec<usize> = (0..n).collect();
+                let minority = self.rng.sample(&all, n / 2);
+                let side: Vec<bool> = (0..n).map(|i| minority.contains(&i)).collect();
+                let grudge: Vec<String> = (0..n)
+                    .map(|i| {
+                        let others: Vec<usize> = (0..n).filter(|&j| side[j] != side[i]).collect();
+                        format!("{:?} {}", node_name(i), NodeSet(&others))
+                    })
+                    .collect();
+                self.fault = Some(ActiveFault::Partition(side));
+                format!("[:isolated {{{}}}]", grudge.join(", "))
+            }
+            FaultKind::Delay => {
+                self.fault = Some(ActiveFault::Delay);
+                format!("[:delayed {}]", self.cfg.latency * DELAY_FACTOR)
+            }
+            FaultKind::Crash => {
+                let node = self.rng.below(n as u64) as usize;
+                self.nodes[node].up = false;
+                self.fault = Some(ActiveFault::Crash(node));
+                format!("[:killed {}]", NodeSet(&[node]))
+            }
+        };
+
+        self.num_faults += 1;
+        self.nemesis(":start", value);
+        self.schedule(self.cfg.fault_window, Action::FaultStop);
+    }
+
+    fn handle_fault_stop(&mut self) {
+        let value = match self.fault.take() {
+            Some(ActiveFault::Crash(node)) => {
+                self.nodes[node].up = true;
+                format!("[:restarted {}]", NodeSet(&[node]))
+            }
+            _ => ":network-healed".into(),
+        };
+        self.nemesis(":stop", value);
+
+        if self.ops.len() < self.cfg.ops {
+            self.schedule(self.cfg.fault_window, Action::FaultStart);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::check::Checker;
+    use crate::types::{ClientId, Consistency, Timeline};
+
+    /// Client events of a simulated history, and its greatest client.
+    fn simulated_history(cfg: &SimConfig) -> (Vec<Event>, ClientId) {
+        let events: Vec<Event> = simulate(cfg)
+            .expect("simulator failed")
+            .lines
+            .into_iter()
+            .filter_map(|line| match line {
+                SimLine::Client(event) => Some(event),
+                SimLine::Nemesis { .. } => None,
+            })
+            .collect();
+        let max_client = events.iter().map(|e| e.client).max().unwrap_or(0);
+        (events, max_client)
+    }
+
+    /// Simulated histories of the leader mode are linearizable with or
+    /// without faults, while the other modes serve stale reads under faults.
+    #[test]
+    fn simulated_modes_keep_their_guarantees() {
+        for mode in [
+            ReplicationMode::Leader,
+            ReplicationMode::PrimaryBackup,
+            ReplicationMode::Quorum,
+        ] {
+            let mut violated = 0;
+            for seed in 0..8 {
+                let cfg = SimConfig {
+                    seed,
+                    mode,
+                    keys: 4,
+                    ops: 120,
+                    faults: if seed % 4 == 0 {
+                        vec![]
+                    } else {
+                        vec![FaultKind::Partition, FaultKind::Delay, FaultKind::Crash]
+                    },
+                    fault_window: 20_000_000,
+                    ..SimConfig::default()
+                };
+                let (events, max_client) = simulated_history(&cfg);
+                let timeline = Timeline::new(events, max_client).expect("invalid history");
+                let mut checker = Checker::new(timeline);
+                checker.set_quiet();
+                if checker.check().expect("checker failed") != Consistency::Linearizable {
+                    assert!(cfg.mode != ReplicationMode::Leader, "seed {}: leader", seed);
+                    assert!(
+                        !cfg.faults.is_empty(),
+                        "seed {}: {:?} without faults",
+                        seed,
+                        mode
+                    );
+                    violated += 1;
+                }
+            }
+            if mode != ReplicationMode::Leader {
+                assert!(violated > 0, "{:?} never violated", mode);
+            }
+        }
+    }
+
+    /// With a timeout right above the round trip, replies often arrive right
+    /// at the timeout, which they must beat for leader histories to stay
+    /// linearizable with the timed-out ops taken as failed.
+    #[test]
+    fn simulated_replies_beat_timeouts() {
+        for seed in 0..40 {
+            let cfg = SimConfig {
+                seed,
+                keys: 4,
+                ops: 120,
+                latency: 10,
+                timeout: 25,
+                ..SimConfig::default()
+            };
+            let (mut events, max_client) = simulated_history(&cfg);
+            for event in events.iter_mut().filter(|e| e.etype == EventType::Error) {
+                event.etype = EventType::Fail;
+            }
+            let timeline = Timeline::new(events, max_client).expect("invalid history");
+            let mut checker = Checker::new(timeline);
+            checker.set_quiet();
+            assert_eq!(
+                checker.check().expect("checker failed"),
+                Consistency::Linearizable,
+                "seed {}",
+                seed
+            );
+        }
+    }
+}
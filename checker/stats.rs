@@ -0,0 +1,352 @@
+//! Latency and throughput statistics of a timeline, i.e., what Jepsen plots
+//! as `latency-raw.png` and `rate.png`.
+//!
+//! Operations are grouped by type (`:f`) and completion type (`:ok`, `:fail`,
+//! or `:info`), and further by the period of the nemesis faults they fall in:
+//! latencies by invocation, as Jepsen plots them, and throughput by
+//! completion. Operations that never completed have no latency and are left
+//! out, and those completed with `:info` count once by their completion, not
+//! as the indeterminate operations kept of them, so that the statistics do not
+//! depend on the repair policy. All times are in `:time` units.
+
+use std::collections::BTreeMap;
+
+use crate::json::Json;
+use crate::nemesis::{FaultWindow, Period, period_at, periods};
+use crate::types::{EventType, Timeline, Timestamp};
+
+/// Group of operations, by type and completion type.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct OpGroup {
+    /// Operation type, without the leading ':'.
+    pub f: &'static str,
+    pub etype: EventType,
+}
+
+impl OpGroup {
+    /// Completion type, without the leading ':'.
+    pub fn outcome(&self) -> &'static str {
+        &self.etype.to_type()[1..]
+    }
+}
+
+/// Latency quantiles of a group of operations.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Latencies {
+    pub count: usize,
+    pub p50: Timestamp,
+    pub p95: Timestamp,
+    pub p99: Timestamp,
+    pub max: Timestamp,
+}
+
+impl Latencies {
+    /// Quantiles by nearest rank of a non-empty sample.
+    fn of(mut sample: Vec<Timestamp>) -> Self {
+        sample.sort_unstable();
+        let rank = |q: f64| sample[((q * sample.len() as f64).ceil() as usize).max(1) - 1];
+        Latencies {
+            count: sample.len(),
+            p50: rank(0.5),
+            p95: rank(0.95),
+            p99: rank(0.99),
+            max: sample[sample.len() - 1],
+        }
+    }
+}
+
+/// Completions in a period of the faults, over its duration.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PeriodRate {
+    pub start: Timestamp,
+    pub end: Timestamp,
+    pub count: usize,
+}
+
+/// Latency and throughput statistics of a timeline.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TimelineStats {
+    /// Latencies of each group over the whole history.
+    pub latency: BTreeMap<OpGroup, Latencies>,
+    /// Latencies of each group in each period of the faults (if any).
+    pub period_latency: BTreeMap<(Period, OpGroup), Latencies>,
+    /// Width of the throughput buckets, the first starting at the earliest
+    /// invocation.
+    pub bucket: Timestamp,
+    pub start: Timestamp,
+    /// Completions of each group in each bucket.
+    pub throughput: BTreeMap<OpGroup, Vec<usize>>,
+    /// Completions of each group in each period of the faults (if any).
+    pub period_throughput: BTreeMap<(Period, OpGroup), PeriodRate>,
+}
+
+/// Compute the statistics of the timeline, with throughput in buckets of the
+/// given width, split by the periods of the given faults.
+pub fn timeline_stats(
+    timeline: &Timeline,
+    windows: &[FaultWindow],
+    bucket: Timestamp,
+) -> TimelineStats {
+    let bucket = bucket.max(1);
+    let completed = timeline
+        .queues
+        .iter()
+        .flatten()
+        // :info completions are among the failed ones
+        .filter(|span| span.terminated() && !span.indeterminate)
+        .map(|span| (span, EventType::Okay))
+        .chain(timeline.failed.iter().map(|(span, etype)| (span, *etype)));
+
+    let mut samples: BTreeMap<OpGroup, Vec<_>> = BTreeMap::new();
+    for (span, etype) in completed {
+        let group = OpGroup {
+            f: &span.data.to_type()[1..],
+            etype,
+        };
+        samples
+            .entry(group)
+            .or_default()
+            .push((span.invoke, span.finish));
+    }
+    let start = samples.values().flatten().map(|s| s.0).min().unwrap_or(0);
+    let end = samples.values().flatten().map(|s| s.1).max().unwrap_or(0);
+
+    // periods span from the first invocation to the last completion
+    let mut spans = BTreeMap::new();
+    if !windows.is_empty() {
+        for period in periods(windows) {
+            let (lo, hi) = match period {
+                Period::Before => (start, windows[0].start.saturating_sub(1)),
+                Period::During(nth) => {
+                    let window = &windows[nth - 1];
+                    (window.start, window.stop.unwrap_or(end))
+                }
+                Period::After(nth) => (
+                    windows[nth - 1].stop.map_or(end, |stop| stop + 1),
+                    windows
+                        .get(nth)
+                        .map_or(end, |next| next.start.saturating_sub(1)),
+                ),
+            };
+            spans.insert(period, (lo, hi.max(lo)));
+        }
+    }
+
+    let mut stats = TimelineStats {
+        latency: BTreeMap::new(),
+        period_latency: BTreeMap::new(),
+        bucket,
+        start,
+        throughput: BTreeMap::new(),
+        period_throughput: BTreeMap::new(),
+    };
+    for (group, sample) in samples {
+        let latencies = sample.iter().map(|(invoke, finish)| finish - invoke);
+        stats
+            .latency
+            .insert(group, Latencies::of(latencies.collect()));
+
+        let mut counts = vec![0; ((end - start) / bucket + 1) as usize];
+        for &(_, finish) in &sample {
+            counts[((finish - start) / bucket) as usize] += 1;
+        }
+        stats.throughput.insert(group, counts);
+
+        if windows.is_empty() {
+            continue;
+        }
+        let mut by_period: BTreeMap<Period, Vec<Timestamp>> = BTreeMap::new();
+        for &(invoke, finish) in &sample {
+            by_period
+                .entry(period_at(windows, invoke))
+                .or_default()
+                .push(finish - invoke);
+        }
+        for (period, sample) in by_period {
+            stats
+                .period_latency
+                .insert((period, group), Latencies::of(sample));
+        }
+        for (&period, &(start, end)) in &spans {
+            let count = sample
+                .iter()
+                .filter(|&&(_, finish)| period_at(windows, finish) == period)
+                .count();
+            let rate = PeriodRate { start, end, count };
+            stats.period_throughput.insert((period, group), rate);
+        }
+    }
+    stats
+}
+
+impl TimelineStats {
+    /// Render as a JSON object, with latencies over the whole history under
+    /// the period `"all"`.
+    pub fn to_json(&self) -> Json {
+        let group_json =
+            |group: &OpGroup| [("f", group.f.into()), ("type", group.outcome().into())];
+        let latency_json = |period: String, group: &OpGroup, l: &Latencies| {
+            let mut fields = vec![("period", period.into())];
+            fields.extend(group_json(group));
+            fields.extend([
+                ("count", l.count.into()),
+                ("p50", l.p50.into()),
+                ("p95", l.p95.into()),
+                ("p99", l.p99.into()),
+                ("max", l.max.into()),
+            ]);
+            Json::object(fields)
+        };
+
+        let mut latency: Vec<Json> = self
+            .latency
+            .iter()
+            .map(|(group, l)| latency_json("all".to_string(), group, l))
+            .collect();
+        latency.extend(
+            self.period_latency
+                .iter()
+                .map(|((period, group), l)| latency_json(period.to_string(), group, l)),
+        );
+        let series = self
+            .throughput
+            .iter()
+            .map(|(group, counts)| {
+                let mut fields = group_json(group).to_vec();
+                fields.push(("counts", counts.clone().into()));
+                Json::object(fields)
+            })
+            .collect();
+        let periods = self
+            .period_throughput
+            .iter()
+            .map(|((period, group), rate)| {
+                let mut fields = vec![("period", period.to_string().into())];
+                fields.extend(group_json(group));
+                fields.extend([
+                    ("start", rate.start.into()),
+                    ("end", rate.end.into()),
+                    ("count", rate.count.into()),
+                ]);
+                Json::object(fields)
+            })
+            .collect();
+
+        Json::object([
+            ("latency", Json::Array(latency)),
+            (
+                "throughput",
+                Json::object([
+                    ("bucket", self.bucket.into()),
+                    ("start", self.start.into()),
+                    ("series", Json::Array(series)),
+                    ("periods", Json::Array(periods)),
+                ]),
+            ),
+        ])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::generator::{GenConfig, Rng, generate};
+    use crate::nemesis::Fault;
+    use crate::repair::{Repair, RepairPolicy};
+
+    /// Latency statistics account for every completed operation, with its
+    /// latency from the events, and throughput counts each once, both overall and
+    /// split by the periods of a fault, whatever the repair policy.
+    #[test]
+    fn timeline_stats_count_every_completion() {
+        let mut rng = Rng::new(0);
+
+        for case in 0..40 {
+            let cfg = GenConfig {
+                seed: rng.next_u64(),
+                clients: rng.between(1, 5) as usize,
+                keys: 2,
+                ops: 100,
+                value_range: 5,
+                fail_rate: 0.2,
+                info_rate: 0.1,
+                ..GenConfig::default()
+            };
+            let mut events = generate(&cfg).expect("generator failed").events;
+            let max_client = events.iter().map(|e| e.client).max().unwrap_or(0);
+            // every other case, leave the last operation completed flying
+            if case % 2 == 1 {
+                let last = events.iter().rposition(|e| e.etype != EventType::Invoke);
+                events.remove(last.unwrap());
+            }
+
+            // completions and greatest latency of each group, from the events
+            let mut invoked = BTreeMap::new();
+            let mut expected: BTreeMap<(&str, EventType), (usize, Timestamp)> = BTreeMap::new();
+            for e in &events {
+                if e.etype == EventType::Invoke {
+                    invoked.insert(e.client, e.time);
+                } else {
+                    let latency = e.time - invoked[&e.client];
+                    let group = expected
+                        .entry((&e.opdata.to_type()[1..], e.etype))
+                        .or_default();
+                    group.0 += 1;
+                    group.1 = group.1.max(latency);
+                }
+            }
+            let (first, last) = (events[0].time, events[events.len() - 1].time);
+            let windows = [FaultWindow {
+                start: first + (last - first) / 3,
+                stop: Some(first + (last - first) * 2 / 3),
+                fault: Fault::Other(":kill".to_string()),
+            }];
+
+            let bucket = 1 + rng.below(1000);
+            let stats_under = |policy| {
+                let mut repair = Repair::new(policy);
+                let timeline = Timeline::with_repair(events.clone(), max_client, &mut repair)
+                    .expect("invalid history");
+                timeline_stats(&timeline, &windows, bucket)
+            };
+            let stats = stats_under(RepairPolicy::Skip);
+            for policy in [RepairPolicy::Close, RepairPolicy::Drop] {
+                assert_eq!(
+                    stats_under(policy),
+                    stats,
+                    "case {}: stats under {:?}",
+                    case,
+                    policy
+                );
+            }
+            let actual: BTreeMap<_, _> = stats
+                .latency
+                .iter()
+                .map(|(group, l)| {
+                    assert!(l.p50 <= l.p95 && l.p95 <= l.p99 && l.p99 <= l.max);
+                    ((group.f, group.etype), (l.count, l.max))
+                })
+                .collect();
+            assert_eq!(actual, expected, "case {}: latencies", case);
+
+            for (group, l) in &stats.latency {
+                let throughput: usize = stats.throughput[group].iter().sum();
+                assert_eq!(throughput, l.count, "case {}: throughput", case);
+                let latency: usize = stats
+                    .period_latency
+                    .iter()
+                    .filter(|((_, g), _)| g == group)
+                    .map(|(_, l)| l.count)
+                    .sum();
+                let throughput: usize = stats
+                    .period_throughput
+                    .iter()
+                    .filter(|((_, g), _)| g == group)
+                    .map(|(_, rate)| rate.count)
+                    .sum();
+                assert_eq!(latency, l.count, "case {}: period latencies", case);
+                assert_eq!(throughput, l.count, "case {}: period throughput", case);
+            }
+        }
+    }
+}
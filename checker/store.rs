@@ -1,124 +1,219 @@
 //! IO-related helpers for interacting with the Jepsen store directory.
 
-use std::error::Error;
 use std::fs::File;
-use std::io::{self, BufRead};
+use std::io::{self, BufRead, Write};
 use std::path::Path;
 
-use crate::types::{ClientId, Event, EventType, KeyType, OpData, Timestamp, UniqueTag, ValType};
+use crate::edn::Edn;
+use crate::error::{Error, ParseErrorKind, Result};
+use crate::repair::{Repair, RepairAction, RepairPolicy};
+use crate::rmw::RmwFunc;
+use crate::types::{ClientId, Event, EventType, Mop, OpData, Timestamp, UniqueTag, Value};
 
 /// History edn file name.
-const HISTORY_FILE: &str = "history.edn";
+pub const HISTORY_FILE: &str = "history.edn";
+
+/// Result of parsing a piece of a history line.
+type ParseResult<T> = std::result::Result<T, ParseErrorKind>;
+
+/// Error for a field value that cannot be interpreted.
+fn invalid(field: &str, value: &str) -> ParseErrorKind {
+    ParseErrorKind::InvalidField {
+        field: field.to_string(),
+        value: value.to_string(),
+    }
+}
 
 // Parsing methods for the fundamental types...
 impl EventType {
-    pub(crate) fn from_type(s: &str) -> Result<Self, Box<dyn Error>> {
+    pub(crate) fn from_type(s: &str) -> ParseResult<Self> {
         match s {
             ":invoke" => Ok(EventType::Invoke),
             ":ok" => Ok(EventType::Okay),
             ":fail" => Ok(EventType::Fail),
             ":info" => Ok(EventType::Error),
-            _ => Err(format!("unknown event type: {}", s).into()),
+            _ => Err(ParseErrorKind::UnknownEventType(s.to_string())),
+        }
+    }
+
+    pub(crate) fn to_type(self) -> &'static str {
+        match self {
+            EventType::Invoke => ":invoke",
+            EventType::Okay => ":ok",
+            EventType::Fail => ":fail",
+            EventType::Error => ":info",
         }
     }
 }
 
 impl OpData {
-    pub(crate) fn from_type(s: &str) -> Result<Self, Box<dyn Error>> {
+    pub(crate) fn from_type(s: &str) -> ParseResult<Self> {
         match s {
             ":read" => Ok(OpData::Read {
-                key: 0,
-                val: None,
+                key: Value::Nil,
+                val: Value::Nil,
                 tag: None,
             }),
             ":write" => Ok(OpData::Write {
-                key: 0,
-                val: 0,
+                key: Value::Nil,
+                val: Value::Nil,
                 tag: 0,
             }),
-            ":cas" => Ok(OpData::Rmw {
-                key: 0,
-                rval: None,
+            _ if let Some(func) = RmwFunc::from_type(s) => Ok(OpData::Rmw {
+                key: Value::Nil,
+                func,
+                arg: Value::Nil,
+                rval: Value::Nil,
                 rtag: None,
-                wval: None,
+                wval: Value::Nil,
                 wtag: None,
             }),
-            _ => Err(format!("unknown operation type: {}", s).into()),
+            // increments by 1 are adds of nil, see the counter model
+            ":add" | ":incr" => Ok(OpData::Add {
+                key: Value::Nil,
+                val: Value::Nil,
+            }),
+            ":enqueue" => Ok(OpData::Enqueue {
+                key: Value::Nil,
+                val: Value::Nil,
+            }),
+            ":dequeue" => Ok(OpData::Dequeue {
+                key: Value::Nil,
+                val: Value::Nil,
+            }),
+            ":append" => Ok(OpData::Append {
+                key: Value::Nil,
+                val: Value::Nil,
+            }),
+            ":delete" => Ok(OpData::Delete {
+                key: Value::Nil,
+                tag: 0,
+            }),
+            ":txn" => Ok(OpData::Txn { mops: vec![] }),
+            _ => Err(ParseErrorKind::UnknownOpType(s.to_string())),
         }
     }
 
-    pub(crate) fn fill_values(&mut self, s: &str) -> Result<(), Box<dyn Error>> {
+    pub(crate) fn to_type(&self) -> &'static str {
         match self {
-            OpData::Read { key, val, .. } => {
-                if let Some((k, v)) = s
-                    .trim_start_matches('[')
-                    .trim_end_matches(']')
-                    .split_once(' ')
-                {
-                    *key = k.parse::<KeyType>()?;
-                    if v.trim() == "nil" {
-                        *val = None;
-                    } else {
-                        *val = Some(v.parse::<ValType>()?);
-                    }
-                } else {
-                    return Err(format!("invalid :value for :read: {}", s).into());
+            OpData::Read { .. } => ":read",
+            OpData::Write { .. } => ":write",
+            OpData::Rmw { func, .. } => func.to_type(),
+            OpData::Add { .. } => ":add",
+            OpData::Enqueue { .. } => ":enqueue",
+            OpData::Dequeue { .. } => ":dequeue",
+            OpData::Append { .. } => ":append",
+            OpData::Delete { .. } => ":delete",
+            OpData::Txn { .. } => ":txn",
+        }
+    }
+
+    pub(crate) fn fill_values(&mut self, s: &str) -> ParseResult<()> {
+        let value = |edn: &Edn| Value::from_edn(edn).ok_or_else(|| invalid(":value", s));
+        if let OpData::Delete { key, .. } = self {
+            // either `[k nil]` like the others, or just the key
+            *key = match Edn::parse(s) {
+                Ok(Edn::Vector(pair)) if matches!(pair.as_slice(), [_, Edn::Nil]) => {
+                    value(&pair[0])?
                 }
+                Ok(edn) => value(&edn)?,
+                Err(_) => return Err(invalid(":value", s)),
+            };
+            return Ok(());
+        }
+        if let OpData::Txn { mops } = self {
+            // `[[:r k v] [:w k v] ...]`, with nil read values at invocation
+            let Ok(Edn::Vector(items)) = Edn::parse(s) else {
+                return Err(invalid(":value", s));
+            };
+            *mops = items
+                .iter()
+                .map(|item| match item {
+                    Edn::Vector(mop) => match mop.as_slice() {
+                        [Edn::Keyword(f), k, v] if f == "r" => Ok(Mop::Read {
+                            key: value(k)?,
+                            val: value(v)?,
+                        }),
+                        [Edn::Keyword(f), k, v] if f == "w" => Ok(Mop::Write {
+                            key: value(k)?,
+                            val: value(v)?,
+                        }),
+                        _ => Err(invalid(":value", s)),
+                    },
+                    _ => Err(invalid(":value", s)),
+                })
+                .collect::<ParseResult<_>>()?;
+            return Ok(());
+        }
+        let Ok(Edn::Vector(pair)) = Edn::parse(s) else {
+            return Err(invalid(":value", s));
+        };
+        let [k, v] = pair.as_slice() else {
+            return Err(invalid(":value", s));
+        };
+
+        match self {
+            OpData::Read { key, val, .. } => {
+                *key = value(k)?;
+                *val = value(v)?;
             }
 
             OpData::Write { key, val, .. } => {
-                if let Some((k, v)) = s
-                    .trim_start_matches('[')
-                    .trim_end_matches(']')
-                    .split_once(' ')
-                {
-                    *key = k.parse::<KeyType>()?;
-                    *val = v.parse::<ValType>()?;
-                } else {
-                    return Err(format!("invalid :value for :write: {}", s).into());
-                }
+                *key = value(k)?;
+                *val = value(v)?;
             }
 
             OpData::Rmw {
-                key, rval, wval, ..
+                key,
+                func,
+                arg,
+                rval,
+                wval,
+                ..
             } => {
-                if let Some((k, vp)) = s
-                    .trim_start_matches('[')
-                    .trim_end_matches(']')
-                    .split_once(' ')
-                {
-                    *key = k.parse::<KeyType>()?;
-                    if let Some((rv, wv)) = vp
-                        .trim_start_matches('[')
-                        .trim_end_matches(']')
-                        .split_once(' ')
-                    {
-                        *rval = Some(rv.parse::<ValType>()?);
-                        *wval = Some(wv.parse::<ValType>()?);
-                    } else {
-                        return Err(format!("invalid :value for :cas: {}", s).into());
+                *key = value(k)?;
+                let Edn::Vector(vp) = v else {
+                    return Err(invalid(":value", s));
+                };
+                let (rv, wv) = match (func.has_arg(), vp.as_slice()) {
+                    (false, [rv, wv]) => (rv, wv),
+                    (true, [av, rv, wv]) => {
+                        *arg = value(av)?;
+                        (rv, wv)
                     }
-                } else {
-                    return Err(format!("invalid :value for :cas: {}", s).into());
-                }
+                    _ => return Err(invalid(":value", s)),
+                };
+                *rval = value(rv)?;
+                *wval = value(wv)?;
+            }
+
+            OpData::Add { key, val }
+            | OpData::Enqueue { key, val }
+            | OpData::Dequeue { key, val }
+            | OpData::Append { key, val } => {
+                *key = value(k)?;
+                *val = value(v)?;
             }
+
+            // filled above
+            OpData::Delete { .. } | OpData::Txn { .. } => unreachable!(),
         }
 
         Ok(())
     }
 
-    pub(crate) fn fill_tstags(&mut self, s: &str) -> Result<(), Box<dyn Error>> {
+    pub(crate) fn fill_tstags(&mut self, s: &str) -> ParseResult<()> {
         match self {
             OpData::Read { tag, .. } => {
                 if s.trim() == "nil" {
                     *tag = None;
                 } else {
-                    *tag = Some(s.parse::<UniqueTag>()?);
+                    *tag = Some(s.parse::<UniqueTag>().map_err(|_| invalid(":tstag", s))?);
                 }
             }
 
-            OpData::Write { tag, .. } => {
-                *tag = s.parse::<UniqueTag>()?;
+            OpData::Write { tag, .. } | OpData::Delete { tag, .. } => {
+                *tag = s.parse::<UniqueTag>().map_err(|_| invalid(":tstag", s))?;
             }
 
             OpData::Rmw { rtag, wtag, .. } => {
@@ -130,21 +225,80 @@ impl OpData {
                     if rt.trim() == "nil" {
                         *rtag = None;
                     } else {
-                        *rtag = Some(rt.parse::<UniqueTag>()?);
+                        *rtag = Some(rt.parse::<UniqueTag>().map_err(|_| invalid(":tstag", s))?);
                     }
                     if wt.trim() == "nil" {
                         *wtag = None;
                     } else {
-                        *wtag = Some(wt.parse::<UniqueTag>()?);
+                        *wtag = Some(wt.parse::<UniqueTag>().map_err(|_| invalid(":tstag", s))?);
                     }
                 } else {
-                    return Err(format!("invalid :tstag for :cas: {}", s).into());
+                    return Err(invalid(":tstag", s));
                 }
             }
+
+            // no tags to fill
+            OpData::Add { .. }
+            | OpData::Enqueue { .. }
+            | OpData::Dequeue { .. }
+            | OpData::Append { .. }
+            | OpData::Txn { .. } => {}
         }
 
         Ok(())
     }
+
+    /// Formats the `:value` field, inverse of `fill_values()`.
+    pub(crate) fn to_values(&self) -> String {
+        match self {
+            OpData::Read { key, val, .. } => format!("[{} {}]", key, val),
+            OpData::Write { key, val, .. } => format!("[{} {}]", key, val),
+            OpData::Rmw {
+                key,
+                func,
+                arg,
+                rval,
+                wval,
+                ..
+            } => {
+                if func.has_arg() {
+                    format!("[{} [{} {} {}]]", key, arg, rval, wval)
+                } else {
+                    format!("[{} [{} {}]]", key, rval, wval)
+                }
+            }
+            OpData::Add { key, val }
+            | OpData::Enqueue { key, val }
+            | OpData::Dequeue { key, val }
+            | OpData::Append { key, val } => format!("[{} {}]", key, val),
+            OpData::Delete { key, .. } => format!("[{} nil]", key),
+            OpData::Txn { mops } => {
+                let mops: Vec<String> = mops
+                    .iter()
+                    .map(|mop| match mop {
+                        Mop::Read { key, val } => format!("[:r {} {}]", key, val),
+                        Mop::Write { key, val } => format!("[:w {} {}]", key, val),
+                    })
+                    .collect();
+                format!("[{}]", mops.join(" "))
+            }
+        }
+    }
+
+    /// Formats the `:tstag` field, inverse of `fill_tstags()`.
+    pub(crate) fn to_tstags(&self) -> String {
+        let opt = |t: &Option<UniqueTag>| t.map(|t| t.to_string()).unwrap_or("nil".into());
+        match self {
+            OpData::Read { tag, .. } => opt(tag),
+            OpData::Write { tag, .. } | OpData::Delete { tag, .. } => tag.to_string(),
+            OpData::Rmw { rtag, wtag, .. } => format!("[{} {}]", opt(rtag), opt(wtag)),
+            OpData::Add { .. }
+            | OpData::Enqueue { .. }
+            | OpData::Dequeue { .. }
+            | OpData::Append { .. }
+            | OpData::Txn { .. } => "nil".into(),
+        }
+    }
 }
 
 /// Inner parsing helper for a segment. Returns false if the current line should
@@ -157,26 +311,32 @@ fn parse_segment(
     last_index: &mut i64,
     last_time: &mut Timestamp,
     max_client: &mut ClientId,
+    index: &mut Option<u64>,
     time: &mut Option<Timestamp>,
     etype: &mut Option<EventType>,
     client: &mut Option<ClientId>,
     op: &mut Option<OpData>,
-) -> Result<bool, Box<dyn Error>> {
+) -> ParseResult<bool> {
     match field {
         ":index" => {
-            let this_index = stuff.parse::<i64>()?;
+            let this_index = stuff.parse::<i64>().map_err(|_| invalid(field, stuff))?;
             if this_index <= *last_index {
-                return Err(format!("index {} <= last index {}", this_index, last_index).into());
+                return Err(ParseErrorKind::IndexNotMonotonic {
+                    index: this_index,
+                    last: *last_index,
+                });
             }
             *last_index = this_index;
+            *index = Some(this_index as u64);
         }
 
         ":time" => {
-            let this_time = stuff.parse::<u64>()?;
+            let this_time = stuff.parse::<u64>().map_err(|_| invalid(field, stuff))?;
             if this_time <= *last_time {
-                return Err(
-                    format!("timestamp {} <= last timestamp {}", this_time, last_time).into(),
-                );
+                return Err(ParseErrorKind::TimeNotMonotonic {
+                    time: this_time,
+                    last: *last_time,
+                });
             }
             *last_time = this_time;
             *time = Some(this_time);
@@ -192,7 +352,9 @@ fn parse_segment(
                 return Ok(false);
             }
 
-            let this_client = stuff.parse::<ClientId>()?;
+            let this_client = stuff
+                .parse::<ClientId>()
+                .map_err(|_| invalid(field, stuff))?;
             if this_client > *max_client {
                 *max_client = this_client;
             }
@@ -207,7 +369,9 @@ fn parse_segment(
             if let Some(op) = op.as_mut() {
                 op.fill_values(stuff)?;
             } else {
-                return Err("missing op type :f for :value".into());
+                return Err(ParseErrorKind::MissingOpType {
+                    field: field.to_string(),
+                });
             }
         }
 
@@ -215,7 +379,9 @@ fn parse_segment(
             if let Some(op) = op.as_mut() {
                 op.fill_tstags(stuff)?;
             } else {
-                return Err("missing op type :f for :tstag".into());
+                return Err(ParseErrorKind::MissingOpType {
+                    field: field.to_string(),
+                });
             }
         }
 
@@ -226,9 +392,47 @@ fn parse_segment(
     Ok(true)
 }
 
+/// Splits the inside of a history line into its top-level segments, leaving
+/// commas nested in values (e.g., in exception maps) alone.
+pub(crate) fn split_segments(line: &str) -> Vec<&str> {
+    let mut segs = vec![];
+    let (mut depth, mut start) = (0, 0);
+    let (mut in_str, mut escaped) = (false, false);
+
+    for (i, c) in line.char_indices() {
+        if in_str {
+            match c {
+                _ if escaped => escaped = false,
+                '\\' => escaped = true,
+                '"' => in_str = false,
+                _ => {}
+            }
+            continue;
+        }
+        match c {
+            '"' => in_str = true,
+            '[' | '{' | '(' => depth += 1,
+            ']' | '}' | ')' => depth -= 1,
+            ',' if depth == 0 => {
+                segs.push(line[start..i].trim());
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    segs.push(line[start..].trim());
+    segs
+}
+
 /// Reads the history file into a stream of events. Returns the vec of events
-/// and the maximum client ID found.
-pub(crate) fn parse_history(test_dir: &Path) -> Result<(Vec<Event>, ClientId), Box<dyn Error>> {
+/// and the maximum client ID found. Fails on the first malformed line.
+pub fn parse_history(test_dir: &Path) -> Result<(Vec<Event>, ClientId)> {
+    parse_history_with(test_dir, &mut Repair::new(RepairPolicy::Abort))
+}
+
+/// Like `parse_history()`, but reports malformed lines to `repair`, skipping
+/// them unless its policy aborts.
+pub fn parse_history_with(test_dir: &Path, repair: &mut Repair) -> Result<(Vec<Event>, ClientId)> {
     let file = File::open(test_dir.join(HISTORY_FILE))?;
     let reader = io::BufReader::new(file);
 
@@ -237,70 +441,262 @@ pub(crate) fn parse_history(test_dir: &Path) -> Result<(Vec<Event>, ClientId), B
     let mut last_time: Timestamp = 0;
     let mut max_client: ClientId = 0;
 
-    let mut time = None;
-    let mut etype = None;
-    let mut client = None;
-    let mut op = None;
-
-    for line in reader.lines() {
+    for (lineno, line) in reader.lines().enumerate() {
         let line = line?;
-        let line = line.trim_start_matches('{').trim_end_matches('}');
+        let lineno = lineno + 1;
+        let line = line.trim();
+        let line = line.strip_prefix('{').unwrap_or(line);
+        let line = line.strip_suffix('}').unwrap_or(line);
         if line.is_empty() {
             continue;
         }
 
+        let mut index = None;
+        let mut time = None;
+        let mut etype = None;
+        let mut client = None;
+        let mut op = None;
+
+        // fields may come in any order, but :process decides whether to skip
+        // the line and :f is needed to interpret :value and :tstag
+        let mut segs: Vec<&str> = split_segments(line)
+            .into_iter()
+            .filter(|s| s.starts_with(':'))
+            .collect();
+        segs.sort_by_key(|seg| match seg.split_once(' ') {
+            Some((":process", _)) => 0,
+            Some((":f", _)) => 1,
+            _ => 2,
+        });
+
         // some events are not from clients but from e.g. nemesis, need to
         // skip those
         let mut skip = false;
+        let mut error = None;
 
-        for seg in line
-            .split(',')
-            .map(|s| s.trim())
-            .filter(|s| s.starts_with(':'))
-        {
-            if let Some((field, stuff)) = seg.split_once(' ') {
-                match parse_segment(
-                    field,
-                    stuff,
-                    &mut last_index,
-                    &mut last_time,
-                    &mut max_client,
-                    &mut time,
-                    &mut etype,
-                    &mut client,
-                    &mut op,
-                ) {
-                    Ok(true) => {}
-                    Ok(false) => {
-                        skip = true;
-                        break;
-                    }
-                    Err(err) => {
-                        eprintln!("Skip line due to segment: {}: {}", seg, err);
-                        skip = true;
-                        break;
-                    }
-                }
-            } else {
-                eprintln!("Skip line due to invalid segment: {}", seg);
-                skip = true;
+        for seg in segs {
+            let Some((field, stuff)) = seg.split_once(' ') else {
+                error = Some(ParseErrorKind::InvalidSegment(seg.to_string()));
                 break;
+            };
+            match parse_segment(
+                field,
+                stuff.trim(),
+                &mut last_index,
+                &mut last_time,
+                &mut max_client,
+                &mut index,
+                &mut time,
+                &mut etype,
+                &mut client,
+                &mut op,
+            ) {
+                Ok(true) => {}
+                Ok(false) => {
+                    skip = true;
+                    break;
+                }
+                Err(kind) => {
+                    error = Some(kind);
+                    break;
+                }
             }
         }
-
         if skip {
             continue;
         }
 
         // compose line into an event
-        if let (Some(time), Some(etype), Some(client), Some(op)) =
-            (time.take(), etype.take(), client.take(), op.take())
-        {
-            events.push(Event::new(time, etype, client, op));
-        } else {
-            return Err(format!("missing event field(s) in line: {}", line).into());
-        }
+        let kind = match (error, time, etype, client, op) {
+            (None, Some(time), Some(etype), Some(client), Some(op)) => {
+                // fall back to the line position if no :index given
+                let index = index.unwrap_or(lineno as u64 - 1);
+                events.push(Event::new(index, time, etype, client, op));
+                continue;
+            }
+            (Some(kind), ..) => kind,
+            (None, ..) => ParseErrorKind::MissingFields,
+        };
+        repair.report(
+            Error::Parse { line: lineno, kind },
+            RepairAction::SkippedLine,
+        )?;
     }
 
     Ok((events, max_client))
 }
+
+/// Writer of events in the history edn format, i.e., the inverse of
+/// `parse_history()`. Indices are assigned in the order of writing.
+pub struct HistoryWriter<W: Write> {
+    out: W,
+    next_index: u64,
+}
+
+impl<W: Write> HistoryWriter<W> {
+    pub fn new(out: W) -> Self {
+        HistoryWriter { out, next_index: 0 }
+    }
+
+    /// Write a client event as one line.
+    pub fn write_event(&mut self, event: &Event) -> Result<()> {
+        writeln!(
+            self.out,
+            "{{:index {}, :time {}, :type {}, :process {}, :f {}, :value {}, :tstag {}}}",
+            self.next_index,
+            event.time,
+            event.etype.to_type(),
+            event.client,
+            event.opdata.to_type(),
+            event.opdata.to_values(),
+            event.opdata.to_tstags(),
+        )?;
+        self.next_index += 1;
+        Ok(())
+    }
+
+    /// Write a nemesis event (always of type `:info`) as one line.
+    pub fn write_nemesis(&mut self, time: Timestamp, f: &str, value: &str) -> Result<()> {
+        writeln!(
+            self.out,
+            "{{:index {}, :time {}, :type :info, :process :nemesis, :f {}, :value {}}}",
+            self.next_index, time, f, value,
+        )?;
+        self.next_index += 1;
+        Ok(())
+    }
+
+    pub fn flush(&mut self) -> Result<()> {
+        self.out.flush()?;
+        Ok(())
+    }
+}
+
+/// Write a store directory of the given files (with paths relative to it)
+/// under the system's temporary directory, for tests to read back.
+#[cfg(test)]
+pub(crate) fn fixture_dir(name: &str, files: &[(&str, &str)]) -> std::path::PathBuf {
+    let dir = std::env::temp_dir().join(format!("sop-checker-{}-{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    for (file, text) in files {
+        let path = dir.join(file);
+        std::fs::create_dir_all(path.parent().unwrap()).expect("cannot create fixture");
+        std::fs::write(path, text).expect("cannot write fixture");
+    }
+    dir
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::{Error, ParseErrorKind};
+    use crate::generator::{AnomalyKind, GenConfig, Rng, generate};
+
+    /// Histories written by `HistoryWriter` parse back into the same events.
+    #[test]
+    fn written_histories_parse_back() {
+        let mut rng = Rng::new(0);
+
+        for case in 0..40 {
+            let cfg = GenConfig {
+                seed: rng.next_u64(),
+                clients: rng.between(1, 5) as usize,
+                keys: rng.between(1, 3) as usize,
+                ops: 50,
+                fail_rate: 0.1,
+                info_rate: 0.1,
+                anomalies: vec![(AnomalyKind::StaleRead, 2)],
+                ..GenConfig::default()
+            };
+            let events = generate(&cfg).expect("generator failed").events;
+            let mut out = vec![];
+            let mut writer = HistoryWriter::new(&mut out);
+            for event in &events {
+                writer.write_event(event).expect("cannot write event");
+            }
+            writer.flush().expect("cannot flush");
+            let text = String::from_utf8(out).expect("not UTF-8");
+
+            let dir = fixture_dir("round-trip", &[("history.edn", &text)]);
+            let (parsed, max_client) = parse_history(&dir).expect("cannot parse");
+            assert_eq!(
+                max_client,
+                events.iter().map(|e| e.client).max().unwrap(),
+                "case {}: max client",
+                case
+            );
+            assert_eq!(
+                format!("{:?}", parsed),
+                format!("{:?}", events),
+                "case {}: events",
+                case
+            );
+            let _ = std::fs::remove_dir_all(dir);
+        }
+    }
+
+    /// Malformed history lines fail the parse with their line number and what is
+    /// wrong with them.
+    #[test]
+    fn malformed_histories_fail_to_parse() {
+        let first = "{:index 0, :time 10, :type :invoke, :process 0, :f :write, :value [0 1]}";
+        let invalid = |field: &str, value: &str| ParseErrorKind::InvalidField {
+            field: field.to_string(),
+            value: value.to_string(),
+        };
+        let cases = [
+            (
+                "{:index 1, :time 20, :type :ok, :process 0, :f :frob, :value [0 1]}",
+                ParseErrorKind::UnknownOpType(":frob".to_string()),
+            ),
+            (
+                "{:index 1, :time 20, :type :maybe, :process 0, :f :write, :value [0 1]}",
+                ParseErrorKind::UnknownEventType(":maybe".to_string()),
+            ),
+            (
+                "{:index 1, :time 20, :ty",
+                ParseErrorKind::InvalidSegment(":ty".to_string()),
+            ),
+            (
+                "{:index 0, :time 20, :type :ok, :process 0, :f :write, :value [0 1]}",
+                ParseErrorKind::IndexNotMonotonic { index: 0, last: 0 },
+            ),
+            (
+                "{:index 1, :time 5, :type :ok, :process 0, :f :write, :value [0 1]}",
+                ParseErrorKind::TimeNotMonotonic { time: 5, last: 10 },
+            ),
+            (
+                "{:index 1, :time x, :type :ok, :process 0, :f :write, :value [0 1]}",
+                invalid(":time", "x"),
+            ),
+            (
+                "{:index 1, :time 20, :type :ok, :process 0, :f :write, :value [0 \"1]}",
+                invalid(":value", "[0 \"1]"),
+            ),
+            (
+                "{:index 1, :time 20, :type :ok, :process 0, :value [0 1]}",
+                ParseErrorKind::MissingOpType {
+                    field: ":value".to_string(),
+                },
+            ),
+            (
+                "{:index 1, :time 20, :process 0, :f :write, :value [0 1]}",
+                ParseErrorKind::MissingFields,
+            ),
+        ];
+        for (nth, (line, kind)) in cases.into_iter().enumerate() {
+            let dir = fixture_dir(
+                &format!("malformed-{}", nth),
+                &[("history.edn", &format!("{}\n{}\n", first, line))],
+            );
+            match parse_history(&dir) {
+                Err(Error::Parse {
+                    line: 2,
+                    kind: found,
+                }) => assert_eq!(found, kind, "{}", line),
+                other => panic!("{}: parsed as {:?}", line, other),
+            }
+            let _ = std::fs::remove_dir_all(dir);
+        }
+    }
+}
@@ -0,0 +1,257 @@
+//! Fast path of register checking by version tags (`:tstag`).
+//!
+//! When every write carries a unique tag and every read reports the tag of
+//! the write it observed, the reads-from relation is known exactly, and the
+//! following are found in polynomial time:
+//!
+//! - a phantom read, observing a tag never written;
+//! - two RMWs following the same version, which only one of them can;
+//! - a read preceding the write it observes in real time;
+//! - a stale read, whose write was overwritten before the read began.
+//!
+//! Linearizability is even decided exactly by the zones of Gibbons and
+//! Korach, "Testing Shared Memories" (SIAM J. Comput. 1997). Gathering each
+//! write with the reads of it into a cluster, a cluster's zone spans from its
+//! least finish to its greatest invoke; it is forward if the least finish
+//! comes first, backward otherwise. The history is linearizable iff no read
+//! precedes its write, no two forward zones overlap, and no backward zone
+//! lies within a forward one. An RMW reads one version and writes the next
+//! at once, so a chain of them is contiguous in the version order: its
+//! clusters form a block, zoned the same way, whose operations must each
+//! finish after the ones ordered before it started.
+//!
+//! Otherwise (tags absent or ambiguous, an RMW whose read is unknown, or a
+//! level other than linearizability passing the checks above), the search
+//! decides.
+
+use std::collections::HashMap;
+
+use crate::check::CkSpan;
+use crate::rmw::RmwFunc;
+use crate::types::{Consistency, OpData, Timestamp, UniqueTag, ValType, Value};
+
+/// Versions observed and created by an operation, with its interval.
+#[derive(Debug, Clone, Copy)]
+struct TagOp {
+    invoke: Timestamp,
+    finish: Timestamp,
+    /// Finish as recorded, before the tolerance.
+    seen: Timestamp,
+    /// Tag of the version read, `Some(None)` for the initial one.
+    read: Option<Option<UniqueTag>>,
+    /// Tag of the version written.
+    write: Option<UniqueTag>,
+}
+
+/// Tags and values of a register operation, `None` if they are absent or the
+/// operation is not a tag-carrying one. An indeterminate RMW never reports
+/// the version it read, so its read is left unknown.
+fn tag_op(span: &CkSpan) -> Option<(TagOp, Option<&ValType>, Option<&ValType>)> {
+    let op = |read, write| TagOp {
+        invoke: span.invoke,
+        finish: span.finish,
+        seen: span.finish,
+        read,
+        write,
+    };
+    match &span.data {
+        // a read of a value that is not nil must tell where it came from
+        OpData::Read { val, tag, .. } if tag.is_some() || *val == Value::Nil => {
+            Some((op(Some(*tag), None), Some(val), None))
+        }
+        OpData::Write { val, tag, .. } => Some((op(None, Some(*tag)), None, Some(val))),
+        OpData::Rmw {
+            func: RmwFunc::Cas | RmwFunc::Swap | RmwFunc::IncrIf,
+            wval,
+            wtag: Some(wtag),
+            ..
+        } if span.optional => Some((op(None, Some(*wtag)), None, Some(wval))),
+        OpData::Rmw {
+            func,
+            rval,
+            rtag,
+            wval,
+            wtag: Some(wtag),
+            ..
+        } if func.reads_value(rval) && (rtag.is_some() || *rval == Value::Nil) => {
+            Some((op(Some(*rtag), Some(*wtag)), Some(rval), Some(wval)))
+        }
+        _ => None,
+    }
+}
+
+/// Decide the level on a single key's operations by their tags, `None` to
+/// leave it to the search, or else `Err` with the time by which a violation
+/// is witnessed. Real time is relaxed by `epsilon`, by extending every
+/// operation's finish.
+pub(crate) fn check_tags(
+    client_queues: &[Vec<CkSpan>],
+    level: Consistency,
+    epsilon: Timestamp,
+) -> Option<Result<(), Timestamp>> {
+    // operations that never completed have no say
+    let spans = client_queues
+        .iter()
+        .flatten()
+        .filter(|span| span.finish != 0);
+
+    let mut ops = vec![];
+    let mut unknown = vec![];
+    let mut reads = vec![];
+    let mut writes: HashMap<UniqueTag, (usize, &ValType)> = HashMap::new();
+    for span in spans {
+        let (mut op, rval, wval) = tag_op(span)?;
+        op.finish = op.finish.saturating_add(epsilon);
+        if let (Some(tag), Some(wval)) = (op.write, wval)
+            && writes.insert(tag, (ops.len(), wval)).is_some()
+        {
+            return None; // tags not unique, e.g., absent
+        }
+        if let (Some(tag), Some(rval)) = (op.read, rval) {
+            reads.push((ops.len(), tag, rval));
+        }
+        ops.push((op, span.optional));
+        unknown.push(span.optional && matches!(span.data, OpData::Rmw { .. }));
+    }
+
+    // exact reads-from, initial version as none
+    let mut observed = vec![false; ops.len()];
+    let mut followed = HashMap::new();
+    for &(idx, tag, rval) in &reads {
+        match tag.map(|tag| writes.get(&tag)) {
+            None => {}
+            Some(None) => return Some(Err(ops[idx].0.seen)), // phantom read
+            Some(Some(&(writer, wval))) => {
+                if wval != rval {
+                    return None; // tag and value disagree
+                }
+                observed[writer] = true;
+            }
+        }
+        if ops[idx].0.write.is_some()
+            && let Some(other) = followed.insert(tag, idx)
+        {
+            // two RMWs following the same version
+            return Some(Err(ops[idx].0.seen.max(ops[other].0.seen)));
+        }
+    }
+    if (0..ops.len()).any(|idx| unknown[idx] && observed[idx]) {
+        return None; // RMW taking effect with its read unknown
+    }
+    if level != Consistency::Linearizable {
+        return None;
+    }
+
+    // indeterminate writes are left out unless observed
+    let writer_of = |tag: Option<UniqueTag>| tag.map(|tag| ops[writes[&tag].0].0);
+    let mut live: Vec<TagOp> = vec![];
+    for (idx, &(op, optional)) in ops.iter().enumerate() {
+        if op.write.is_some() && (!optional || observed[idx]) {
+            live.push(op);
+        }
+    }
+    live.sort_by_key(|op| op.invoke);
+    // least finish among the writes invoked from each position on
+    let mut min_finish = vec![Timestamp::MAX; live.len() + 1];
+    for (i, op) in live.iter().enumerate().rev() {
+        min_finish[i] = min_finish[i + 1].min(op.finish);
+    }
+
+    for &(idx, tag, _) in &reads {
+        let read = ops[idx].0;
+        let writer = writer_of(tag);
+        if let Some(writer) = writer
+            && read.finish < writer.invoke
+        {
+            return Some(Err(writer.invoke)); // read preceding its write
+        }
+        // some other write invoked after the observed one finished, and
+        // finished before the read began
+        let after = writer.map_or(0, |writer| {
+            live.partition_point(|op| op.invoke <= writer.finish)
+        });
+        if min_finish[after] < read.invoke {
+            return Some(Err(read.seen)); // stale read
+        }
+    }
+
+    // blocks of clusters chained by RMWs, from a plain write or the initial
+    // version (written at time 0), each with its zone
+    let mut reads_of: HashMap<Option<UniqueTag>, Vec<TagOp>> = HashMap::new();
+    for &(idx, tag, _) in &reads {
+        if ops[idx].0.write.is_none() {
+            reads_of.entry(tag).or_default().push(ops[idx].0);
+        }
+    }
+    let mut roots: Vec<TagOp> = live
+        .iter()
+        .filter(|op| op.read.is_none())
+        .copied()
+        .collect();
+    if reads_of.contains_key(&None) || followed.contains_key(&None) {
+        roots.push(TagOp {
+            invoke: 0,
+            finish: 0,
+            seen: 0,
+            read: None,
+            write: None,
+        });
+    }
+    let mut zones = vec![];
+    let mut chained = 0;
+    for root in roots {
+        let mut writer = root;
+        let (mut f_min, mut s_max) = (root.finish, root.invoke);
+        loop {
+            // each operation goes at the earliest once the ones ordered
+            // before it were invoked
+            s_max = s_max.max(writer.invoke);
+            let read_of = reads_of.get(&writer.write).map_or(&[][..], Vec::as_slice);
+            for op in std::iter::once(&writer).chain(read_of) {
+                if op.finish < s_max {
+                    return Some(Err(s_max)); // finished before its turn
+                }
+                f_min = f_min.min(op.finish);
+            }
+            s_max = read_of
+                .iter()
+                .map(|read| read.invoke)
+                .fold(s_max, Timestamp::max);
+            let Some(&next) = followed.get(&writer.write) else {
+                break;
+            };
+            writer = ops[next].0;
+            chained += 1;
+        }
+        zones.push((f_min, s_max));
+    }
+    if chained < followed.len() {
+        return None; // RMWs following each other in a cycle
+    }
+
+    let (mut forward, backward): (Vec<_>, Vec<_>) =
+        zones.into_iter().partition(|(f_min, s_max)| f_min < s_max);
+    forward.sort_unstable();
+    if let Some(pair) = forward.windows(2).find(|pair| pair[1].0 < pair[0].1) {
+        return Some(Err(pair[0].1.max(pair[1].1))); // overlapping forward zones
+    }
+    for (f_min, s_max) in backward {
+        // the only forward zone that may contain it starts right before it
+        let next = forward.partition_point(|&(lo, _)| lo < s_max);
+        if next > 0 && f_min < forward[next - 1].1 {
+            // backward zone within a forward one
+            return Some(Err(forward[next - 1].1.max(f_min)));
+        }
+    }
+    // within a tolerance, or with reads started early, real time may no longer
+    // imply program order, which the zones then miss
+    let in_order = client_queues.iter().all(|queue| {
+        let mut spans = queue.iter().filter(|span| span.finish != 0);
+        let mut last = spans.next();
+        spans.all(|span| {
+            let before = last.replace(span).unwrap();
+            before.finish.saturating_add(epsilon) < span.invoke
+        })
+    });
+    in_order.then_some(Ok(()))
+}
@@ -0,0 +1,330 @@
+//! Checker of multi-key transactional histories.
+//!
+//! Transactions cannot be decomposed per key, so the search runs over the
+//! state of all keys at once, placing one transaction at a time. Serializability
+//! asks for some serial order of the transactions, and strict serializability
+//! for one that also respects real time. Snapshot isolation places the start
+//! and the commit of each transaction separately: its reads observe the state
+//! at its start (plus its own writes), and no other transaction writing any of
+//! the same keys may commit in between (first committer wins). None of them
+//! involve program order. A serial order is also a valid placement for
+//! snapshot isolation, so the three form a chain.
+//!
+//! The cost grows exponentially with the number of concurrent transactions
+//! (and with all of them for the levels without real time), so large
+//! histories call for the time and possibilities limits (`--time-limit` and
+//! `--possib-limit` on the command line).
+
+use std::cmp;
+use std::collections::{BTreeMap, BTreeSet, HashSet, VecDeque};
+use std::time::{Duration, Instant};
+
+use crate::error::{Error, Result};
+use crate::types::{
+    Consistency, KeyType, Mop, OpData, OpSpan, Timeline, Timestamp, ValType, Value,
+};
+
+/// Committed state of all keys; keys holding nil are left out.
+type TxnState = BTreeMap<KeyType, ValType>;
+
+/// Set of transactions, as a bitmap over their indices.
+type TxnSet = Vec<u64>;
+
+fn set_has(set: &TxnSet, idx: usize) -> bool {
+    set[idx / 64] & (1 << (idx % 64)) != 0
+}
+
+fn set_add(set: &mut TxnSet, idx: usize) {
+    set[idx / 64] |= 1 << (idx % 64);
+}
+
+/// A single possibility to be explored.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct TxnPossib {
+    /// The resulting state after the committed transactions.
+    state: TxnState,
+    /// Transactions committed so far.
+    done: TxnSet,
+    /// Transactions started but not committed yet (snapshot isolation only).
+    active: BTreeSet<usize>,
+    /// Maximum invoke timestamp of committed transactions.
+    max_invoke: Timestamp,
+}
+
+/// Refined type of a transaction span with only relevant info for checking.
+#[derive(Debug, Clone)]
+struct TxSpan {
+    invoke: Timestamp,
+    finish: Timestamp,
+    mops: Vec<Mop>,
+    /// Final value written to each key.
+    writes: BTreeMap<KeyType, ValType>,
+    /// May or may not have taken effect.
+    optional: bool,
+}
+
+impl TxSpan {
+    fn from_raw(raw: &OpSpan) -> Result<Self> {
+        let OpData::Txn { mops } = &raw.data else {
+            return Err(Error::Config(format!("not a transaction: {}", raw.data)));
+        };
+        // operations that never completed may or may not have taken effect
+        let finish = if raw.terminated() {
+            raw.finish
+        } else {
+            Timestamp::MAX
+        };
+        let writes = mops
+            .iter()
+            .filter_map(|mop| match mop {
+                Mop::Write { key, val } => Some((key.clone(), val.clone())),
+                Mop::Read { .. } => None,
+            })
+            .collect();
+        Ok(TxSpan {
+            invoke: raw.invoke,
+            finish,
+            mops: mops.clone(),
+            writes,
+            optional: raw.indeterminate || !raw.terminated(),
+        })
+    }
+
+    /// Do the reads observe `snapshot`, along with the transaction's own
+    /// earlier writes?
+    fn reads_from(&self, snapshot: &TxnState) -> bool {
+        let mut own: BTreeMap<&KeyType, &ValType> = BTreeMap::new();
+        self.mops.iter().all(|mop| match mop {
+            Mop::Read { key, val } => {
+                let seen = own.get(key).copied().or_else(|| snapshot.get(key));
+                seen.unwrap_or(&Value::Nil) == val
+            }
+            Mop::Write { key, val } => {
+                own.insert(key, val);
+                true
+            }
+        })
+    }
+
+    /// State after installing my writes.
+    fn apply(&self, state: &TxnState) -> TxnState {
+        let mut new_state = state.clone();
+        for (key, val) in &self.writes {
+            if *val == Value::Nil {
+                new_state.remove(key);
+            } else {
+                new_state.insert(key.clone(), val.clone());
+            }
+        }
+        new_state
+    }
+
+    /// Do I write any key the other one writes?
+    fn conflicts_with(&self, other: &TxSpan) -> bool {
+        self.writes.keys().any(|key| other.writes.contains_key(key))
+    }
+}
+
+/// Checker of a transactional history, exploring all keys together.
+#[derive(Debug)]
+pub struct TxnChecker {
+    txns: Vec<TxSpan>,
+
+    /// Levels to check, strongest first.
+    levels: Vec<Consistency>,
+
+    /// Print progress and results?
+    verbose: bool,
+    /// Give up checking after this long.
+    time_limit: Option<Duration>,
+    /// Give up checking once this many possibilities are pending.
+    possib_limit: Option<usize>,
+}
+
+impl TxnChecker {
+    /// Create a new checker of a transactional history. Operations other than
+    /// transactions are rejected.
+    pub fn new(timeline: Timeline) -> Result<Self> {
+        let txns = timeline
+            .queues
+            .iter()
+            .flatten()
+            .map(TxSpan::from_raw)
+            .collect::<Result<_>>()?;
+
+        Ok(TxnChecker {
+            txns,
+            levels: vec![Consistency::StrictSerializable],
+            verbose: true,
+            time_limit: None,
+            possib_limit: None,
+        })
+    }
+
+    /// Set the levels to check (strict serializability only by default). The
+    /// result of a check is the strongest of them that holds, or `Weak` if
+    /// none.
+    pub fn set_levels(&mut self, levels: &[Consistency]) {
+        self.levels = levels
+            .iter()
+            .copied()
+            .filter(|&level| level != Consistency::Weak)
+            .collect();
+        self.levels.sort_unstable_by(|a, b| b.cmp(a));
+        self.levels.dedup();
+    }
+
+    /// Suppress progress and result printing.
+    pub fn set_quiet(&mut self) {
+        self.verbose = false;
+    }
+
+    /// Make the check return an error once it has run longer than the limit.
+    pub fn set_time_limit(&mut self, limit: Duration) {
+        self.time_limit = Some(limit);
+    }
+
+    /// Make the check return an error once too many possibilities are pending,
+    /// which bounds its memory usage.
+    pub fn set_possib_limit(&mut self, limit: usize) {
+        self.possib_limit = Some(limit);
+    }
+
+    /// Run the check, returning the strongest level that holds. Levels other
+    /// than the transactional ones are rejected.
+    pub fn check(&self) -> Result<Consistency> {
+        if let Some(level) = self.levels.iter().find(|level| !level.is_transactional()) {
+            return Err(Error::Config(format!(
+                "not a transactional level: {}",
+                level
+            )));
+        }
+        let deadline = self.time_limit.map(|limit| (Instant::now() + limit, limit));
+
+        if self.verbose {
+            println!(" checking {} transactions ...", self.txns.len());
+        }
+        for &level in &self.levels {
+            if self.search(level, deadline)? {
+                if self.verbose {
+                    println!("  ✅ {} ", level);
+                }
+                return Ok(level);
+            }
+            if self.verbose {
+                println!("  ❌ non-{}", level);
+            }
+        }
+        Ok(Consistency::Weak)
+    }
+
+    /// Search for a placement of the transactions valid under the level.
+    fn search(&self, level: Consistency, deadline: Option<(Instant, Duration)>) -> Result<bool> {
+        let mut last_print = Instant::now();
+        let initial = TxnPossib {
+            state: TxnState::new(),
+            done: vec![0; self.txns.len().div_ceil(64)],
+            active: BTreeSet::new(),
+            max_invoke: 0,
+        };
+        let mut possibilities = VecDeque::from([initial.clone()]);
+        let mut possibilities_set = HashSet::from([initial]);
+
+        while let Some(possib) = possibilities.pop_front() {
+            let now = Instant::now();
+            if let Some((deadline, limit)) = deadline
+                && now > deadline
+            {
+                return Err(Error::TimeLimitExceeded { limit });
+            }
+            if let Some(limit) = self.possib_limit
+                && possibilities.len() > limit
+            {
+                return Err(Error::PossibLimitExceeded { limit });
+            }
+            if self.verbose && now.duration_since(last_print).as_millis() > 500 {
+                last_print = now;
+                println!(
+                    "  ...  |done|: {:5}  |possib|: {:8}",
+                    possib.done.iter().map(|w| w.count_ones()).sum::<u32>(),
+                    possibilities.len(),
+                );
+            }
+
+            // indeterminate transactions may have never taken effect
+            if self
+                .txns
+                .iter()
+                .enumerate()
+                .all(|(idx, txn)| txn.optional || set_has(&possib.done, idx))
+            {
+                return Ok(true);
+            }
+
+            for (idx, txn) in self.txns.iter().enumerate() {
+                if set_has(&possib.done, idx) {
+                    continue;
+                }
+                let new_possib = if possib.active.contains(&idx) {
+                    self.commit(&possib, idx)
+                } else if level == Consistency::SnapshotIsolation && !txn.writes.is_empty() {
+                    Self::start(&possib, txn, idx)
+                } else {
+                    Self::place(&possib, txn, idx, level == Consistency::StrictSerializable)
+                };
+                if let Some(new_possib) = new_possib
+                    && !possibilities_set.contains(&new_possib)
+                {
+                    possibilities.push_back(new_possib.clone());
+                    possibilities_set.insert(new_possib);
+                }
+            }
+        }
+        Ok(false)
+    }
+
+    /// Start and commit the transaction at once, if its reads match the state
+    /// (and it does not precede a committed transaction in real time).
+    fn place(possib: &TxnPossib, txn: &TxSpan, idx: usize, real_time: bool) -> Option<TxnPossib> {
+        if real_time && txn.finish < possib.max_invoke {
+            return None;
+        }
+        if !txn.reads_from(&possib.state) {
+            return None;
+        }
+        let mut new_possib = possib.clone();
+        new_possib.state = txn.apply(&possib.state);
+        set_add(&mut new_possib.done, idx);
+        new_possib.max_invoke = cmp::max(txn.invoke, possib.max_invoke);
+        Some(new_possib)
+    }
+
+    /// Start the transaction on the current state as its snapshot, which
+    /// fixes what its reads observe.
+    fn start(possib: &TxnPossib, txn: &TxSpan, idx: usize) -> Option<TxnPossib> {
+        if !txn.reads_from(&possib.state) {
+            return None;
+        }
+        let mut new_possib = possib.clone();
+        new_possib.active.insert(idx);
+        Some(new_possib)
+    }
+
+    /// Commit a started transaction, unless another started one writes the
+    /// same keys: that one could then never commit.
+    fn commit(&self, possib: &TxnPossib, idx: usize) -> Option<TxnPossib> {
+        let txn = &self.txns[idx];
+        if possib
+            .active
+            .iter()
+            .any(|&other| other != idx && txn.conflicts_with(&self.txns[other]))
+        {
+            return None;
+        }
+        let mut new_possib = possib.clone();
+        new_possib.state = txn.apply(&possib.state);
+        new_possib.active.remove(&idx);
+        set_add(&mut new_possib.done, idx);
+        Some(new_possib)
+    }
+}
@@ -1,41 +1,141 @@
 //! Definition of fundamental types for consistency checking.
 
-use std::collections::HashMap;
-use std::error::Error;
+use std::collections::{BTreeSet, HashMap};
 use std::fmt;
+use std::str::FromStr;
+use std::sync::Arc;
+
+use crate::edn::Edn;
+use crate::error::{Error, Result};
+use crate::repair::{Repair, RepairAction, RepairPolicy};
+use crate::rmw::RmwFunc;
 
 /// Client ID type.
-pub(crate) type ClientId = usize;
+pub type ClientId = usize;
 
 /// Key type.
-pub(crate) type KeyType = u64;
+pub type KeyType = Value;
 
 /// Value type.
-pub(crate) type ValType = u64;
+pub type ValType = Value;
 
 /// Timestamp type.
-pub(crate) type Timestamp = u64;
+pub type Timestamp = u64;
 
 // Operation-unique tag type.
-pub(crate) type UniqueTag = u64;
+pub type UniqueTag = u64;
+
+/// EDN value, as found for keys and values in histories. Values compare
+/// structurally, so that any of them may be written and read back, and order
+/// by variant first to keep sets and maps of them deterministic.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Value {
+    #[default]
+    Nil,
+    Bool(bool),
+    Int(i64),
+    Str(Arc<str>),
+    Keyword(Arc<str>),  // without the leading ':'
+    List(Arc<[Value]>), // from both vectors and lists
+    Set(Arc<BTreeSet<Value>>),
+}
+
+impl Value {
+    /// Convert a parsed EDN form, if it is made of supported values only
+    /// (e.g., not maps).
+    pub(crate) fn from_edn(edn: &Edn) -> Option<Self> {
+        match edn {
+            Edn::Nil => Some(Value::Nil),
+            Edn::Bool(b) => Some(Value::Bool(*b)),
+            Edn::Int(i) => Some(Value::Int(*i)),
+            Edn::Str(s) => Some(Value::Str(s.as_str().into())),
+            Edn::Keyword(k) => Some(Value::Keyword(k.as_str().into())),
+            Edn::Vector(items) | Edn::List(items) => {
+                let items: Option<Vec<Value>> = items.iter().map(Value::from_edn).collect();
+                Some(Value::List(items?.into()))
+            }
+            Edn::Set(items) => {
+                let items: Option<BTreeSet<Value>> = items.iter().map(Value::from_edn).collect();
+                Some(Value::Set(Arc::new(items?)))
+            }
+            _ => None,
+        }
+    }
+}
+
+impl From<i64> for Value {
+    fn from(i: i64) -> Self {
+        Value::Int(i)
+    }
+}
+
+/// Formats in EDN syntax.
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Nil => write!(f, "nil"),
+            Value::Bool(b) => write!(f, "{}", b),
+            Value::Int(i) => write!(f, "{}", i),
+            Value::Str(s) => {
+                write!(f, "\"")?;
+                for c in s.chars() {
+                    match c {
+                        '"' => write!(f, "\\\"")?,
+                        '\\' => write!(f, "\\\\")?,
+                        '\n' => write!(f, "\\n")?,
+                        '\t' => write!(f, "\\t")?,
+                        '\r' => write!(f, "\\r")?,
+                        _ => write!(f, "{}", c)?,
+                    }
+                }
+                write!(f, "\"")
+            }
+            Value::Keyword(k) => write!(f, ":{}", k),
+            Value::List(items) => {
+                write!(f, "[")?;
+                for (i, item) in items.iter().enumerate() {
+                    write!(f, "{}{}", if i == 0 { "" } else { " " }, item)?;
+                }
+                write!(f, "]")
+            }
+            Value::Set(items) => {
+                write!(f, "#{{")?;
+                for (i, item) in items.iter().enumerate() {
+                    write!(f, "{}{}", if i == 0 { "" } else { " " }, item)?;
+                }
+                write!(f, "}}")
+            }
+        }
+    }
+}
+
+/// Parses from EDN syntax.
+impl FromStr for Value {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Value::from_edn(&Edn::parse(s)?)
+            .ok_or_else(|| Error::Config(format!("unsupported value: {}", s)))
+    }
+}
 
 /// Event type enum.
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
-pub(crate) enum EventType {
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum EventType {
     Invoke, // :invoke
     Okay,   // :ok
     Fail,   // :fail
     Error,  // :info (indicating error)
 }
 
-/// Operation type and data. Option fields are `None` when the operation is
-/// on the fly and result values are not known yet. A value could also remain
-/// `None` after the timeline has parsed due to failed read.
+/// Operation type and data. Result values are `Nil` when the operation is on
+/// the fly and they are not known yet. A value could also remain `Nil` after
+/// the timeline has parsed due to failed read.
 #[derive(Debug, Clone)]
-pub(crate) enum OpData {
+pub enum OpData {
     Read {
         key: KeyType,
-        val: Option<ValType>,
+        val: ValType,
         tag: Option<UniqueTag>,
     },
     Write {
@@ -44,46 +144,116 @@ pub(crate) enum OpData {
         tag: UniqueTag,
     },
     /// Any general operation fits RMW (read-modify-write). A common example
-    /// beyond regular reads/writes is CAS (compare-and-swap).
-    /// In our current implementation, only :cas is supported for this type.
-    /// The function from `rval` to `wval` is assumed correct.
+    /// beyond regular reads/writes is CAS (compare-and-swap); see `RmwFunc`
+    /// for the supported functions. Whether `wval` follows from `rval` (and
+    /// `arg`) is validated when the operation completes.
     Rmw {
         key: KeyType,
-        rval: Option<ValType>,
+        func: RmwFunc,
+        arg: ValType,
+        rval: ValType,
         rtag: Option<UniqueTag>,
-        wval: Option<ValType>,
+        wval: ValType,
         wtag: Option<UniqueTag>,
     },
+    /// Adding an element to a collection (or an amount to a counter), as the
+    /// model of the data type decides.
+    Add { key: KeyType, val: ValType },
+    /// Enqueuing to the tail of a queue.
+    Enqueue { key: KeyType, val: ValType },
+    /// Dequeuing from the head of a queue, `Nil` if found empty.
+    Dequeue { key: KeyType, val: ValType },
+    /// Appending an element to a list, read back in full by `Read`.
+    Append { key: KeyType, val: ValType },
+    /// Deleting a register, leaving a tombstone that reads as `Nil`.
+    Delete { key: KeyType, tag: UniqueTag },
+    /// Multi-key transaction of reads and writes, taking effect atomically.
+    Txn { mops: Vec<Mop> },
+}
+
+/// Micro-operation of a transaction, as `[:r k v]` or `[:w k v]`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Mop {
+    Read { key: KeyType, val: ValType },
+    Write { key: KeyType, val: ValType },
+}
+
+impl fmt::Display for Mop {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Mop::Read { key, val } => write!(f, "R_{}:{}", key, val),
+            Mop::Write { key, val } => write!(f, "W_{}<{}", key, val),
+        }
+    }
 }
 
 impl fmt::Display for OpData {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
-            OpData::Read { key, val, .. } => {
-                write!(
-                    f,
-                    "R_{}:{}",
-                    key,
-                    val.as_ref().map(|v| v.to_string()).unwrap_or("-".into())
-                )
-            }
+            OpData::Read { key, val, .. } => write!(f, "R_{}:{}", key, val),
             OpData::Write { key, val, .. } => write!(f, "W_{}<{}", key, val),
             OpData::Rmw {
-                key, rval, wval, ..
-            } => {
-                write!(
-                    f,
-                    "RMW_{}:{}<{}",
-                    key,
-                    rval.as_ref().map(|v| v.to_string()).unwrap_or("-".into()),
-                    wval.as_ref().map(|v| v.to_string()).unwrap_or("-".into())
-                )
+                key,
+                func: RmwFunc::Cas,
+                rval,
+                wval,
+                ..
+            } => write!(f, "RMW_{}:{}<{}", key, rval, wval),
+            OpData::Rmw {
+                key,
+                func,
+                rval,
+                wval,
+                ..
+            } => write!(f, "RMW[{}]_{}:{}<{}", func, key, rval, wval),
+            OpData::Add { key, val } => write!(f, "A_{}+{}", key, val),
+            OpData::Enqueue { key, val } => write!(f, "E_{}<{}", key, val),
+            OpData::Dequeue { key, val } => write!(f, "D_{}>{}", key, val),
+            OpData::Append { key, val } => write!(f, "L_{}+{}", key, val),
+            OpData::Delete { key, .. } => write!(f, "DEL_{}", key),
+            OpData::Txn { mops } => {
+                write!(f, "T[")?;
+                for (i, mop) in mops.iter().enumerate() {
+                    write!(f, "{}{}", if i == 0 { "" } else { " " }, mop)?;
+                }
+                write!(f, "]")
             }
         }
     }
 }
 
 impl OpData {
+    /// Key of the operation. Transactions span multiple keys, and are all
+    /// grouped under the `Nil` key.
+    pub fn key(&self) -> KeyType {
+        match self {
+            OpData::Read { key, .. } => key.clone(),
+            OpData::Write { key, .. } => key.clone(),
+            OpData::Rmw { key, .. } => key.clone(),
+            OpData::Add { key, .. } => key.clone(),
+            OpData::Enqueue { key, .. } => key.clone(),
+            OpData::Dequeue { key, .. } => key.clone(),
+            OpData::Append { key, .. } => key.clone(),
+            OpData::Delete { key, .. } => key.clone(),
+            OpData::Txn { .. } => Value::Nil,
+        }
+    }
+
+    /// Does the operation leave the state as is?
+    pub fn read_only(&self) -> bool {
+        match self {
+            OpData::Read { .. } => true,
+            OpData::Write { .. } => false,
+            OpData::Rmw { .. } => false,
+            OpData::Add { .. } => false,
+            OpData::Enqueue { .. } => false,
+            OpData::Dequeue { .. } => false,
+            OpData::Append { .. } => false,
+            OpData::Delete { .. } => false,
+            OpData::Txn { mops } => mops.iter().all(|mop| matches!(mop, Mop::Read { .. })),
+        }
+    }
+
     /// Check if I form a matching pair with a previous `OpData`.
     fn match_previous(&self, prev: &OpData) -> bool {
         match (self, prev) {
@@ -91,7 +261,34 @@ impl OpData {
             (OpData::Write { key, val, .. }, OpData::Write { key: k, val: v, .. }) => {
                 key == k && val == v
             }
-            (OpData::Rmw { key, .. }, OpData::Rmw { key: k, .. }) => key == k,
+            (
+                OpData::Rmw { key, func, arg, .. },
+                OpData::Rmw {
+                    key: k,
+                    func: f,
+                    arg: a,
+                    ..
+                },
+            ) => key == k && func == f && arg == a,
+            (OpData::Add { key, val }, OpData::Add { key: k, val: v }) => key == k && val == v,
+            (OpData::Enqueue { key, val }, OpData::Enqueue { key: k, val: v }) => {
+                key == k && val == v
+            }
+            (OpData::Dequeue { key, .. }, OpData::Dequeue { key: k, .. }) => key == k,
+            (OpData::Append { key, val }, OpData::Append { key: k, val: v }) => {
+                key == k && val == v
+            }
+            (OpData::Delete { key, .. }, OpData::Delete { key: k, .. }) => key == k,
+            (OpData::Txn { mops }, OpData::Txn { mops: ms }) => {
+                mops.len() == ms.len()
+                    && mops.iter().zip(ms).all(|pair| match pair {
+                        (Mop::Read { key, .. }, Mop::Read { key: k, .. }) => key == k,
+                        (Mop::Write { key, val }, Mop::Write { key: k, val: v }) => {
+                            key == k && val == v
+                        }
+                        _ => false,
+                    })
+            }
             _ => false,
         }
     }
@@ -99,15 +296,38 @@ impl OpData {
     /// Overwrite my value fields with the other `OpData` values.
     fn overwrite_by(&mut self, other: OpData) {
         match (self, other) {
-            (OpData::Read { val, .. }, OpData::Read { val: v, .. }) => *val = v,
+            (OpData::Read { val, tag, .. }, OpData::Read { val: v, tag: t, .. }) => {
+                *val = v;
+                *tag = t;
+            }
+            (OpData::Dequeue { val, .. }, OpData::Dequeue { val: v, .. }) => *val = v,
             (
-                OpData::Rmw { rval, wval, .. },
                 OpData::Rmw {
-                    rval: rv, wval: wv, ..
+                    rval,
+                    rtag,
+                    wval,
+                    wtag,
+                    ..
+                },
+                OpData::Rmw {
+                    rval: rv,
+                    rtag: rt,
+                    wval: wv,
+                    wtag: wt,
+                    ..
                 },
             ) => {
                 *rval = rv;
+                *rtag = rt;
                 *wval = wv;
+                *wtag = wt;
+            }
+            (OpData::Txn { mops }, OpData::Txn { mops: ms }) => {
+                for (mop, m) in mops.iter_mut().zip(ms) {
+                    if let (Mop::Read { val, .. }, Mop::Read { val: v, .. }) = (mop, m) {
+                        *val = v;
+                    }
+                }
             }
             _ => {}
         }
@@ -117,62 +337,70 @@ impl OpData {
 /// Operation span type, i.e., operation data with invocation and completion
 /// timestamps.
 #[derive(Debug, Clone)]
-pub(crate) struct OpSpan {
-    pub(crate) invoke: Timestamp,
-    pub(crate) finish: Timestamp,
-    pub(crate) data: OpData,
-    pub(crate) _client: ClientId,
+pub struct OpSpan {
+    pub index: u64,                // :index of the invocation
+    pub finish_index: Option<u64>, // :index of the :ok completion, if any
+    pub invoke: Timestamp,
+    pub finish: Timestamp,
+    pub data: OpData,
+    pub client: ClientId,
+
+    /// May or may not have taken effect (finish is then `Timestamp::MAX`).
+    pub indeterminate: bool,
 }
 
 impl OpSpan {
-    pub(crate) fn new(
+    pub fn new(
+        index: u64,
         invoke: Timestamp,
         finish: Timestamp,
         data: OpData,
         client: ClientId,
     ) -> Self {
         OpSpan {
+            index,
+            finish_index: None,
             invoke,
             finish,
             data,
-            _client: client,
+            client,
+            indeterminate: false,
         }
     }
 
-    pub(crate) fn key(&self) -> KeyType {
-        match self.data {
-            OpData::Read { key, .. } => key,
-            OpData::Write { key, .. } => key,
-            OpData::Rmw { key, .. } => key,
-        }
+    pub fn key(&self) -> KeyType {
+        self.data.key()
     }
 
-    #[allow(dead_code)]
-    pub(crate) fn read_only(&self) -> bool {
-        match self.data {
-            OpData::Read { .. } => true,
-            OpData::Write { .. } => false,
-            OpData::Rmw { .. } => false,
-        }
+    pub fn read_only(&self) -> bool {
+        self.data.read_only()
     }
 
-    pub(crate) fn terminated(&self) -> bool {
+    pub fn terminated(&self) -> bool {
         self.finish != 0
     }
 }
 
-/// Event type, used only during the parsing of history.
+/// Event type, used during the parsing (or generating) of history.
 #[derive(Debug, Clone)]
-pub(crate) struct Event {
-    time: Timestamp,
-    etype: EventType,
-    client: ClientId,
-    opdata: OpData,
+pub struct Event {
+    pub index: u64, // :index in the history file
+    pub time: Timestamp,
+    pub etype: EventType,
+    pub client: ClientId,
+    pub opdata: OpData,
 }
 
 impl Event {
-    pub(crate) fn new(time: Timestamp, etype: EventType, client: ClientId, opdata: OpData) -> Self {
+    pub fn new(
+        index: u64,
+        time: Timestamp,
+        etype: EventType,
+        client: ClientId,
+        opdata: OpData,
+    ) -> Self {
         Event {
+            index,
             time,
             etype,
             client,
@@ -184,37 +412,86 @@ impl Event {
 /// The collection of per-client queues of operation spans.
 /// This is the complete input to feed to the checker algorithm.
 #[derive(Debug, Clone)]
-pub(crate) struct Timeline {
-    pub(crate) queues: Vec<Vec<OpSpan>>,
+pub struct Timeline {
+    pub queues: Vec<Vec<OpSpan>>,
+    /// Operations completed with `:fail` or `:info`, left out of the queues
+    /// (but for `:info` ones kept as indeterminate), with their completion
+    /// type.
+    pub failed: Vec<(OpSpan, EventType)>,
 
     // Operation per-type statistics: for each, [invokes, okays, fails]
-    pub(crate) stats_ops_sum: usize,
-    pub(crate) stats_ops_r: [usize; 3],
-    pub(crate) stats_ops_w: [usize; 3],
-    pub(crate) stats_ops_cas: [usize; 3],
+    pub stats_ops_sum: usize,
+    pub stats_ops_r: [usize; 3],
+    pub stats_ops_w: [usize; 3],
+    pub stats_ops_cas: [usize; 3],
+    pub stats_ops_add: [usize; 3],
+    pub stats_ops_enq: [usize; 3],
+    pub stats_ops_deq: [usize; 3],
+    pub stats_ops_app: [usize; 3],
+    pub stats_ops_del: [usize; 3],
+    pub stats_ops_txn: [usize; 3],
 
     // Operation per-key count statistics
-    pub(crate) stats_key_ops: HashMap<KeyType, usize>,
-    pub(crate) stats_key_min: usize,
-    pub(crate) stats_key_med: usize,
-    pub(crate) stats_key_avg: usize,
-    pub(crate) stats_key_max: usize,
+    pub stats_key_ops: HashMap<KeyType, usize>,
+    pub stats_key_min: usize,
+    pub stats_key_med: usize,
+    pub stats_key_avg: usize,
+    pub stats_key_max: usize,
 
     // Operation per-client count statistics
-    pub(crate) stats_cli_min: usize,
-    pub(crate) stats_cli_med: usize,
-    pub(crate) stats_cli_avg: usize,
-    pub(crate) stats_cli_max: usize,
+    pub stats_cli_min: usize,
+    pub stats_cli_med: usize,
+    pub stats_cli_avg: usize,
+    pub stats_cli_max: usize,
+}
+
+/// Can the operation as invoked be kept as indeterminate, its effect known
+/// without its completion? Reads cannot, nor can RMWs whose result their
+/// inputs do not tell.
+fn closable(invoked: &OpData) -> bool {
+    match invoked {
+        OpData::Write { .. }
+        | OpData::Add { .. }
+        | OpData::Enqueue { .. }
+        | OpData::Append { .. }
+        | OpData::Delete { .. } => true,
+        OpData::Rmw { func, .. } => func.inputs_known(),
+        OpData::Txn { mops } => mops.iter().all(|mop| matches!(mop, Mop::Write { .. })),
+        OpData::Read { .. } | OpData::Dequeue { .. } => false,
+    }
 }
 
 impl Timeline {
-    pub(crate) fn new(events: Vec<Event>, max_client: ClientId) -> Result<Self, Box<dyn Error>> {
+    /// Build the timeline, failing on the first violation of the
+    /// invoke/complete protocol.
+    pub fn new(events: Vec<Event>, max_client: ClientId) -> Result<Self> {
+        Self::with_repair(events, max_client, &mut Repair::new(RepairPolicy::Abort))
+    }
+
+    /// Build the timeline, reporting violations of the invoke/complete
+    /// protocol to `repair` and repairing them according to its policy.
+    ///
+    /// Operations closed as indeterminate are put each on a queue of its own
+    /// after the clients' queues, as they are not ordered before the later
+    /// operations of their client.
+    pub fn with_repair(
+        events: Vec<Event>,
+        max_client: ClientId,
+        repair: &mut Repair,
+    ) -> Result<Self> {
         let mut tl = Timeline {
             queues: vec![vec![]; max_client + 1],
+            failed: vec![],
             stats_ops_sum: 0,
             stats_ops_r: [0; 3],
             stats_ops_w: [0; 3],
             stats_ops_cas: [0; 3],
+            stats_ops_add: [0; 3],
+            stats_ops_enq: [0; 3],
+            stats_ops_deq: [0; 3],
+            stats_ops_app: [0; 3],
+            stats_ops_del: [0; 3],
+            stats_ops_txn: [0; 3],
             stats_key_ops: HashMap::new(),
             stats_key_min: usize::MAX,
             stats_key_med: 0,
@@ -226,60 +503,119 @@ impl Timeline {
             stats_cli_max: 0,
         };
 
+        // data of each client's flying operation as invoked, kept in case it
+        // gets closed as indeterminate
+        let mut flying: Vec<Option<OpData>> = vec![None; max_client + 1];
+
         for e in events {
             match e.etype {
                 EventType::Invoke => {
-                    if (!tl.queues[e.client].is_empty())
-                        && tl.queues[e.client].last().unwrap().finish == 0
-                    {
-                        return Err(format!(
-                            "client {} :invoke @ {} when previous op flying",
-                            e.client, e.time
-                        )
-                        .into());
+                    if flying[e.client].is_some() {
+                        tl.repair_flying(
+                            e.client,
+                            &mut flying,
+                            Error::InvokeWhileFlying {
+                                client: e.client,
+                                time: e.time,
+                                index: e.index,
+                            },
+                            repair,
+                        )?;
                     }
+                    flying[e.client] = Some(e.opdata.clone());
 
                     // erase any read results at the time of :invoke
                     let mut opdata = e.opdata;
                     match &mut opdata {
                         OpData::Read { val, .. } => {
-                            *val = None;
+                            *val = Value::Nil;
                             tl.stats_ops_r[0] += 1;
                         }
                         OpData::Write { .. } => {
                             tl.stats_ops_w[0] += 1;
                         }
                         OpData::Rmw { rval, wval, .. } => {
-                            *rval = None;
-                            *wval = None;
+                            *rval = Value::Nil;
+                            *wval = Value::Nil;
                             tl.stats_ops_cas[0] += 1;
                         }
+                        OpData::Add { .. } => {
+                            tl.stats_ops_add[0] += 1;
+                        }
+                        OpData::Enqueue { .. } => {
+                            tl.stats_ops_enq[0] += 1;
+                        }
+                        OpData::Dequeue { val, .. } => {
+                            *val = Value::Nil;
+                            tl.stats_ops_deq[0] += 1;
+                        }
+                        OpData::Append { .. } => {
+                            tl.stats_ops_app[0] += 1;
+                        }
+                        OpData::Delete { .. } => {
+                            tl.stats_ops_del[0] += 1;
+                        }
+                        OpData::Txn { mops } => {
+                            for mop in mops {
+                                if let Mop::Read { val, .. } = mop {
+                                    *val = Value::Nil;
+                                }
+                            }
+                            tl.stats_ops_txn[0] += 1;
+                        }
                     }
 
-                    tl.queues[e.client].push(OpSpan::new(e.time, 0, opdata, e.client));
+                    tl.queues[e.client].push(OpSpan::new(e.index, e.time, 0, opdata, e.client));
                 }
 
                 EventType::Okay => {
-                    if tl.queues[e.client].is_empty()
-                        || tl.queues[e.client].last().unwrap().terminated()
-                    {
-                        return Err(format!(
-                            "client {} :ok @ {} when no op is flying",
-                            e.client, e.time
-                        )
-                        .into());
+                    if flying[e.client].is_none() {
+                        repair.report(
+                            Error::CompleteWhenIdle {
+                                client: e.client,
+                                time: e.time,
+                                index: e.index,
+                                etype: e.etype,
+                            },
+                            RepairAction::DroppedEvent,
+                        )?;
+                        continue;
                     }
 
                     // check data validity
-                    let op = tl.queues[e.client].last_mut().unwrap();
-                    if !e.opdata.match_previous(&op.data) {
-                        return Err(format!(
-                            "client {} :ok @ {} op {} mismatching previous {}",
-                            e.client, e.time, e.opdata, op.data
-                        )
-                        .into());
+                    let invoked = &tl.queues[e.client].last().unwrap().data;
+                    if !e.opdata.match_previous(invoked) {
+                        let err = Error::MismatchedCompletion {
+                            client: e.client,
+                            time: e.time,
+                            index: e.index,
+                            invoked: Box::new(invoked.clone()),
+                            completed: Box::new(e.opdata),
+                        };
+                        tl.repair_flying(e.client, &mut flying, err, repair)?;
+                        continue;
+                    }
+                    if let OpData::Rmw {
+                        func,
+                        arg,
+                        rval,
+                        wval,
+                        ..
+                    } = &e.opdata
+                        && !func.validate(arg, rval, wval)
+                    {
+                        let err = Error::InvalidRmwResult {
+                            client: e.client,
+                            time: e.time,
+                            index: e.index,
+                            completed: Box::new(e.opdata),
+                        };
+                        tl.repair_flying(e.client, &mut flying, err, repair)?;
+                        continue;
                     }
+                    flying[e.client] = None;
 
+                    let op = tl.queues[e.client].last_mut().unwrap();
                     match op.data {
                         OpData::Read { .. } => {
                             tl.stats_ops_r[1] += 1;
@@ -290,9 +626,28 @@ impl Timeline {
                         OpData::Rmw { .. } => {
                             tl.stats_ops_cas[1] += 1;
                         }
+                        OpData::Add { .. } => {
+                            tl.stats_ops_add[1] += 1;
+                        }
+                        OpData::Enqueue { .. } => {
+                            tl.stats_ops_enq[1] += 1;
+                        }
+                        OpData::Dequeue { .. } => {
+                            tl.stats_ops_deq[1] += 1;
+                        }
+                        OpData::Append { .. } => {
+                            tl.stats_ops_app[1] += 1;
+                        }
+                        OpData::Delete { .. } => {
+                            tl.stats_ops_del[1] += 1;
+                        }
+                        OpData::Txn { .. } => {
+                            tl.stats_ops_txn[1] += 1;
+                        }
                     }
 
                     op.finish = e.time;
+                    op.finish_index = Some(e.index);
                     op.data.overwrite_by(e.opdata);
 
                     tl.stats_key_ops
@@ -302,15 +657,18 @@ impl Timeline {
                 }
 
                 EventType::Fail | EventType::Error => {
-                    if tl.queues[e.client].is_empty()
-                        || tl.queues[e.client].last().unwrap().terminated()
-                    {
-                        return Err(format!(
-                            "client {} :fail/:info @ {} when no op is flying",
-                            e.client, e.time
-                        )
-                        .into());
-                    }
+                    let Some(invoked) = flying[e.client].take() else {
+                        repair.report(
+                            Error::CompleteWhenIdle {
+                                client: e.client,
+                                time: e.time,
+                                index: e.index,
+                                etype: e.etype,
+                            },
+                            RepairAction::DroppedEvent,
+                        )?;
+                        continue;
+                    };
 
                     match tl.queues[e.client].last().unwrap().data {
                         OpData::Read { .. } => {
@@ -322,14 +680,63 @@ impl Timeline {
                         OpData::Rmw { .. } => {
                             tl.stats_ops_cas[2] += 1;
                         }
+                        OpData::Add { .. } => {
+                            tl.stats_ops_add[2] += 1;
+                        }
+                        OpData::Enqueue { .. } => {
+                            tl.stats_ops_enq[2] += 1;
+                        }
+                        OpData::Dequeue { .. } => {
+                            tl.stats_ops_deq[2] += 1;
+                        }
+                        OpData::Append { .. } => {
+                            tl.stats_ops_app[2] += 1;
+                        }
+                        OpData::Delete { .. } => {
+                            tl.stats_ops_del[2] += 1;
+                        }
+                        OpData::Txn { .. } => {
+                            tl.stats_ops_txn[2] += 1;
+                        }
                     }
 
-                    // remove failed operation
-                    tl.queues[e.client].pop();
+                    // remove failed operation, though an :info one may have
+                    // taken effect, and is kept as indeterminate if it can be
+                    let mut op = tl.queues[e.client].pop().unwrap();
+                    if e.etype == EventType::Error && closable(&invoked) {
+                        tl.close(&op, invoked);
+                    }
+                    op.finish = e.time;
+                    op.finish_index = Some(e.index);
+                    tl.failed.push((op, e.etype));
                 }
             }
         }
 
+        // operations still flying at the end never completed, and are kept
+        // unterminated unless repairing protocol violations
+        for client in 0..=max_client {
+            let Some(invoked) = &flying[client] else {
+                continue;
+            };
+            let op = tl.queues[client].last_mut().unwrap();
+            if repair.policy() == RepairPolicy::Skip {
+                op.indeterminate = closable(invoked);
+                flying[client] = None;
+                tl.stats_key_ops
+                    .entry(op.key())
+                    .and_modify(|c| *c += 1)
+                    .or_insert(1);
+                continue;
+            }
+            let err = Error::DanglingInvoke {
+                client,
+                time: op.invoke,
+                index: op.index,
+            };
+            tl.repair_flying(client, &mut flying, err, repair)?;
+        }
+
         // calculate per-key count statistics
         let key_cnts: Vec<usize> = tl.stats_key_ops.values().copied().collect();
         tl.stats_ops_sum = key_cnts.iter().sum();
@@ -342,7 +749,7 @@ impl Timeline {
             let mut sorted = key_cnts.clone();
             sorted.sort_unstable();
             let mid = sorted.len() / 2;
-            if sorted.len() % 2 == 0 {
+            if sorted.len().is_multiple_of(2) {
                 tl.stats_key_med = (sorted[mid - 1] + sorted[mid]) / 2;
             } else {
                 tl.stats_key_med = sorted[mid];
@@ -350,7 +757,7 @@ impl Timeline {
         }
 
         // calculate per-client count statistics
-        let cli_cnts: Vec<usize> = tl.queues.iter().map(|q| q.len()).collect();
+        let cli_cnts: Vec<usize> = tl.queues[..=max_client].iter().map(|q| q.len()).collect();
         if !cli_cnts.is_empty() {
             tl.stats_cli_min = *cli_cnts.iter().min().unwrap();
             tl.stats_cli_max = *cli_cnts.iter().max().unwrap();
@@ -360,7 +767,7 @@ impl Timeline {
             let mut sorted = cli_cnts.clone();
             sorted.sort_unstable();
             let mid = sorted.len() / 2;
-            if sorted.len() % 2 == 0 {
+            if sorted.len().is_multiple_of(2) {
                 tl.stats_cli_med = (sorted[mid - 1] + sorted[mid]) / 2;
             } else {
                 tl.stats_cli_med = sorted[mid];
@@ -370,25 +777,183 @@ impl Timeline {
         Ok(tl)
     }
 
+    /// Repair the flying operation of a client involved in a protocol
+    /// violation: close it as indeterminate if the policy says so, or drop it.
+    /// Reads, dequeues, RMWs whose read value is a result (e.g., swaps), and
+    /// transactions with reads are always dropped, as their effects depend on
+    /// (or their checking needs) the unknown results.
+    fn repair_flying(
+        &mut self,
+        client: ClientId,
+        flying: &mut [Option<OpData>],
+        error: Error,
+        repair: &mut Repair,
+    ) -> Result<()> {
+        let invoked = flying[client].take().unwrap();
+        let action = if repair.policy() == RepairPolicy::Close && closable(&invoked) {
+            RepairAction::ClosedOp
+        } else {
+            RepairAction::DroppedOp
+        };
+        repair.report(error, action)?;
+
+        let op = self.queues[client].pop().unwrap();
+        if action == RepairAction::ClosedOp {
+            self.close(&op, invoked);
+        }
+        Ok(())
+    }
+
+    /// Put the operation as invoked on a queue of its own, as indeterminate.
+    fn close(&mut self, op: &OpSpan, invoked: OpData) {
+        let mut span = OpSpan::new(op.index, op.invoke, Timestamp::MAX, invoked, op.client);
+        span.indeterminate = true;
+        self.stats_key_ops
+            .entry(span.key())
+            .and_modify(|c| *c += 1)
+            .or_insert(1);
+        self.queues.push(vec![span]);
+    }
+
     #[inline]
-    pub(crate) fn num_keys(&self) -> usize {
+    pub fn num_keys(&self) -> usize {
         self.stats_key_ops.len()
     }
 
     #[inline]
-    pub(crate) fn num_clients(&self) -> usize {
+    pub fn num_clients(&self) -> usize {
         self.queues.len()
     }
 }
 
 /// Ranks of supported consistency levels. Currently only a chain-hierarchy of
 /// levels supported, which conveniently covers the four most common levels.
+///
+/// The staleness levels relax linearizability by a bound (see
+/// `Checker::set_max_k()` and `Checker::set_max_delta()`): Δ-atomicity lets
+/// reads start up to Δ earlier, and k-atomicity lets them return any of the
+/// last k writes. They rank between sequential consistency and
+/// linearizability, though neither implies sequential consistency (nor the
+/// other).
+///
+/// The transactional levels form a chain of their own on top, checked only
+/// for transactional histories; they do not compare meaningfully with the
+/// single-object ones.
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
-pub(crate) enum Consistency {
+pub enum Consistency {
     Weak = 0,
-    // TODO: currently only linearizability exploration supported
+    // TODO: eventual consistency not supported yet
     // Eventual = 1,
-    // Causal = 2, // actually causal+
-    // Sequential = 3,
-    Linearizable = 4,
+    Causal = 2, // actually causal+
+    Sequential = 3,
+    DeltaAtomic = 4,
+    KAtomic = 5,
+    Linearizable = 6,
+    SnapshotIsolation = 7,
+    Serializable = 8,
+    StrictSerializable = 9,
+}
+
+impl Consistency {
+    /// Is this a level of transactional histories?
+    pub fn is_transactional(self) -> bool {
+        self >= Consistency::SnapshotIsolation
+    }
+
+    /// Is this a level of bounded staleness, measured by a least bound?
+    pub fn is_staleness(self) -> bool {
+        matches!(self, Consistency::DeltaAtomic | Consistency::KAtomic)
+    }
+}
+
+impl fmt::Display for Consistency {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                Consistency::Weak => "weak",
+                Consistency::Causal => "causal",
+                Consistency::Sequential => "sequential",
+                Consistency::DeltaAtomic => "delta-atomic",
+                Consistency::KAtomic => "k-atomic",
+                Consistency::Linearizable => "linearizable",
+                Consistency::SnapshotIsolation => "snapshot-isolation",
+                Consistency::Serializable => "serializable",
+                Consistency::StrictSerializable => "strict-serializable",
+            }
+        )
+    }
+}
+
+impl FromStr for Consistency {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "weak" => Ok(Consistency::Weak),
+            "causal" => Ok(Consistency::Causal),
+            "sequential" => Ok(Consistency::Sequential),
+            "delta-atomic" | "δ-atomic" => Ok(Consistency::DeltaAtomic),
+            "k-atomic" => Ok(Consistency::KAtomic),
+            "linearizable" => Ok(Consistency::Linearizable),
+            "snapshot-isolation" | "si" => Ok(Consistency::SnapshotIsolation),
+            "serializable" => Ok(Consistency::Serializable),
+            "strict-serializable" => Ok(Consistency::StrictSerializable),
+            _ => Err(Error::Config(format!("unknown consistency level: {}", s))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Violations of the invoke/complete protocol fail the timeline with the
+    /// client and index of the offending event.
+    #[test]
+    fn protocol_violations_fail_the_timeline() {
+        let write = |val| OpData::Write {
+            key: Value::Int(0),
+            val: Value::Int(val),
+            tag: 0,
+        };
+        let invoke = Event::new(0, 10, EventType::Invoke, 0, write(1));
+        let cases = [(EventType::Invoke, write(1)), (EventType::Okay, write(2))];
+        for (etype, opdata) in cases {
+            let events = vec![invoke.clone(), Event::new(1, 20, etype, 0, opdata)];
+            let err = Timeline::new(events, 0).expect_err("invalid history");
+            assert!(err.is_protocol_violation(), "{}", err);
+            match (etype, err) {
+                (
+                    EventType::Invoke,
+                    Error::InvokeWhileFlying {
+                        client: 0,
+                        index: 1,
+                        ..
+                    },
+                )
+                | (
+                    EventType::Okay,
+                    Error::MismatchedCompletion {
+                        client: 0,
+                        index: 1,
+                        ..
+                    },
+                ) => {}
+                (_, err) => panic!("{:?}: {}", etype, err),
+            }
+        }
+
+        let events = vec![Event::new(0, 10, EventType::Fail, 1, write(1))];
+        assert!(matches!(
+            Timeline::new(events, 1),
+            Err(Error::CompleteWhenIdle {
+                client: 1,
+                index: 0,
+                etype: EventType::Fail,
+                ..
+            })
+        ));
+    }
 }
@@ -0,0 +1,284 @@
+//! Validation of register histories before any search.
+//!
+//! Some anomalies are evident from the recorded values alone, without ordering
+//! any operations: a read observing a value (or tag) that nothing ever wrote
+//! to its key, two writes carrying the same tag, and an RMW whose read tag
+//! names a write of another value than its read value. All are found in
+//! linear time and reported with the indices of the events involved. Garbage
+//! reads and inconsistent RMWs are certain violations of every level but weak
+//! on their key, sparing a slow search to fail it there while the other keys
+//! are still searched; duplicate tags only leave the tags ambiguous, so that
+//! the search decides by values.
+
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+use crate::types::{Consistency, KeyType, OpData, OpSpan, Timeline, UniqueTag, ValType, Value};
+
+/// Anomaly in the values recorded by a register history.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DataAnomaly {
+    /// Read (or value-comparing RMW) of a value, or value and tag, never
+    /// written to the key, at the `:index` of its completion.
+    GarbageRead {
+        index: u64,
+        key: KeyType,
+        val: ValType,
+        tag: Option<UniqueTag>,
+    },
+    /// Two writes to the key with the same tag, at the `:index`es of their
+    /// invocations.
+    DuplicateTag {
+        indices: (u64, u64),
+        key: KeyType,
+        tag: UniqueTag,
+    },
+    /// RMW whose read tag names a write of another value than its read value,
+    /// at the `:index` of its completion.
+    InconsistentRmw {
+        index: u64,
+        key: KeyType,
+        rval: ValType,
+        rtag: UniqueTag,
+        written: ValType,
+    },
+}
+
+impl fmt::Display for DataAnomaly {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DataAnomaly::GarbageRead {
+                index,
+                key,
+                val,
+                tag,
+            } => {
+                write!(f, "garbage read of [{} {}]", key, val)?;
+                if let Some(tag) = tag {
+                    write!(f, " tagged {}", tag)?;
+                }
+                write!(f, " (index {})", index)
+            }
+            DataAnomaly::DuplicateTag { indices, key, tag } => write!(
+                f,
+                "duplicate tag {} on key {} (indices {} and {})",
+                tag, key, indices.0, indices.1
+            ),
+            DataAnomaly::InconsistentRmw {
+                index,
+                key,
+                rval,
+                rtag,
+                written,
+            } => write!(
+                f,
+                "RMW reading [{} {}] from tag {} that wrote {} (index {})",
+                key, rval, rtag, written, index
+            ),
+        }
+    }
+}
+
+impl DataAnomaly {
+    /// Does the anomaly violate every level but weak?
+    pub fn is_violation(&self) -> bool {
+        !matches!(self, DataAnomaly::DuplicateTag { .. })
+    }
+
+    /// Does the anomaly refute the level on its key?
+    pub fn refutes(&self, level: Consistency) -> bool {
+        match self {
+            // no order of the operations explains a value nothing wrote
+            DataAnomaly::GarbageRead { .. } => level != Consistency::Weak,
+            // nor a read of another value than that of the write it read
+            DataAnomaly::InconsistentRmw { .. } => level != Consistency::Weak,
+            // the search decides by values instead
+            DataAnomaly::DuplicateTag { .. } => false,
+        }
+    }
+
+    /// Key of the operations involved.
+    pub fn key(&self) -> &KeyType {
+        match self {
+            DataAnomaly::GarbageRead { key, .. }
+            | DataAnomaly::DuplicateTag { key, .. }
+            | DataAnomaly::InconsistentRmw { key, .. } => key,
+        }
+    }
+
+    /// Index of the latest event involved.
+    pub fn index(&self) -> u64 {
+        match self {
+            DataAnomaly::GarbageRead { index, .. } | DataAnomaly::InconsistentRmw { index, .. } => {
+                *index
+            }
+            DataAnomaly::DuplicateTag { indices, .. } => indices.1,
+        }
+    }
+}
+
+/// Writes to one key: their values, and their tags unless absent.
+#[derive(Default)]
+struct Written<'a> {
+    vals: HashSet<&'a ValType>,
+    tags: HashMap<UniqueTag, (&'a ValType, u64)>,
+    /// Tags of more than one write, which reads cannot be traced by.
+    ambiguous: HashSet<UniqueTag>,
+    /// Do any operations carry tags? Absent tags of writes parse as 0.
+    tagged: bool,
+}
+
+/// Value and tag an operation writes, if any.
+fn write_of(data: &OpData) -> Option<(&ValType, Option<UniqueTag>)> {
+    match data {
+        OpData::Write { val, tag, .. } => Some((val, Some(*tag))),
+        OpData::Rmw { wval, wtag, .. } => Some((wval, *wtag)),
+        // absent tags of deletes parse as 0 too, but take no part
+        OpData::Delete { tag, .. } => Some((&Value::Nil, (*tag != 0).then_some(*tag))),
+        _ => None,
+    }
+}
+
+/// Value and tag an operation observed when completed, if it reads by value.
+fn read_of(span: &OpSpan) -> Option<(&ValType, Option<UniqueTag>)> {
+    if span.indeterminate || !span.terminated() {
+        return None;
+    }
+    match &span.data {
+        OpData::Read { val, tag, .. } => Some((val, *tag)),
+        OpData::Rmw {
+            func, rval, rtag, ..
+        } if func.reads_value(rval) => Some((rval, *rtag)),
+        _ => None,
+    }
+}
+
+/// Validate the register operations of a timeline, returning the anomalies
+/// found ordered by (the latest) event index. Operations of other types are
+/// ignored.
+pub fn validate_registers(timeline: &Timeline) -> Vec<DataAnomaly> {
+    let mut spans: Vec<&OpSpan> = timeline.queues.iter().flatten().collect();
+    spans.sort_by_key(|span| span.index);
+
+    let mut anomalies = vec![];
+    let mut written: HashMap<KeyType, Written> = HashMap::new();
+    for span in &spans {
+        let entry = written.entry(span.key()).or_default();
+        if let Some((_, Some(_))) = read_of(span) {
+            entry.tagged = true;
+        }
+        if let Some((val, tag)) = write_of(&span.data) {
+            entry.vals.insert(val);
+            entry.tagged |= tag.is_some_and(|tag| tag != 0);
+        }
+    }
+    for span in &spans {
+        let Some((val, Some(tag))) = write_of(&span.data) else {
+            continue;
+        };
+        let key = span.key();
+        let entry = written.get_mut(&key).unwrap();
+        if !entry.tagged {
+            continue;
+        }
+        match entry.tags.get(&tag) {
+            Some(&(_, first)) => {
+                entry.ambiguous.insert(tag);
+                anomalies.push(DataAnomaly::DuplicateTag {
+                    indices: (first, span.index),
+                    key,
+                    tag,
+                });
+            }
+            None => {
+                entry.tags.insert(tag, (val, span.index));
+            }
+        }
+    }
+
+    for span in &spans {
+        let Some((val, tag)) = read_of(span) else {
+            continue;
+        };
+        let key = span.key();
+        let entry = &written[&key];
+        let index = span.finish_index.unwrap_or(span.index);
+        let garbage = || DataAnomaly::GarbageRead {
+            index,
+            key: key.clone(),
+            val: val.clone(),
+            tag,
+        };
+        if *val != Value::Nil && !entry.vals.contains(val) {
+            anomalies.push(garbage());
+            continue;
+        }
+        let Some(tag) = tag.filter(|tag| entry.tagged && !entry.ambiguous.contains(tag)) else {
+            continue;
+        };
+        match (&span.data, entry.tags.get(&tag)) {
+            (_, None) => anomalies.push(garbage()),
+            (_, Some(&(written, _))) if written == val => {}
+            (OpData::Rmw { .. }, Some(&(written, _))) => {
+                anomalies.push(DataAnomaly::InconsistentRmw {
+                    index,
+                    key: key.clone(),
+                    rval: val.clone(),
+                    rtag: tag,
+                    written: written.clone(),
+                })
+            }
+            _ => anomalies.push(garbage()),
+        }
+    }
+
+    anomalies.sort_by_key(DataAnomaly::index);
+    anomalies
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::check::Checker;
+    use crate::store::{fixture_dir, parse_history};
+    use crate::types::Consistency;
+
+    /// Two writes with the same tag leave the tags ambiguous without violating
+    /// any level, so that the history is still checked by values.
+    #[test]
+    fn duplicate_tags_are_not_violations() {
+        let history = "\
+    {:index 0, :time 10, :type :invoke, :process 0, :f :write, :value [0 1], :tstag 7}
+    {:index 1, :time 20, :type :ok, :process 0, :f :write, :value [0 1], :tstag 7}
+    {:index 2, :time 30, :type :invoke, :process 0, :f :write, :value [0 2], :tstag 7}
+    {:index 3, :time 40, :type :ok, :process 0, :f :write, :value [0 2], :tstag 7}
+    {:index 4, :time 50, :type :invoke, :process 1, :f :read, :value [0 nil]}
+    {:index 5, :time 60, :type :ok, :process 1, :f :read, :value [0 2], :tstag 7}
+    ";
+        let dir = fixture_dir("duplicate-tags", &[("history.edn", history)]);
+        let (events, max_client) = parse_history(&dir).expect("cannot parse");
+        let timeline = Timeline::new(events, max_client).expect("invalid history");
+        let anomalies = validate_registers(&timeline);
+        assert!(
+            matches!(
+                anomalies.as_slice(),
+                [DataAnomaly::DuplicateTag {
+                    indices: (0, 2),
+                    tag: 7,
+                    ..
+                }]
+            ),
+            "{:?}",
+            anomalies
+        );
+        assert!(!anomalies[0].is_violation());
+
+        let mut checker = Checker::new(timeline);
+        checker.set_quiet();
+        assert_eq!(
+            checker.check().expect("checker failed"),
+            Consistency::Linearizable
+        );
+        let _ = std::fs::remove_dir_all(dir);
+    }
+}